@@ -1,9 +1,20 @@
-use crate::game::ecs::{Attributes, Liquid, Position};
+use crate::game::ecs::{AiFlags, Attributes, Liquid, Position};
 use crate::data::Data;
+use super::alignment;
+use super::pack::FOLLOW_DISTANCE;
+use super::state::{AiState, Awareness, Senses};
+use super::utility::{TargetInfo, UtilityConfig};
 use rgoap::{self, Action, State};
 use specs::{Entities, Entity, ReadStorage, WriteStorage};
+use std::collections::{BTreeSet, VecDeque};
 use std::f32;
 
+/// How far away an agent notices potions lying on the floor
+pub const ITEM_SEARCH_RADIUS: u32 = 10;
+
+/// Corners of the square walked by patrolling agents, relative to home
+pub const PATROL_ROUTE: &[(i32, i32)] = &[(-3, -3), (3, -3), (3, 3), (-3, 3)];
+
 pub trait StateBuilder<P> {
     fn with<S>(self, name: S, value: bool) -> Self
     where
@@ -68,16 +79,47 @@ pub struct Agent {
     pub turn_ended: bool,
     pub position: Position,
     pub attributes: Attributes,
+    pub flags: AiFlags,
+    pub home: Position,
+    pub inventory: Vec<Entity>,
+    /// Copied from the agent's memory once it has taken in its surroundings
+    pub morale: f32,
+    pub broken: bool,
 }
 
 impl Agent {
-    pub fn new(position: Position, attributes: Attributes) -> Self {
+    pub fn new(position: Position, attributes: Attributes, flags: AiFlags, home: Position, inventory: Vec<Entity>) -> Self {
         Agent {
             turn_ended: false,
             position: position,
             attributes: attributes,
+            flags: flags,
+            home: home,
+            inventory: inventory,
+            morale: 1.0,
+            broken: false,
         }
     }
+
+    pub fn hp_ratio(&self) -> f32 {
+        if self.attributes.max_hp > 0 {
+            self.attributes.hp as f32 / self.attributes.max_hp as f32
+        } else {
+            1.0
+        }
+    }
+
+    /// The next corner of the patrol route after the one we're closest to
+    pub fn next_waypoint(&self) -> Position {
+        let closest = PATROL_ROUTE
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (dx, dy))| self.home.delta(*dx, *dy).distance(&self.position))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        let (dx, dy) = PATROL_ROUTE[(closest + 1) % PATROL_ROUTE.len()];
+        self.home.delta(dx, dy)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -88,20 +130,85 @@ pub struct AiAction {
     pub name: String,
     pub pre_conditions: State<AiPredicate>,
     pub post_conditions: State<AiPredicate>,
+    /// What the action is aimed at, for scoring
+    pub target: Option<TargetInfo>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AiActionType {
     Meditate,
+    /// Walk to where an item lies
+    GoTo(Entity),
     DrinkPotable(Entity),
     Get(Entity),
     Attack(Entity),
+    /// Run away from a threat
+    Flee(Entity),
+    /// Head for where an enemy was last seen
+    Chase(Entity, Position),
+    Wander,
+    /// Walk to the next waypoint around home
+    Patrol(Position),
+    ReturnHome,
+    /// Keep up with a pack leader, last seen at a position
+    Follow(Entity, Position),
+    /// Tell a guard, last seen at a position, about the crimes we've seen
+    Report(Entity, Position),
+    /// Bring a potion to a hurt ally, last seen at a position
+    Help(Entity, Position),
+}
+
+impl AiActionType {
+    /// Name used to look up the action's scorer
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AiActionType::Meditate => "meditate",
+            AiActionType::GoTo(_) => "go_to",
+            AiActionType::DrinkPotable(_) => "drink",
+            AiActionType::Get(_) => "get",
+            AiActionType::Attack(_) => "attack",
+            AiActionType::Flee(_) => "flee",
+            AiActionType::Chase(_, _) => "chase",
+            AiActionType::Wander => "wander",
+            AiActionType::Patrol(_) => "patrol",
+            AiActionType::ReturnHome => "return_home",
+            AiActionType::Follow(_, _) => "follow",
+            AiActionType::Report(_, _) => "report",
+            AiActionType::Help(_, _) => "help",
+        }
+    }
+
+    /// True if both actions work towards the same goal, so a plan for one can carry on
+    /// when the other comes out on top. Waypoints change as a patrol goes on, and leaders move.
+    pub fn same_goal(&self, other: &AiActionType) -> bool {
+        match (self, other) {
+            (AiActionType::Patrol(_), AiActionType::Patrol(_)) => true,
+            (AiActionType::Follow(a, _), AiActionType::Follow(b, _))
+            | (AiActionType::Report(a, _), AiActionType::Report(b, _))
+            | (AiActionType::Help(a, _), AiActionType::Help(b, _)) => a == b,
+            (a, b) => a == b,
+        }
+    }
+
+    /// Actions that are finished as soon as they have been carried out once.
+    /// The rest take as many turns as they need to reach their goal.
+    pub fn is_one_shot(&self) -> bool {
+        match self {
+            AiActionType::Meditate | AiActionType::Wander | AiActionType::Flee(_) | AiActionType::Help(_, _) => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum AiPredicate {
     Have(Entity),
+    /// Standing on the same tile as an item
+    At(Entity),
+    AtHome,
     UnderThreat,
+    Thirsty,
+    Hurt,
 }
 
 impl AiAction {
@@ -112,6 +219,11 @@ impl AiAction {
         action
     }
 
+    pub fn about(mut self, target: TargetInfo) -> Self {
+        self.target = Some(target);
+        self
+    }
+
     pub fn meditate(agent: &Agent) -> AiAction {
         AiAction {
             name: format!("meditate"),
@@ -122,6 +234,23 @@ impl AiAction {
                 .with(AiPredicate::UnderThreat, false),
             post_conditions: State::new()
                 .with(AiPredicate::UnderThreat, false),
+            target: None,
+        }
+    }
+
+    pub fn go_to(agent: &Agent, e: Entity, item_position: &Position) -> AiAction {
+        AiAction {
+            name: format!("go_to({:?})", e),
+            cost: agent.position.distance(item_position),
+            utility: 0.0,
+            action_type: AiActionType::GoTo(e),
+            pre_conditions: State::new()
+                .with(AiPredicate::At(e), false)
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::At(e), true)
+                .with(AiPredicate::AtHome, false),
+            target: None,
         }
     }
 
@@ -131,8 +260,13 @@ impl AiAction {
             cost: 1,
             utility: 0.0,
             action_type: AiActionType::Get(e),
-            pre_conditions: State::new().with(AiPredicate::Have(e), false),
-            post_conditions: State::new().with(AiPredicate::Have(e), true),
+            pre_conditions: State::new()
+                .with(AiPredicate::At(e), true)
+                .with(AiPredicate::Have(e), false),
+            post_conditions: State::new()
+                .with(AiPredicate::At(e), false)
+                .with(AiPredicate::Have(e), true),
+            target: None,
         }
     }
 
@@ -140,10 +274,14 @@ impl AiAction {
         AiAction {
             name: format!("drink({:?})", e),
             cost: 1,
-            utility: 1.0 - agent.attributes.thirst,
+            utility: agent.attributes.thirst.max(1.0 - agent.hp_ratio()),
             action_type: AiActionType::DrinkPotable(e),
             pre_conditions: State::new().with(AiPredicate::Have(e), true),
-            post_conditions: State::new().with(AiPredicate::Have(e), false),
+            post_conditions: State::new()
+                .with(AiPredicate::Have(e), false)
+                .with(AiPredicate::Thirsty, false)
+                .with(AiPredicate::Hurt, false),
+            target: None,
         }
     }
 
@@ -157,10 +295,135 @@ impl AiAction {
                 .with(AiPredicate::UnderThreat, true),
             post_conditions: State::new()
                 .with(AiPredicate::UnderThreat, false),
+            target: None,
+        }
+    }
+
+    pub fn flee(agent: &Agent, e: Entity, threat: f32) -> AiAction {
+        AiAction {
+            name: format!("flee({:?})", e),
+            cost: 1,
+            utility: threat * (1.0 - agent.morale),
+            action_type: AiActionType::Flee(e),
+            pre_conditions: State::new()
+                .with(AiPredicate::UnderThreat, true),
+            post_conditions: State::new()
+                .with(AiPredicate::UnderThreat, false)
+                .with(AiPredicate::AtHome, false),
+            target: None,
+        }
+    }
+
+    pub fn chase(agent: &Agent, e: Entity, last_seen: &Position) -> AiAction {
+        AiAction {
+            name: format!("chase({:?})", e),
+            cost: agent.position.distance(last_seen),
+            utility: 0.9,
+            action_type: AiActionType::Chase(e, last_seen.clone()),
+            pre_conditions: State::new()
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::UnderThreat, true)
+                .with(AiPredicate::AtHome, false),
+            target: None,
+        }
+    }
+
+    pub fn wander(_agent: &Agent) -> AiAction {
+        AiAction {
+            name: format!("wander"),
+            cost: 1,
+            utility: 0.5,
+            action_type: AiActionType::Wander,
+            pre_conditions: State::new()
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::AtHome, false),
+            target: None,
+        }
+    }
+
+    pub fn patrol(agent: &Agent) -> AiAction {
+        let waypoint = agent.next_waypoint();
+        AiAction {
+            name: format!("patrol({:?})", waypoint),
+            cost: agent.position.distance(&waypoint),
+            utility: 0.6,
+            action_type: AiActionType::Patrol(waypoint),
+            pre_conditions: State::new()
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::AtHome, false),
+            target: None,
+        }
+    }
+
+    pub fn return_home(agent: &Agent) -> AiAction {
+        let distance = agent.position.distance(&agent.home) as f32;
+        AiAction {
+            name: format!("return_home"),
+            cost: distance as u32,
+            utility: (distance / 20.0).min(0.8),
+            action_type: AiActionType::ReturnHome,
+            pre_conditions: State::new()
+                .with(AiPredicate::AtHome, false)
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::AtHome, true),
+            target: None,
+        }
+    }
+
+    pub fn follow(agent: &Agent, leader: Entity, leader_position: &Position) -> AiAction {
+        AiAction {
+            name: format!("follow({:?})", leader),
+            cost: agent.position.distance(leader_position),
+            utility: 0.7,
+            action_type: AiActionType::Follow(leader, leader_position.clone()),
+            pre_conditions: State::new()
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::AtHome, false),
+            target: None,
+        }
+    }
+
+    pub fn report(agent: &Agent, guard: Entity, guard_position: &Position) -> AiAction {
+        AiAction {
+            name: format!("report({:?})", guard),
+            cost: agent.position.distance(guard_position),
+            utility: 0.7,
+            action_type: AiActionType::Report(guard, guard_position.clone()),
+            pre_conditions: State::new()
+                .with(AiPredicate::UnderThreat, false),
+            post_conditions: State::new()
+                .with(AiPredicate::AtHome, false),
+            target: None,
+        }
+    }
+
+    pub fn help(agent: &Agent, ally: Entity, ally_position: &Position) -> AiAction {
+        AiAction {
+            name: format!("help({:?})", ally),
+            cost: agent.position.distance(ally_position),
+            utility: 0.7,
+            action_type: AiActionType::Help(ally, ally_position.clone()),
+            pre_conditions: State::new(),
+            post_conditions: State::new()
+                .with(AiPredicate::AtHome, false),
+            target: None,
         }
     }
 }
 
+/// A sequence of steps found by the planner, carried out one turn at a time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    /// The action the plan was made for, last in `steps`
+    pub goal: AiActionType,
+    pub steps: VecDeque<AiActionType>,
+}
+
 pub struct AiActions {
     pub agent: Agent,
     pub actions: Vec<AiAction>,
@@ -184,44 +447,150 @@ impl AiActions {
         self.actions.push(action);
     }
 
+    /// Build the planner's view of the world from the components around the agent
+    /// and collect every action it could take. Returns what the agent noticed.
+    /// Creatures the agent holds a grudge against are enemies whatever their faction.
     pub fn setup_actions<'a>(
         &mut self,
         data: &mut Data,
         entities: &Entities<'a>,
         positions: &mut WriteStorage<'a, Position>,
-        attributes: &ReadStorage<'a, Attributes>,
+        attributes: &WriteStorage<'a, Attributes>,
         liquids: &ReadStorage<'a, Liquid>,
-    ) {
+        grudges: &BTreeSet<Entity>,
+    ) -> Senses {
         use specs::Join;
 
+        let mut senses = Senses::default();
+
+        let agent_attrs = &self.agent.attributes;
+        self.state.insert(AiPredicate::UnderThreat, false);
+        self.state.insert(AiPredicate::AtHome, self.agent.position == self.agent.home);
+        self.state.insert(AiPredicate::Thirsty, agent_attrs.thirst > 0.5);
+        self.state.insert(AiPredicate::Hurt, self.agent.hp_ratio() < 0.5);
+
         self.add_action(AiAction::meditate(&self.agent));
+        if self.agent.flags.wander {
+            self.add_action(AiAction::wander(&self.agent));
+        }
+        if self.agent.flags.patrol {
+            self.add_action(AiAction::patrol(&self.agent));
+        }
+        if self.agent.position != self.agent.home {
+            self.add_action(AiAction::return_home(&self.agent));
+        }
 
-        for (e, _pos, attr) in (entities, positions, attributes).join() {
-            if let Some(liquid) = liquids.get(e) {
-                if liquid.potable {
-                    self.add_action(AiAction::drink(&self.agent, e));
-                    self.add_action(AiAction::get(&self.agent, e));
-                }
+        let mut carrying_potion = false;
+        for e in self.agent.inventory.clone() {
+            self.state.insert(AiPredicate::Have(e), true);
+            self.state.insert(AiPredicate::At(e), false);
+            if liquids.get(e).map(|l| l.potable).unwrap_or(false) {
+                carrying_potion = true;
+                self.add_action(AiAction::drink(&self.agent, e));
+            }
+        }
+
+        for (e, pos, liquid) in (&**entities, &*positions, liquids).join() {
+            if !liquid.potable || pos.distance(&self.agent.position) > ITEM_SEARCH_RADIUS {
+                continue;
+            }
+
+            senses.items.push((e, pos.clone()));
+            self.add_item_actions(e, pos);
+        }
+
+        for (e, pos, attr) in (&**entities, &*positions, attributes).join() {
+            if !attr.alive {
+                continue;
             }
 
             let opinion = data.factions.get(&self.agent.attributes.faction, &attr.faction);
-            if opinion.is_hostile() {
-                // todo something more useful here
-                let threat = if data.fov.is_in_fov(self.agent.position.x, self.agent.position.y) {
-                    1.0
-                } else {
-                    0.0
+            let ally = attr.faction == self.agent.attributes.faction || data.factions.is_friendly(&self.agent.attributes.faction, &attr.faction);
+            let visible = if e == data.player {
+                data.fov.is_in_fov(self.agent.position.x, self.agent.position.y)
+            } else {
+                pos.distance(&self.agent.position) <= self.agent.attributes.vision_radius as u32
+            };
+            // the evil pick on anyone weak enough that isn't a friend
+            let prey = !ally && visible && alignment::is_evil(&self.agent.attributes) && alignment::is_weakened(attr);
+            if prey && !grudges.contains(&e) {
+                senses.prey.push(e);
+            }
+
+            if ally && !grudges.contains(&e) && *pos != self.agent.position && pos.distance(&self.agent.position) <= self.agent.attributes.vision_radius as u32 {
+                senses.allies.push((e, pos.clone()));
+                if carrying_potion && alignment::is_good(&self.agent.attributes) && alignment::is_weakened(attr) {
+                    let target = TargetInfo {
+                        distance: pos.distance(&self.agent.position),
+                        opinion: opinion.value(),
+                        threat: false,
+                    };
+                    self.add_action(AiAction::help(&self.agent, e, pos).about(target));
+                }
+            } else if data.factions.thresholds.is_hostile(opinion) || grudges.contains(&e) || prey {
+                let threat = if visible { 1.0 } else { 0.0 };
+                let target = TargetInfo {
+                    distance: pos.distance(&self.agent.position),
+                    opinion: opinion.value(),
+                    threat: visible,
                 };
 
                 if threat > 0.0 {
+                    senses.enemies.push((e, pos.clone()));
                     self.state.insert(AiPredicate::UnderThreat, true);
+                    self.add_action(AiAction::flee(&self.agent, e, threat).about(target.clone()));
                 }
 
-                self.add_action(AiAction::attack(&self.agent, e, threat));
+                if self.agent.flags.aggressive {
+                    self.add_action(AiAction::attack(&self.agent, e, threat).about(target));
+                }
             }
         }
 
         info!("[{:?}] actions available: {:?}", data.time, self.actions);
+        senses
+    }
+
+    fn add_item_actions(&mut self, e: Entity, pos: &Position) {
+        self.state.insert(AiPredicate::Have(e), false);
+        self.state.insert(AiPredicate::At(e), *pos == self.agent.position);
+        let target = TargetInfo {
+            distance: pos.distance(&self.agent.position),
+            ..Default::default()
+        };
+        self.add_action(AiAction::go_to(&self.agent, e, pos));
+        self.add_action(AiAction::get(&self.agent, e));
+        self.add_action(AiAction::drink(&self.agent, e).about(target));
+    }
+
+    /// Add actions for things the agent remembers but can't see right now
+    pub fn add_memory_actions(&mut self, memory: &AiState, senses: &Senses) {
+        if memory.awareness == Awareness::Hunting {
+            for (e, sighting) in memory.enemies.iter() {
+                if !senses.enemies.iter().any(|(seen, _)| seen == e) {
+                    let target = TargetInfo {
+                        distance: sighting.position.distance(&self.agent.position),
+                        ..Default::default()
+                    };
+                    self.add_action(AiAction::chase(&self.agent, *e, &sighting.position).about(target));
+                }
+            }
+        }
+
+        for (e, pos) in memory.items.iter() {
+            if !senses.items.iter().any(|(seen, _)| seen == e) {
+                self.add_item_actions(*e, pos);
+            }
+        }
+    }
+
+    /// Replace each action's utility with its score from the response curves
+    pub fn score(&mut self, config: &UtilityConfig) {
+        for i in 0..self.actions.len() {
+            let utility = config.score(&self.agent, &self.actions[i]);
+            self.actions[i].utility = utility;
+        }
+        debug!("scored actions: {:?}", self.actions.iter().map(|a| (&a.name, a.utility)).collect::<Vec<_>>());
     }
 
     pub fn find_max_utility<'a>(&'a self) -> Option<&'a AiAction> {
@@ -240,17 +609,87 @@ impl AiActions {
         max_i.and_then(|i| self.actions.get(i))
     }
 
-    pub fn plan(&self) -> Option<Vec<AiActionType>> {
+    pub fn is_satisfied(&self, conditions: &State<AiPredicate>) -> bool {
+        conditions.iter().all(|(k, v)| self.state.get(k) == Some(v))
+    }
+
+    /// Plan for the most useful action: if it can't be done right away,
+    /// find the steps that make its preconditions true first
+    pub fn plan(&self) -> Option<Plan> {
         let possible_actions: Vec<Action<AiActionType, AiPredicate>> =
             self.actions.iter().map(|a| a.clone().action()).collect();
 
-        if let Some(ai_action) = self.find_max_utility() {
-            let action = ai_action.clone().action();
+        let ai_action = self.find_max_utility()?;
+        let mut steps = VecDeque::new();
 
-            let planned = rgoap::plan(&self.state, &action.post_conditions, &possible_actions);
-            planned.map(|actions| actions.iter().map(|action| action.name.clone()).collect())
-        } else {
-            None
+        if !self.is_satisfied(&ai_action.pre_conditions) {
+            let planned = rgoap::plan(&self.state, &ai_action.pre_conditions, &possible_actions)?;
+            steps.extend(planned.iter().map(|action| action.name.clone()));
+        }
+        steps.push_back(ai_action.action_type.clone());
+
+        Some(Plan {
+            goal: ai_action.action_type.clone(),
+            steps: steps,
+        })
+    }
+
+    /// True if a step of a plan has nothing left to do
+    pub fn is_done(&self, step: &AiActionType) -> bool {
+        match step {
+            AiActionType::GoTo(e) => self.state.get(&AiPredicate::At(*e)) == Some(&true)
+                || self.state.get(&AiPredicate::Have(*e)) == Some(&true),
+            AiActionType::Get(e) => self.state.get(&AiPredicate::Have(*e)) == Some(&true),
+            AiActionType::DrinkPotable(e) => self.state.get(&AiPredicate::Have(*e)) != Some(&true),
+            AiActionType::Patrol(waypoint) => self.agent.position == *waypoint,
+            AiActionType::Chase(_, last_seen) => self.agent.position == *last_seen,
+            AiActionType::ReturnHome => self.agent.position == self.agent.home,
+            AiActionType::Follow(_, leader_position) => self.agent.position.distance(leader_position) <= FOLLOW_DISTANCE,
+            AiActionType::Report(_, guard_position) => self.agent.position.distance(guard_position) <= 1,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::entities;
+
+    #[test]
+    fn it_plans_to_fetch_a_potion_before_drinking() {
+        let (_world, ids) = entities(1);
+        let potion = ids[0];
+        let attributes = Attributes { max_hp: 10, hp: 2, calmness: 0.5, ..Default::default() };
+        let agent = Agent::new(Position::new(0, 0), attributes, AiFlags::default(), Position::new(0, 0), vec![]);
+
+        let mut ai_actions = AiActions::new(agent);
+        ai_actions.state.insert(AiPredicate::UnderThreat, false);
+        ai_actions.state.insert(AiPredicate::Have(potion), false);
+        ai_actions.state.insert(AiPredicate::At(potion), false);
+        ai_actions.add_action(AiAction::meditate(&ai_actions.agent));
+        ai_actions.add_action(AiAction::go_to(&ai_actions.agent, potion, &Position::new(3, 0)));
+        ai_actions.add_action(AiAction::get(&ai_actions.agent, potion));
+        ai_actions.add_action(AiAction::drink(&ai_actions.agent, potion));
+
+        let plan = ai_actions.plan().unwrap();
+        assert_eq!(plan.goal, AiActionType::DrinkPotable(potion));
+        assert_eq!(plan.steps, vec![
+            AiActionType::GoTo(potion),
+            AiActionType::Get(potion),
+            AiActionType::DrinkPotable(potion),
+        ]);
+    }
+
+    #[test]
+    fn patrols_go_round_the_route() {
+        let home = Position::new(10, 10);
+        let mut agent = Agent::new(home.delta(-3, -3), Attributes::default(), AiFlags::default(), home.clone(), vec![]);
+
+        for (dx, dy) in PATROL_ROUTE.iter().cycle().skip(1).take(4) {
+            let waypoint = agent.next_waypoint();
+            assert_eq!(waypoint, home.delta(*dx, *dy));
+            agent.position = waypoint;
         }
     }
 }
@@ -0,0 +1,184 @@
+use specs::Entity;
+use crate::game::deeds::{Deed, DeedKind};
+use crate::game::ecs::{Attributes, Position};
+use crate::game::events::Time;
+use crate::game::factions::Factions;
+use super::actions::Agent;
+use super::state::{AiState, Sighting};
+
+/// Goodness above this makes a creature look out for its friends
+pub const GOOD: f32 = 0.3;
+/// Goodness below this makes a creature pick on the weak
+pub const EVIL: f32 = -0.3;
+/// Lawfulness above this makes a creature report crimes to a guard
+pub const LAWFUL: f32 = 0.3;
+/// Creatures below this fraction of their hp count as weakened
+pub const WEAKENED: f32 = 0.5;
+/// How far one witnessed deed moves the witness's faction's opinion of the actor's
+pub const WITNESS_SHIFT: f32 = 0.05;
+
+pub fn is_good(attributes: &Attributes) -> bool {
+    attributes.goodness > GOOD
+}
+
+pub fn is_evil(attributes: &Attributes) -> bool {
+    attributes.goodness < EVIL
+}
+
+pub fn is_lawful(attributes: &Attributes) -> bool {
+    attributes.lawfulness > LAWFUL
+}
+
+pub fn is_weakened(attributes: &Attributes) -> bool {
+    attributes.max_hp > 0 && (attributes.hp as f32) < attributes.max_hp as f32 * WEAKENED
+}
+
+/// How much the witness's faction should change its opinion of the actor's faction.
+/// The good and the lawful think less of crimes, the evil and chaotic more, and
+/// everyone approves of harm done to their enemies. Kills count double.
+pub fn opinion_shift(witness: &Attributes, deed: &Deed, factions: &Factions) -> f32 {
+    let mut shift = 0.0;
+    if deed.crime {
+        shift -= WITNESS_SHIFT * (witness.goodness + witness.lawfulness);
+    }
+    if factions.is_hostile(&witness.faction, &deed.victim_faction) {
+        shift += WITNESS_SHIFT;
+    }
+
+    match deed.kind {
+        DeedKind::Attack => shift,
+        DeedKind::Kill => shift * 2.0,
+    }
+}
+
+/// Take in something the agent saw another creature do
+pub fn witness(entity: Entity, memory: &mut AiState, agent: &Agent, deed: &Deed, factions: &mut Factions) {
+    if deed.actor == entity {
+        return;
+    }
+    if deed.victim == entity {
+        memory.grudges.insert(deed.actor);
+        return;
+    }
+
+    let attributes = &agent.attributes;
+    let shift = opinion_shift(attributes, deed, factions);
+    factions.adjust(&attributes.faction, &deed.actor_faction, shift);
+
+    if deed.crime && is_lawful(attributes) {
+        if agent.flags.guard {
+            memory.grudges.insert(deed.actor);
+        } else {
+            memory.reports.insert(deed.actor, deed.position.clone());
+        }
+    }
+
+    let ally = deed.victim_faction == attributes.faction || factions.is_friendly(&attributes.faction, &deed.victim_faction);
+    if ally && deed.actor_faction != deed.victim_faction && is_good(attributes) {
+        memory.grudges.insert(deed.actor);
+    }
+}
+
+/// Tell a guard about every crime the reporter has seen. The guard goes after the culprits.
+pub fn report(now: Time, reporter: &mut AiState, guard: &mut AiState, guard_agent: &Agent) {
+    let sightings: Vec<(Entity, Sighting)> = reporter.reports
+        .iter()
+        .map(|(e, pos)| (*e, Sighting { position: pos.clone(), time: now }))
+        .collect();
+
+    guard.grudges.extend(reporter.reports.keys().cloned());
+    guard.hear(now, guard_agent, &sightings);
+    reporter.reports.clear();
+}
+
+/// The closest guard among the agent's allies
+pub fn nearest_guard<F>(agent: &Agent, allies: &[(Entity, Position)], is_guard: F) -> Option<(Entity, Position)>
+    where F: Fn(Entity) -> bool
+{
+    allies
+        .iter()
+        .filter(|(e, _)| is_guard(*e))
+        .min_by_key(|(_, pos)| pos.distance(&agent.position))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::{entities, AiFlags};
+    use crate::game::factions::{FactionConfig, MONSTER, NEUTRAL, PLAYER};
+
+    fn gnome(lawfulness: f32, goodness: f32, guard: bool) -> Agent {
+        let attributes = Attributes {
+            max_hp: 10,
+            hp: 10,
+            goodness: goodness,
+            lawfulness: lawfulness,
+            faction: NEUTRAL.to_owned(),
+            ..Default::default()
+        };
+        let flags = AiFlags { guard: guard, ..Default::default() };
+        Agent::new(Position::new(0, 0), attributes, flags, Position::new(0, 0), vec![])
+    }
+
+    #[test]
+    fn lawful_witnesses_report_crimes_and_guards_act_on_them() {
+        let (_world, ids) = entities(3);
+        let (player, victim, witness_entity) = (ids[0], ids[1], ids[2]);
+        let mut factions = Factions::new(&FactionConfig::builtin());
+        let deed = Deed {
+            id: 1,
+            kind: DeedKind::Attack,
+            actor: player,
+            actor_faction: PLAYER.to_owned(),
+            victim: victim,
+            victim_faction: NEUTRAL.to_owned(),
+            position: Position::new(3, 3),
+            time: Time::new(1, 0),
+            crime: true,
+        };
+
+        let witness_agent = gnome(0.8, 0.0, false);
+        let mut witness_memory = AiState::default();
+        witness(witness_entity, &mut witness_memory, &witness_agent, &deed, &mut factions);
+        assert_eq!(witness_memory.reports.get(&player), Some(&Position::new(3, 3)));
+        assert!(witness_memory.grudges.is_empty());
+        assert!(factions.get(NEUTRAL, PLAYER).is_negative());
+
+        let guard_agent = gnome(0.8, 0.0, true);
+        let mut guard_memory = AiState::default();
+        report(Time::new(2, 0), &mut witness_memory, &mut guard_memory, &guard_agent);
+        assert!(witness_memory.reports.is_empty());
+        assert!(guard_memory.grudges.contains(&player));
+        assert!(guard_memory.enemies.contains_key(&player));
+    }
+
+    #[test]
+    fn good_witnesses_stand_up_for_allies_and_evil_ones_approve() {
+        let (_world, ids) = entities(3);
+        let (orc, victim, witness_entity) = (ids[0], ids[1], ids[2]);
+        let mut factions = Factions::new(&FactionConfig::builtin());
+        let deed = Deed {
+            id: 1,
+            kind: DeedKind::Kill,
+            actor: orc,
+            actor_faction: MONSTER.to_owned(),
+            victim: victim,
+            victim_faction: NEUTRAL.to_owned(),
+            position: Position::new(3, 3),
+            time: Time::new(1, 0),
+            crime: true,
+        };
+
+        let mut memory = AiState::default();
+        witness(witness_entity, &mut memory, &gnome(0.0, 0.8, false), &deed, &mut factions);
+        assert!(memory.grudges.contains(&orc));
+        let after_good = factions.get(NEUTRAL, MONSTER).value();
+        assert!(after_good < 0.0);
+
+        let mut memory = AiState::default();
+        witness(witness_entity, &mut memory, &gnome(-0.5, -0.8, false), &deed, &mut factions);
+        assert!(memory.grudges.is_empty());
+        assert!(factions.get(NEUTRAL, MONSTER).value() > after_good);
+    }
+}
@@ -0,0 +1,94 @@
+use specs::Entity;
+use crate::game::deeds::{DeedKind, Deeds};
+use crate::game::events::Time;
+use super::actions::{AiActionType, AiActions};
+use super::pack::{Order, Orders};
+
+/// Companions set off after the player once they are further away than this
+pub const LEASH_DISTANCE: u32 = 4;
+
+/// What the player has told their companions to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompanionOrder {
+    /// Keep up with the player and join in their fights
+    Follow,
+    /// Hold position, only fighting what comes close
+    Stay,
+    /// Go after a target
+    Attack(Entity),
+}
+
+impl Default for CompanionOrder {
+    fn default() -> Self {
+        CompanionOrder::Follow
+    }
+}
+
+/// Whoever the player attacked most recently, if they did so lately
+pub fn player_target(deeds: &Deeds, player: Entity) -> Option<Entity> {
+    deeds.recent
+        .iter()
+        .rev()
+        .find(|deed| deed.actor == player && deed.kind == DeedKind::Attack)
+        .map(|deed| deed.victim)
+}
+
+/// Companions treat the player as their pack leader. Following, they join in the
+/// player's fight or keep up with them; staying, they take no orders.
+pub fn orders(order: &CompanionOrder, player: Entity, player_target: Option<Entity>, now: Time) -> Option<Orders> {
+    let order = match order {
+        CompanionOrder::Attack(target) => Order::Attack(*target),
+        CompanionOrder::Stay => return None,
+        CompanionOrder::Follow => match player_target {
+            Some(target) => Order::Attack(target),
+            None => Order::Follow(player),
+        },
+    };
+
+    Some(Orders { order: order, leader: player, time: now })
+}
+
+/// Drop the actions that would take a companion away from where it was told to be:
+/// wandering off while staying, or heading back to where it started while following
+pub fn restrict(ai_actions: &mut AiActions, order: &CompanionOrder) {
+    let staying = *order == CompanionOrder::Stay;
+    ai_actions.actions.retain(|action| match action.action_type {
+        AiActionType::Wander | AiActionType::Patrol(_) => !staying,
+        AiActionType::ReturnHome => staying,
+        _ => true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::deeds::Deed;
+    use crate::game::ecs::{entities, Position};
+
+    #[test]
+    fn companions_join_in_the_players_fight() {
+        let (_world, ids) = entities(2);
+        let (player, orc) = (ids[0], ids[1]);
+        let now = Time::new(3, 0);
+        let mut deeds = Deeds::default();
+
+        let follow = orders(&CompanionOrder::Follow, player, player_target(&deeds, player), now).unwrap();
+        assert_eq!(follow.order, Order::Follow(player));
+
+        deeds.record(Deed {
+            id: 0,
+            kind: DeedKind::Attack,
+            actor: player,
+            actor_faction: "player".to_owned(),
+            victim: orc,
+            victim_faction: "monster".to_owned(),
+            position: Position::new(1, 1),
+            time: now,
+            crime: false,
+        });
+        let attack = orders(&CompanionOrder::Follow, player, player_target(&deeds, player), now).unwrap();
+        assert_eq!(attack.order, Order::Attack(orc));
+
+        assert_eq!(orders(&CompanionOrder::Stay, player, Some(orc), now), None);
+    }
+}
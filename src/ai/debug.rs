@@ -0,0 +1,82 @@
+use serde::Serialize;
+use crate::game::ecs::Position;
+use super::actions::{AiActions, Plan};
+use super::state::AiState;
+
+/// A candidate action and how it scored
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActionDebug {
+    pub name: String,
+    pub utility: f32,
+}
+
+/// Why an AI controlled entity did what it did on its last turn
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AiDebug {
+    pub entity: String,
+    pub name: String,
+    pub time: String,
+    pub position: (i32, i32),
+    pub awareness: String,
+    pub morale: f32,
+    pub broken: bool,
+    pub actions: Vec<ActionDebug>,
+    pub goal: Option<String>,
+    pub plan: Vec<String>,
+    /// Where the current step is taking it
+    pub path: Vec<(i32, i32)>,
+}
+
+impl AiDebug {
+    pub fn new(entity: String, time: String, ai_actions: &AiActions, memory: &AiState, plan: Option<&Plan>, path: &[Position]) -> Self {
+        let agent = &ai_actions.agent;
+        let mut actions: Vec<ActionDebug> = ai_actions.actions
+            .iter()
+            .map(|a| ActionDebug { name: a.name.clone(), utility: a.utility })
+            .collect();
+        actions.sort_by(|a, b| b.utility.partial_cmp(&a.utility).unwrap_or(std::cmp::Ordering::Equal));
+
+        AiDebug {
+            entity: entity,
+            name: agent.attributes.name.clone(),
+            time: time,
+            position: (agent.position.x, agent.position.y),
+            awareness: format!("{:?}", memory.awareness),
+            morale: memory.morale.value,
+            broken: memory.morale.broken,
+            actions: actions,
+            goal: plan.map(|p| format!("{:?}", p.goal)),
+            plan: plan.map(|p| p.steps.iter().map(|s| format!("{:?}", s)).collect()).unwrap_or_default(),
+            path: path.iter().map(|p| (p.x, p.y)).collect(),
+        }
+    }
+
+    pub fn path_positions(&self) -> Vec<Position> {
+        self.path.iter().map(|(x, y)| Position::new(*x, *y)).collect()
+    }
+
+    /// A text summary for the overlay
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("{} {} at {:?}", self.name, self.entity, self.position),
+            format!("awareness: {}", self.awareness),
+            format!("morale: {:.2}{}", self.morale, if self.broken { " (broken)" } else { "" }),
+            format!("goal: {}", self.goal.as_ref().map(|g| g.as_str()).unwrap_or("none")),
+        ];
+
+        for (i, step) in self.plan.iter().enumerate() {
+            lines.push(format!("  {}. {}", i + 1, step));
+        }
+
+        lines.push("actions:".to_owned());
+        for action in self.actions.iter() {
+            lines.push(format!("  {:.3} {}", action.utility, action.name));
+        }
+
+        lines
+    }
+}
+
+pub fn to_json(records: &[&AiDebug]) -> Result<String, String> {
+    serde_json::to_string(records).map_err(|e| e.to_string())
+}
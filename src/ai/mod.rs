@@ -1,66 +1,398 @@
 use crate::data::Data;
-use crate::game::ecs::{Attributes, Liquid, Position};
-use crate::game::system::{GameActionType, GameActor};
-use crate::game::path::PathFinder;
+use crate::game::deeds::Deed;
+use crate::game::ecs::{AiFlags, Attributes, Inventory, Liquid, MovementTraits, Position, Rect};
+use crate::game::system::{Collider, GameActionType, GameActor};
+use crate::game::path::{DistanceMap, PathFinder};
+use crate::game::level_gen;
+use rand::Rng;
 use specs::{Entities, Entity, ReadStorage, WriteStorage};
+use std::collections::BTreeMap;
 
 pub mod actions;
+pub mod alignment;
+pub mod companion;
+pub mod morale;
+pub mod pack;
 pub mod state;
+pub mod utility;
+pub mod debug;
 
 use actions::{Agent, AiActionType, AiActions};
+use actions::AiAction;
+use companion::{CompanionOrder, LEASH_DISTANCE};
+use pack::{Order, Orders, FOLLOW_DISTANCE};
+use state::{AiState, Awareness, Senses, Sighting};
+use utility::TargetInfo;
+use utility::UtilityConfig;
+use debug::AiDebug;
 
-pub struct Ai {}
+/// How far around itself a fleeing creature looks for somewhere to run
+pub const FLEE_RADIUS: i32 = 15;
+/// Distances from threats are scaled by this before rescanning the flee map;
+/// the bigger it is the more a fleeing creature prefers open ground to dead ends
+pub const FLEE_FACTOR: f32 = 1.2;
+
+pub struct Ai {
+    pub utility: UtilityConfig,
+    /// Record why each entity did what it did
+    pub debug: bool,
+    pub records: BTreeMap<Entity, AiDebug>,
+}
 
 impl Default for Ai {
     fn default() -> Self {
-        Ai {}
+        Ai::new(UtilityConfig::builtin())
     }
 }
 
 impl Ai {
+    pub fn new(utility: UtilityConfig) -> Self {
+        Ai {
+            utility: utility,
+            debug: false,
+            records: BTreeMap::new(),
+        }
+    }
+
+    pub fn toggle_debug(&mut self) {
+        self.debug = !self.debug;
+        if !self.debug {
+            self.records.clear();
+        }
+        info!("ai debugging {}", if self.debug { "on" } else { "off" });
+    }
+
+    pub fn debug_for(&self, entity: Entity) -> Option<&AiDebug> {
+        self.records.get(&entity)
+    }
+
+    /// Every record from the last turn as JSON
+    pub fn dump_json(&self) -> Result<String, String> {
+        let records: Vec<&AiDebug> = self.records.values().collect();
+        debug::to_json(&records)
+    }
+
     pub fn schedule_ai_actions<'a>(
         &mut self,
         app: &mut Data,
         entity: Entity,
         entities: &Entities<'a>,
         positions: &mut WriteStorage<'a, Position>,
-        attributes: &ReadStorage<'a, Attributes>,
+        attributes: &WriteStorage<'a, Attributes>,
         liquids: &ReadStorage<'a, Liquid>,
+        movement_traits: &ReadStorage<'a, MovementTraits>,
+        ai_flags: &ReadStorage<'a, AiFlags>,
+        inventories: &WriteStorage<'a, Inventory>,
+        ai_states: &mut WriteStorage<'a, AiState>,
     ) {
-        // unimplemented
         let entity_attrs = attributes.get(entity).unwrap();
         let entity_position = positions.get(entity).map(|e| e.to_owned()).unwrap();
-        let agent = Agent::new(entity_position.clone(), entity_attrs.clone());
+        let entity_flags = ai_flags.get(entity).cloned().unwrap_or_default();
+        let inventory = inventories.get(entity).map(|i| i.items.clone()).unwrap_or_default();
+        let mut memory = ai_states.get(entity).cloned().unwrap_or_default();
+        let home = memory.home.get_or_insert_with(|| entity_position.clone()).clone();
+        let agent = Agent::new(entity_position.clone(), entity_attrs.clone(), entity_flags, home, inventory);
+
+        // take in what's been going on nearby since our last turn
+        let deeds: Vec<Deed> = app.deeds
+            .since(memory.last_deed)
+            .filter(|deed| deed.position.distance(&agent.position) <= agent.attributes.vision_radius as u32)
+            .cloned()
+            .collect();
+        memory.last_deed = app.deeds.last_id();
+        for deed in deeds.iter() {
+            if !memory.is_asleep() || deed.victim == entity {
+                alignment::witness(entity, &mut memory, &agent, deed, &mut app.factions);
+            }
+        }
+        let alive = |e: &Entity| attributes.get(*e).map(|a| a.alive).unwrap_or(false);
+        memory.grudges.retain(|e| alive(e));
+        memory.reports.retain(|e, _| alive(e));
+
+        // companions take their orders from the player
+        let companion = agent.attributes.faction == app.factions.player_faction;
+        let orders = if companion {
+            if let CompanionOrder::Attack(target) = app.companion_order {
+                if !alive(&target) {
+                    app.companion_order = CompanionOrder::Follow;
+                }
+            }
+            let target = companion::player_target(&app.deeds, app.player).filter(|e| alive(e) && *e != entity);
+            let orders = companion::orders(&app.companion_order, app.player, target, app.time);
+            if let Some(Orders { order: Order::Attack(target), .. }) = orders {
+                memory.grudges.insert(target);
+            }
+            orders
+        } else {
+            None
+        };
+
         let mut ai_actions = AiActions::new(agent);
-        ai_actions.setup_actions(app, entities, positions, attributes, liquids);
+        let mut senses = ai_actions.setup_actions(app, entities, positions, attributes, liquids, &memory.grudges);
+        memory.grudges.extend(senses.prey.iter().cloned());
+        senses.fallen = memory.morale.allies
+            .iter()
+            .filter(|ally| !attributes.get(**ally).map(|a| a.alive).unwrap_or(false))
+            .cloned()
+            .collect();
 
-        if let Some(actions) = ai_actions.plan() {
-            if let Some(action) = actions.get(0) {
-                match action {
-                    AiActionType::Meditate => {
-                        app.action(GameActor::NonPlayer(entity), GameActionType::Pass);
-                    },
-                    AiActionType::Attack(target) => {
-                        if let Some(target_position) = positions.get(*target) {
-                            let pathfinder = PathFinder::new(&app.level);
-                            if let Some((path, cost)) = pathfinder.path(&entity_position, target_position) {
-                                debug!("[{:?}] ai entity {:?} targetting {:?} on path ({:?},{})", app.time, entity, target, path, cost);
-                                assert!(path.get(0).unwrap() == &entity_position);
-                                let next_tile = path.get(1).unwrap();
-                                let x = next_tile.x - entity_position.x;
-                                let y = next_tile.y - entity_position.y;
-                                app.action(GameActor::NonPlayer(entity), GameActionType::MoveAttack(x, y));
-                            }
+        memory.observe(app.time, &ai_actions.agent, &senses);
+        ai_actions.agent.morale = memory.morale.value;
+        ai_actions.agent.broken = memory.morale.broken;
+
+        // pack mates call out what they've seen, and followers take orders from their leader
+        let mates = pack::pack_mates(entity, &ai_actions.agent, entities, positions, attributes);
+        for (mate, _) in mates.iter() {
+            if let Some(mate_memory) = ai_states.get(*mate) {
+                let sightings: Vec<(Entity, Sighting)> = mate_memory.enemies.iter().map(|(e, s)| (*e, s.clone())).collect();
+                memory.hear(app.time, &ai_actions.agent, &sightings);
+            }
+        }
+
+        let orders = orders.or_else(|| memory.current_orders(app.time).cloned());
+        if let Some(Orders { order: Order::Attack(target), .. }) = orders {
+            if let Some(pos) = positions.get(target) {
+                memory.hear(app.time, &ai_actions.agent, &[(target, Sighting { position: pos.clone(), time: app.time })]);
+            }
+        }
+        if memory.is_asleep() {
+            memory.plan = None;
+            if self.debug {
+                self.records.insert(entity, AiDebug::new(format!("{:?}", entity), format!("{}", app.time), &ai_actions, &memory, None, &[]));
+            }
+            if let Err(e) = ai_states.insert(entity, memory) {
+                warn!("[{:?}] could not store ai state for {:?}: {:?}", app.time, entity, e);
+            }
+            app.action(GameActor::NonPlayer(entity), GameActionType::Pass);
+            return;
+        }
+        ai_actions.add_memory_actions(&memory, &senses);
+        if !memory.reports.is_empty() && !ai_actions.agent.flags.guard {
+            let is_guard = |e: Entity| ai_flags.get(e).map(|f| f.guard).unwrap_or(false);
+            if let Some((guard, pos)) = alignment::nearest_guard(&ai_actions.agent, &senses.allies, is_guard) {
+                let distance = pos.distance(&ai_actions.agent.position);
+                if distance <= 1 {
+                    let guard_agent = Agent::new(
+                        pos.clone(),
+                        attributes.get(guard).cloned().unwrap_or_default(),
+                        ai_flags.get(guard).cloned().unwrap_or_default(),
+                        pos.clone(),
+                        vec![],
+                    );
+                    if let Some(guard_memory) = ai_states.get_mut(guard) {
+                        info!("[{:?}] {:?} reports {:?} to {:?}", app.time, entity, memory.reports.keys().collect::<Vec<_>>(), guard);
+                        alignment::report(app.time, &mut memory, guard_memory, &guard_agent);
+                    }
+                } else {
+                    let target = TargetInfo { distance: distance, ..Default::default() };
+                    let report = AiAction::report(&ai_actions.agent, guard, &pos).about(target);
+                    ai_actions.add_action(report);
+                }
+            }
+        }
+        if let Some(Orders { order: Order::Follow(leader), .. }) = orders {
+            if let Some(pos) = positions.get(leader) {
+                let distance = pos.distance(&ai_actions.agent.position);
+                let leash = if companion { LEASH_DISTANCE } else { FOLLOW_DISTANCE };
+                if distance > leash {
+                    let target = TargetInfo { distance: distance, ..Default::default() };
+                    let follow = AiAction::follow(&ai_actions.agent, leader, pos).about(target);
+                    ai_actions.add_action(follow);
+                }
+            }
+        }
+        if companion {
+            companion::restrict(&mut ai_actions, &app.companion_order);
+        }
+        ai_actions.score(&self.utility);
+        if let Some(orders) = orders.as_ref() {
+            pack::obey(&mut ai_actions, &orders.order);
+        }
+
+        // carry on with the plan from last turn unless something more useful came up
+        let top = ai_actions.find_max_utility().map(|a| a.action_type.clone());
+        match top {
+            Some(AiActionType::Flee(_)) => memory.set_awareness(Awareness::Fleeing, app.time),
+            Some(AiActionType::Attack(_)) if !senses.enemies.is_empty() => memory.set_awareness(Awareness::Hunting, app.time),
+            _ => {},
+        }
+
+        if ai_actions.agent.flags.leader {
+            let order = match top {
+                Some(AiActionType::Attack(target)) | Some(AiActionType::Chase(target, _)) => Order::Attack(target),
+                _ => Order::Follow(entity),
+            };
+            for (mate, _) in mates.iter() {
+                if ai_flags.get(*mate).map(|f| f.leader).unwrap_or(false) {
+                    continue;
+                }
+                if let Some(mate_memory) = ai_states.get_mut(*mate) {
+                    mate_memory.orders = Some(Orders { order: order.clone(), leader: entity, time: app.time });
+                }
+            }
+        }
+
+        let plan = match memory.plan.take() {
+            Some(plan) if top.as_ref().map(|t| plan.goal.same_goal(t)).unwrap_or(false) => Some(plan),
+            _ => ai_actions.plan(),
+        };
+
+        if let Some(mut plan) = plan {
+            while plan.steps.front().map(|step| ai_actions.is_done(step)).unwrap_or(false) {
+                plan.steps.pop_front();
+            }
+
+            let traits = movement_traits.get(entity).cloned().unwrap_or_default();
+            if self.debug {
+                let path = plan.steps.front()
+                    .and_then(|step| self.step_target(app, step, &ai_actions.agent, positions))
+                    .and_then(|target| PathFinder::new(&app.level, &traits).path(&entity_position, &target))
+                    .map(|(path, _)| path)
+                    .unwrap_or_default();
+                self.records.insert(entity, AiDebug::new(format!("{:?}", entity), format!("{}", app.time), &ai_actions, &memory, Some(&plan), &path));
+            }
+
+            if let Some(step) = plan.steps.front().cloned() {
+                match self.carry_out(app, entity, &step, &ai_actions.agent, &senses, &mates, positions, liquids, &traits) {
+                    Some(action) => {
+                        debug!("[{:?}] ai entity {:?} doing {:?} of {:?}", app.time, entity, step, plan.goal);
+                        app.action(GameActor::NonPlayer(entity), action);
+                        if step.is_one_shot() {
+                            plan.steps.pop_front();
                         }
                     },
-                    _ => {
-                        // not implemented
+                    None => {
+                        debug!("[{:?}] ai entity {:?} can't {:?}, dropping plan", app.time, entity, step);
+                        plan.steps.clear();
                     }
                 }
             }
+
+            if !plan.steps.is_empty() {
+                memory.plan = Some(plan);
+            }
+        } else if self.debug {
+            self.records.insert(entity, AiDebug::new(format!("{:?}", entity), format!("{}", app.time), &ai_actions, &memory, None, &[]));
+        }
+
+        if let Err(e) = ai_states.insert(entity, memory) {
+            warn!("[{:?}] could not store ai state for {:?}: {:?}", app.time, entity, e);
         }
+
         // only necessary if we didn't issue an action that ends the turn for some reason,
         // (either nothing to do or a bug)
         app.action(GameActor::NonPlayer(entity), GameActionType::Pass);
     }
+
+    /// Turn one step of a plan into a game action, or None if it can't be done any more
+    fn carry_out<'a>(
+        &self,
+        app: &Data,
+        entity: Entity,
+        step: &AiActionType,
+        agent: &Agent,
+        senses: &Senses,
+        mates: &[(Entity, Position)],
+        positions: &WriteStorage<'a, Position>,
+        liquids: &ReadStorage<'a, Liquid>,
+        traits: &MovementTraits,
+    ) -> Option<GameActionType> {
+        match step {
+            AiActionType::Meditate => Some(GameActionType::Pass),
+            AiActionType::Attack(target) => {
+                let target_position = positions.get(*target)?;
+                if agent.position.distance(target_position) <= 1 {
+                    return Some(move_to(&agent.position, target_position));
+                }
+                if pack::should_wait(&app.level, traits, &agent.position, target_position, mates) {
+                    debug!("[{:?}] ai entity {:?} waiting for {:?} to come out of the corridor", app.time, entity, target);
+                    return Some(GameActionType::Pass);
+                }
+
+                // make for a free tile next to the target, rather than queue behind whoever's there
+                let collider = Collider::new(&app.level, traits);
+                match pack::surround_slot(&collider, &agent.position, target_position, mates) {
+                    Some(slot) => self.step_towards(app, entity, &agent.position, &slot, traits),
+                    None => Some(GameActionType::Pass),
+                }
+            },
+            AiActionType::Help(ally, _) => {
+                let ally_position = positions.get(*ally)?;
+                let potion = agent.inventory.iter().find(|e| liquids.get(**e).map(|l| l.potable).unwrap_or(false))?;
+                if agent.position.distance(ally_position) <= 1 {
+                    Some(GameActionType::Give(*potion, *ally))
+                } else {
+                    self.step_towards(app, entity, &agent.position, ally_position, traits)
+                }
+            },
+            AiActionType::GoTo(_) | AiActionType::Chase(_, _) | AiActionType::Patrol(_) | AiActionType::ReturnHome
+            | AiActionType::Follow(_, _) | AiActionType::Report(_, _) => {
+                let target = self.step_target(app, step, agent, positions)?;
+                self.step_towards(app, entity, &agent.position, &target, traits)
+            },
+            AiActionType::Get(item) => Some(GameActionType::PickUp(*item)),
+            AiActionType::DrinkPotable(item) => Some(GameActionType::Drink(*item)),
+            AiActionType::Flee(threat) => {
+                // run from everything in sight, not just the threat the action was about
+                let threat_position = positions.get(*threat)?;
+                let mut threats: Vec<Position> = senses.enemies.iter().map(|(_, pos)| pos.clone()).collect();
+                if threats.is_empty() {
+                    threats.push(threat_position.clone());
+                }
+
+                let bounds = Rect::new(agent.position.x - FLEE_RADIUS, agent.position.y - FLEE_RADIUS, FLEE_RADIUS * 2 + 1, FLEE_RADIUS * 2 + 1);
+                let flee_map = DistanceMap::new(&app.level, traits, &threats, bounds).inverted(&app.level, traits, FLEE_FACTOR);
+                let collider = Collider::new(&app.level, traits);
+
+                match flee_map.downhill(&agent.position, |pos| collider.get(pos).is_empty()) {
+                    Some(pos) => Some(move_to(&agent.position, &pos)),
+                    // cornered, so fight
+                    None if agent.position.distance(threat_position) <= 1 => Some(move_to(&agent.position, threat_position)),
+                    None => Some(GameActionType::Pass),
+                }
+            },
+            AiActionType::Wander => {
+                let collider = Collider::new(&app.level, traits);
+                let open: Vec<Position> = agent.position
+                    .neighbours()
+                    .into_iter()
+                    .filter(|pos| collider.get(pos).is_empty())
+                    .collect();
+
+                if open.is_empty() {
+                    Some(GameActionType::Pass)
+                } else {
+                    let pos = &open[rand::thread_rng().gen_range(0, open.len())];
+                    Some(move_to(&agent.position, pos))
+                }
+            },
+        }
+    }
+
+    /// Where a step that involves walking somewhere is heading
+    fn step_target<'a>(&self, app: &Data, step: &AiActionType, agent: &Agent, positions: &WriteStorage<'a, Position>) -> Option<Position> {
+        match step {
+            AiActionType::Attack(target) | AiActionType::GoTo(target) => positions.get(*target).cloned(),
+            AiActionType::Chase(_, last_seen) => Some(last_seen.clone()),
+            // corners of the route can end up inside walls
+            AiActionType::Patrol(waypoint) => level_gen::nearest_floor(&app.level, waypoint),
+            AiActionType::ReturnHome => Some(agent.home.clone()),
+            AiActionType::Follow(leader, last_seen) | AiActionType::Report(leader, last_seen) | AiActionType::Help(leader, last_seen) => {
+                positions.get(*leader).cloned().or_else(|| Some(last_seen.clone()))
+            },
+            _ => None,
+        }
+    }
+
+    fn step_towards(&self, app: &Data, entity: Entity, from: &Position, to: &Position, traits: &MovementTraits) -> Option<GameActionType> {
+        let pathfinder = PathFinder::new(&app.level, traits);
+        let (path, cost) = pathfinder.path(from, to)?;
+        debug!("[{:?}] ai entity {:?} heading to {:?} on path ({:?},{})", app.time, entity, to, path, cost);
+        assert!(path.get(0).unwrap() == from);
+        path.get(1).map(|next_tile| move_to(from, next_tile))
+    }
+}
+
+fn move_to(from: &Position, to: &Position) -> GameActionType {
+    GameActionType::MoveAttack(to.x - from.x, to.y - from.y)
 }
@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+use specs::Entity;
+use crate::game::events::Time;
+use super::actions::Agent;
+use super::state::Senses;
+
+/// Below this a creature breaks and runs
+pub const BROKEN_MORALE: f32 = 0.3;
+/// A broken creature rallies once morale climbs back to this
+pub const RALLY_MORALE: f32 = 0.7;
+/// Morale lost for losing all of one's hit points at once
+pub const DAMAGE_SHOCK: f32 = 1.0;
+/// Morale lost for each ally seen dying
+pub const ALLY_DEATH_SHOCK: f32 = 0.3;
+/// Morale lost each turn for each enemy more than the creature and its allies can match
+pub const OUTNUMBERED_SHOCK: f32 = 0.05;
+/// Morale regained each turn nothing bad happens, for a creature of middling calmness
+pub const RECOVERY: f32 = 0.02;
+/// Recovery is this much faster with no enemy in sight
+pub const OUT_OF_SIGHT_RECOVERY: f32 = 3.0;
+
+/// How willing a creature is to keep fighting, from 0 (broken) to 1 (steady)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Morale {
+    pub value: f32,
+    /// Set when morale drops below `BROKEN_MORALE`, cleared when it gets back to `RALLY_MORALE`
+    pub broken: bool,
+    /// Hit points at the last update, to notice damage taken since
+    pub last_hp: Option<i32>,
+    /// Allies seen at the last update, to notice which of them died
+    pub allies: BTreeSet<Entity>,
+}
+
+impl Default for Morale {
+    fn default() -> Self {
+        Morale {
+            value: 1.0,
+            broken: false,
+            last_hp: None,
+            allies: BTreeSet::new(),
+        }
+    }
+}
+
+impl Morale {
+    /// Take the knocks of the last turn. Calm creatures lose less morale and get it back faster.
+    pub fn update(&mut self, now: Time, agent: &Agent, senses: &Senses) {
+        let calmness = agent.attributes.calmness.max(0.0).min(1.0);
+        let hp = agent.attributes.hp;
+        let max_hp = agent.attributes.max_hp;
+        let mut shock = 0.0;
+
+        if let Some(last_hp) = self.last_hp {
+            if hp < last_hp && max_hp > 0 {
+                shock += DAMAGE_SHOCK * (last_hp - hp) as f32 / max_hp as f32;
+            }
+        }
+        self.last_hp = Some(hp);
+
+        if !senses.fallen.is_empty() {
+            debug!("[{:?}] saw {} allies die", now, senses.fallen.len());
+            shock += ALLY_DEATH_SHOCK * senses.fallen.len() as f32;
+        }
+
+        let outnumbered = senses.enemies.len() as i32 - senses.allies.len() as i32 - 1;
+        if outnumbered > 0 {
+            shock += OUTNUMBERED_SHOCK * outnumbered as f32;
+        }
+
+        if shock > 0.0 {
+            self.value -= shock * (1.5 - calmness);
+        } else {
+            let rate = if senses.enemies.is_empty() { OUT_OF_SIGHT_RECOVERY } else { 1.0 };
+            self.value += RECOVERY * rate * (0.5 + calmness);
+        }
+        self.value = self.value.max(0.0).min(1.0);
+
+        if !self.broken && self.value < BROKEN_MORALE {
+            debug!("[{:?}] morale broke at {:.2}", now, self.value);
+            self.broken = true;
+        } else if self.broken && self.value >= RALLY_MORALE {
+            debug!("[{:?}] rallied at {:.2}", now, self.value);
+            self.broken = false;
+        }
+
+        self.allies = senses.allies.iter().map(|(e, _)| *e).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::{entities, AiFlags, Attributes, Position};
+
+    fn agent(hp: i32, calmness: f32) -> Agent {
+        let attributes = Attributes { max_hp: 10, hp: hp, calmness: calmness, ..Default::default() };
+        Agent::new(Position::new(0, 0), attributes, AiFlags::default(), Position::new(0, 0), vec![])
+    }
+
+    #[test]
+    fn nervous_creatures_break_sooner_than_calm_ones() {
+        let mut nervous = Morale::default();
+        let mut calm = Morale::default();
+        let time = Time::new(1, 0);
+
+        for hp in [10, 7, 4].iter() {
+            nervous.update(time, &agent(*hp, 0.0), &Senses::default());
+            calm.update(time, &agent(*hp, 1.0), &Senses::default());
+        }
+
+        assert!(nervous.broken);
+        assert!(!calm.broken);
+        assert!(calm.value > nervous.value);
+    }
+
+    #[test]
+    fn losing_allies_and_being_outnumbered_hurt_morale() {
+        let (_world, ids) = entities(5);
+        let ally = ids[0];
+        let enemies: Vec<_> = ids[1..].iter().zip(0..).map(|(&enemy, i)| (enemy, Position::new(i, 5))).collect();
+        let mut morale = Morale::default();
+        let time = Time::new(1, 0);
+
+        morale.update(time, &agent(10, 0.5), &Senses { allies: vec![(ally, Position::new(1, 0))], ..Default::default() });
+        assert_eq!(morale.value, 1.0);
+        assert!(morale.allies.contains(&ally));
+
+        morale.update(time, &agent(10, 0.5), &Senses { fallen: vec![ally], ..Default::default() });
+        assert_eq!(morale.value, 1.0 - ALLY_DEATH_SHOCK);
+
+        let before = morale.value;
+        morale.update(time, &agent(10, 0.5), &Senses { enemies: enemies, ..Default::default() });
+        assert!(morale.value < before);
+    }
+
+    #[test]
+    fn broken_creatures_rally_only_after_recovering() {
+        let mut morale = Morale { value: 0.2, broken: true, last_hp: Some(10), allies: BTreeSet::new() };
+        let time = Time::new(1, 0);
+
+        morale.update(time, &agent(10, 0.5), &Senses::default());
+        assert!(morale.broken);
+
+        for _ in 0..20 {
+            morale.update(time, &agent(10, 0.5), &Senses::default());
+        }
+        assert!(!morale.broken);
+    }
+}
@@ -0,0 +1,140 @@
+use specs::{Entities, Entity, WriteStorage};
+use crate::game::ecs::{Attributes, MovementTraits, Position};
+use crate::game::events::Time;
+use crate::game::level::Level;
+use crate::game::system::Collider;
+use super::actions::{Agent, AiActionType, AiActions};
+
+/// How far pack members call out what they've seen, and how far a leader's orders carry
+pub const PACK_RADIUS: u32 = 8;
+/// Turns an order stands before a follower goes back to its own devices
+pub const ORDER_TURNS: u32 = 5;
+/// Followers try to keep within this distance of their leader
+pub const FOLLOW_DISTANCE: u32 = 3;
+/// How much more a follower wants to do what it was told
+pub const ORDER_BONUS: f32 = 1.5;
+
+/// What a leader wants its followers to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Order {
+    Attack(Entity),
+    /// Keep close to the leader
+    Follow(Entity),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orders {
+    pub order: Order,
+    pub leader: Entity,
+    pub time: Time,
+}
+
+impl Orders {
+    pub fn is_current(&self, now: Time) -> bool {
+        self.time + ORDER_TURNS > now
+    }
+}
+
+/// Living members of the agent's faction within `PACK_RADIUS`
+pub fn pack_mates<'a>(
+    entity: Entity,
+    agent: &Agent,
+    entities: &Entities<'a>,
+    positions: &WriteStorage<'a, Position>,
+    attributes: &WriteStorage<'a, Attributes>,
+) -> Vec<(Entity, Position)> {
+    use specs::Join;
+
+    (&**entities, positions, attributes)
+        .join()
+        .filter(|(e, pos, attr)| {
+            *e != entity
+                && attr.alive
+                && attr.faction == agent.attributes.faction
+                && pos.distance(&agent.position) <= PACK_RADIUS
+        })
+        .map(|(e, pos, _)| (e, pos.clone()))
+        .collect()
+}
+
+/// Favour whatever the leader asked for
+pub fn obey(ai_actions: &mut AiActions, order: &Order) {
+    for action in ai_actions.actions.iter_mut() {
+        let ordered = match (order, &action.action_type) {
+            (Order::Attack(target), AiActionType::Attack(e)) | (Order::Attack(target), AiActionType::Chase(e, _)) => target == e,
+            (Order::Follow(leader), AiActionType::Follow(e, _)) => leader == e,
+            _ => false,
+        };
+
+        if ordered {
+            action.utility *= ORDER_BONUS;
+        }
+    }
+}
+
+/// True for tiles with at most two ways in or out
+pub fn is_corridor(level: &Level, traits: &MovementTraits, pos: &Position) -> bool {
+    let area = level.area();
+    let open = pos
+        .neighbours()
+        .iter()
+        .filter(|n| area.contains(n) && level.get(n.x, n.y).move_cost(traits).is_some())
+        .count();
+    open <= 2
+}
+
+/// Rather than queue up behind a pack mate that's already fighting in a corridor,
+/// wait where the corridor opens out for the target to come to us
+pub fn should_wait(level: &Level, traits: &MovementTraits, agent: &Position, target: &Position, mates: &[(Entity, Position)]) -> bool {
+    if !is_corridor(level, traits, target) || is_corridor(level, traits, agent) {
+        return false;
+    }
+
+    mates.iter().any(|(_, pos)| pos.distance(target) < agent.distance(target))
+}
+
+/// The free tile next to the target that's best to attack from: close by,
+/// and away from pack mates so the target ends up surrounded
+pub fn surround_slot(collider: &Collider, agent: &Position, target: &Position, mates: &[(Entity, Position)]) -> Option<Position> {
+    target
+        .neighbours()
+        .into_iter()
+        .filter(|slot| collider.get(slot).is_empty())
+        .min_by_key(|slot| {
+            let crowding = mates.iter().filter(|(_, pos)| pos.distance(slot) <= 1).count() as u32;
+            agent.distance(slot) + crowding * 2
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::{entities, Rect};
+    use crate::game::level::Tile;
+
+    #[test]
+    fn packs_wait_at_corridor_mouths() {
+        // a room with a corridor leading east out of it
+        let mut level = Level::filled(Rect::new(0, 0, 14, 7), &Tile::wall());
+        for x in 1..6 {
+            for y in 1..6 {
+                *level.get_mut(x, y) = Tile::floor();
+            }
+        }
+        for x in 6..13 {
+            *level.get_mut(x, 3) = Tile::floor();
+        }
+
+        let (_world, ids) = entities(1);
+        let orc = ids[0];
+        let traits = MovementTraits::default();
+        let target = Position::new(10, 3);
+
+        assert!(is_corridor(&level, &traits, &target));
+        assert!(!is_corridor(&level, &traits, &Position::new(5, 3)));
+
+        // alone it goes in, but not if a pack mate is already fighting in there
+        assert!(!should_wait(&level, &traits, &Position::new(5, 3), &target, &[]));
+        assert!(should_wait(&level, &traits, &Position::new(5, 3), &target, &[(orc, Position::new(9, 3))]));
+    }
+}
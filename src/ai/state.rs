@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+use specs::{Component, Entity, VecStorage};
+use crate::game::ecs::Position;
+use crate::game::events::Time;
+use super::actions::{Agent, Plan, ITEM_SEARCH_RADIUS};
+use super::morale::Morale;
+use super::pack::Orders;
+
+/// Sleepers wake up when an enemy gets this close
+pub const WAKE_DISTANCE: u32 = 4;
+/// Turns an alert creature stays on guard after losing sight of everything
+pub const ALERT_TURNS: u32 = 20;
+/// Turns a fleeing creature keeps running once out of sight
+pub const FLEE_TURNS: u32 = 10;
+/// Turns before a sighting is forgotten
+pub const MEMORY_TURNS: u32 = 100;
+
+/// How aware a creature is of what's going on around it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Awareness {
+    Asleep,
+    Wandering,
+    Alert,
+    Hunting,
+    Fleeing,
+}
+
+impl Default for Awareness {
+    fn default() -> Self {
+        Awareness::Wandering
+    }
+}
+
+/// Where and when an enemy was last seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sighting {
+    pub position: Position,
+    pub time: Time,
+}
+
+/// What a creature noticed this turn
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Senses {
+    pub enemies: Vec<(Entity, Position)>,
+    /// Friends in sight
+    pub allies: Vec<(Entity, Position)>,
+    /// Creatures weak enough for an evil agent to pick on
+    pub prey: Vec<Entity>,
+    /// Allies seen last turn that have died since
+    pub fallen: Vec<Entity>,
+    pub items: Vec<(Entity, Position)>,
+}
+
+/// An AI controlled creature's memory, kept from turn to turn
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct AiState {
+    pub awareness: Awareness,
+    /// When awareness last changed
+    pub since: Time,
+    pub plan: Option<Plan>,
+    /// Where the creature was when it first got a turn, for patrolling and going home
+    pub home: Option<Position>,
+    pub enemies: BTreeMap<Entity, Sighting>,
+    /// Potions seen lying about
+    pub items: BTreeMap<Entity, Position>,
+    pub morale: Morale,
+    /// The last order from a pack leader
+    pub orders: Option<Orders>,
+    /// Creatures this one will fight whatever its faction thinks of theirs
+    pub grudges: BTreeSet<Entity>,
+    /// Crimes to tell a guard about: the culprit and where they did it
+    pub reports: BTreeMap<Entity, Position>,
+    /// The last deed this creature has had the chance to notice
+    pub last_deed: u64,
+}
+
+impl Component for AiState {
+    type Storage = VecStorage<Self>;
+}
+
+impl AiState {
+    pub fn new(awareness: Awareness) -> Self {
+        AiState {
+            awareness: awareness,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.awareness == Awareness::Asleep
+    }
+
+    pub fn set_awareness(&mut self, awareness: Awareness, now: Time) {
+        if self.awareness != awareness {
+            debug!("[{:?}] awareness {:?} -> {:?}", now, self.awareness, awareness);
+            self.awareness = awareness;
+            self.since = now;
+        }
+    }
+
+    /// Update memory with what was seen this turn, then move to the next awareness state
+    pub fn observe(&mut self, now: Time, agent: &Agent, senses: &Senses) {
+        for (e, pos) in senses.enemies.iter() {
+            self.enemies.insert(*e, Sighting { position: pos.clone(), time: now });
+        }
+
+        // a sighting is no use once we're standing where the enemy was and it's gone
+        let seen: Vec<Entity> = senses.enemies.iter().map(|(e, _)| *e).collect();
+        self.enemies.retain(|e, sighting| {
+            seen.contains(e) || (sighting.position != agent.position && sighting.time + MEMORY_TURNS > now)
+        });
+
+        // items we should be able to see but can't have been taken
+        for (e, pos) in senses.items.iter() {
+            self.items.insert(*e, pos.clone());
+        }
+        let items: Vec<Entity> = senses.items.iter().map(|(e, _)| *e).collect();
+        self.items.retain(|e, pos| {
+            items.contains(e) || pos.distance(&agent.position) > ITEM_SEARCH_RADIUS
+        });
+        for e in agent.inventory.iter() {
+            self.items.remove(e);
+        }
+
+        self.morale.update(now, agent, senses);
+
+        let next = self.next_awareness(now, agent, senses);
+        self.set_awareness(next, now);
+    }
+
+    /// Learn about enemies from a pack mate. Sleepers don't hear, the broken don't care,
+    /// everyone else goes looking.
+    pub fn hear(&mut self, now: Time, agent: &Agent, sightings: &[(Entity, Sighting)]) {
+        if self.is_asleep() {
+            return;
+        }
+
+        let mut news = false;
+        for (e, sighting) in sightings.iter() {
+            let newer = self.enemies.get(e).map(|known| known.time < sighting.time).unwrap_or(true);
+            if newer {
+                self.enemies.insert(*e, sighting.clone());
+                news = true;
+            }
+        }
+
+        if news && !self.morale.broken {
+            match self.awareness {
+                Awareness::Wandering | Awareness::Alert if agent.flags.aggressive => self.set_awareness(Awareness::Hunting, now),
+                Awareness::Wandering => self.set_awareness(Awareness::Alert, now),
+                _ => {},
+            }
+        }
+    }
+
+    /// The order from the leader, if it still stands
+    pub fn current_orders(&self, now: Time) -> Option<&Orders> {
+        self.orders.as_ref().filter(|orders| orders.is_current(now))
+    }
+
+    fn next_awareness(&self, now: Time, agent: &Agent, senses: &Senses) -> Awareness {
+        let in_sight = !senses.enemies.is_empty();
+        let hurt = agent.attributes.hp < agent.attributes.max_hp;
+        let hunter = if agent.flags.aggressive { Awareness::Hunting } else { Awareness::Alert };
+
+        if self.morale.broken && in_sight {
+            return Awareness::Fleeing;
+        }
+
+        match self.awareness {
+            Awareness::Asleep => {
+                let near = senses.enemies.iter().any(|(_, pos)| pos.distance(&agent.position) <= WAKE_DISTANCE);
+                if near || hurt {
+                    Awareness::Alert
+                } else {
+                    Awareness::Asleep
+                }
+            },
+            Awareness::Wandering => {
+                if in_sight {
+                    hunter
+                } else if hurt {
+                    Awareness::Alert
+                } else {
+                    Awareness::Wandering
+                }
+            },
+            Awareness::Alert => {
+                if in_sight {
+                    hunter
+                } else if self.since + ALERT_TURNS <= now {
+                    Awareness::Wandering
+                } else {
+                    Awareness::Alert
+                }
+            },
+            Awareness::Hunting => {
+                if in_sight || !self.enemies.is_empty() {
+                    Awareness::Hunting
+                } else {
+                    Awareness::Alert
+                }
+            },
+            Awareness::Fleeing => {
+                if !in_sight && self.since + FLEE_TURNS <= now {
+                    Awareness::Alert
+                } else {
+                    Awareness::Fleeing
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::{entities, AiFlags, Attributes};
+
+    fn agent_at(x: i32, y: i32) -> Agent {
+        let attributes = Attributes { max_hp: 10, hp: 10, ..Default::default() };
+        Agent::new(Position::new(x, y), attributes, AiFlags::default(), Position::new(0, 0), vec![])
+    }
+
+    #[test]
+    fn sleepers_wake_when_enemies_come_close() {
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+        let mut state = AiState::new(Awareness::Asleep);
+        let agent = agent_at(0, 0);
+
+        state.observe(Time::new(1, 0), &agent, &Senses { enemies: vec![(player, Position::new(8, 0))], ..Default::default() });
+        assert_eq!(state.awareness, Awareness::Asleep);
+
+        state.observe(Time::new(2, 0), &agent, &Senses { enemies: vec![(player, Position::new(3, 0))], ..Default::default() });
+        assert_eq!(state.awareness, Awareness::Alert);
+
+        state.observe(Time::new(3, 0), &agent, &Senses { enemies: vec![(player, Position::new(3, 0))], ..Default::default() });
+        assert_eq!(state.awareness, Awareness::Hunting);
+    }
+
+    #[test]
+    fn hunters_chase_to_the_last_seen_position() {
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+        let mut state = AiState::new(Awareness::Wandering);
+
+        state.observe(Time::new(1, 0), &agent_at(0, 0), &Senses { enemies: vec![(player, Position::new(5, 5))], ..Default::default() });
+        assert_eq!(state.awareness, Awareness::Hunting);
+
+        // out of sight, but still remembered
+        state.observe(Time::new(2, 0), &agent_at(1, 1), &Senses::default());
+        assert_eq!(state.awareness, Awareness::Hunting);
+        assert_eq!(state.enemies[&player].position, Position::new(5, 5));
+
+        // got there and nobody's home
+        state.observe(Time::new(6, 0), &agent_at(5, 5), &Senses::default());
+        assert!(state.enemies.is_empty());
+        assert_eq!(state.awareness, Awareness::Alert);
+
+        state.observe(Time::new(6 + ALERT_TURNS, 0), &agent_at(5, 5), &Senses::default());
+        assert_eq!(state.awareness, Awareness::Wandering);
+    }
+
+    #[test]
+    fn it_forgets_items_that_are_gone() {
+        let (_world, ids) = entities(1);
+        let potion = ids[0];
+        let mut state = AiState::default();
+
+        state.observe(Time::new(1, 0), &agent_at(0, 0), &Senses { items: vec![(potion, Position::new(2, 0))], ..Default::default() });
+        state.observe(Time::new(2, 0), &agent_at(30, 0), &Senses::default());
+        assert!(state.items.contains_key(&potion));
+
+        state.observe(Time::new(3, 0), &agent_at(1, 0), &Senses::default());
+        assert!(!state.items.contains_key(&potion));
+    }
+
+    #[test]
+    fn broken_creatures_flee_until_they_rally() {
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+        let mut state = AiState::new(Awareness::Hunting);
+        let enemies = vec![(player, Position::new(2, 0))];
+
+        state.morale.value = 0.1;
+        state.observe(Time::new(1, 0), &agent_at(0, 0), &Senses { enemies: enemies.clone(), ..Default::default() });
+        assert!(state.morale.broken);
+        assert_eq!(state.awareness, Awareness::Fleeing);
+
+        state.morale.value = 1.0;
+        state.observe(Time::new(2, 0), &agent_at(0, 0), &Senses { enemies: enemies, ..Default::default() });
+        assert!(!state.morale.broken);
+    }
+
+    #[test]
+    fn pack_mates_hear_about_enemies() {
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+        let sighting = Sighting { position: Position::new(5, 5), time: Time::new(3, 0) };
+        let agent = agent_at(0, 0);
+
+        let mut awake = AiState::new(Awareness::Wandering);
+        awake.hear(Time::new(3, 0), &agent, &[(player, sighting.clone())]);
+        assert_eq!(awake.awareness, Awareness::Hunting);
+        assert_eq!(awake.enemies[&player], sighting);
+
+        let mut asleep = AiState::new(Awareness::Asleep);
+        asleep.hear(Time::new(3, 0), &agent, &[(player, sighting)]);
+        assert_eq!(asleep.awareness, Awareness::Asleep);
+        assert!(asleep.enemies.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::data_file;
+use serde::Deserialize;
+use super::actions::{AiAction, Agent};
+
+pub const UTILITY_FILE: &str = "static/utility.ron";
+
+const BUILTIN_UTILITY: &str = include_str!("../../static/utility.ron");
+
+/// Distances are divided by this to bring them into 0..1
+pub const DISTANCE_SCALE: f32 = 20.0;
+
+/// Maps an input in 0..1 to a score in 0..1
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum Curve {
+    /// slope * x + intercept
+    Linear { slope: f32, intercept: f32 },
+    /// slope * x^exponent + intercept
+    Quadratic { slope: f32, exponent: f32, intercept: f32 },
+    /// 1 / (1 + e^(-steepness * (x - midpoint))). Negative steepness flips the curve.
+    Logistic { steepness: f32, midpoint: f32 },
+}
+
+impl Curve {
+    pub fn eval(&self, x: f32) -> f32 {
+        let x = clamp(x);
+        let y = match *self {
+            Curve::Linear { slope, intercept } => slope * x + intercept,
+            Curve::Quadratic { slope, exponent, intercept } => slope * x.powf(exponent) + intercept,
+            Curve::Logistic { steepness, midpoint } => 1.0 / (1.0 + (-steepness * (x - midpoint)).exp()),
+        };
+        clamp(y)
+    }
+}
+
+fn clamp(x: f32) -> f32 {
+    if x.is_nan() {
+        0.0
+    } else {
+        x.max(0.0).min(1.0)
+    }
+}
+
+/// What a consideration looks at, each normalised to 0..1
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum Input {
+    /// Distance to the action's target
+    Distance,
+    /// Distance from home
+    HomeDistance,
+    HpRatio,
+    Thirst,
+    Calmness,
+    /// The agent's opinion of the target, from hostile (0) to friendly (1)
+    Opinion,
+    /// 1 if the target is a visible threat
+    Threat,
+    /// How steady the agent's nerve is, from broken (0) to steady (1)
+    Morale,
+    /// 1 once morale has broken, until the agent rallies
+    Broken,
+    /// From evil (0) to good (1)
+    Goodness,
+    /// From chaotic (0) to lawful (1)
+    Lawfulness,
+}
+
+/// What an action is about, if anything
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TargetInfo {
+    pub distance: u32,
+    pub opinion: f32,
+    pub threat: bool,
+}
+
+impl Input {
+    pub fn value(self, agent: &Agent, target: Option<&TargetInfo>) -> f32 {
+        match self {
+            Input::Distance => target.map(|t| t.distance as f32 / DISTANCE_SCALE).unwrap_or(0.0),
+            Input::HomeDistance => agent.position.distance(&agent.home) as f32 / DISTANCE_SCALE,
+            Input::HpRatio => agent.hp_ratio(),
+            Input::Thirst => agent.attributes.thirst,
+            Input::Calmness => agent.attributes.calmness,
+            Input::Opinion => target.map(|t| (t.opinion + 1.0) / 2.0).unwrap_or(0.5),
+            Input::Threat => target.map(|t| if t.threat { 1.0 } else { 0.0 }).unwrap_or(0.0),
+            Input::Morale => agent.morale,
+            Input::Broken => if agent.broken { 1.0 } else { 0.0 },
+            Input::Goodness => (agent.attributes.goodness + 1.0) / 2.0,
+            Input::Lawfulness => (agent.attributes.lawfulness + 1.0) / 2.0,
+        }
+    }
+}
+
+/// One thing an agent weighs up before acting
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Consideration {
+    pub input: Input,
+    pub curve: Curve,
+}
+
+/// The considerations for one kind of action
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Scorer {
+    /// Multiplied into the final score to favour one action over another
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    pub considerations: Vec<Consideration>,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+impl Scorer {
+    /// Multiply the score of every consideration together. Any consideration scoring zero vetoes the action.
+    pub fn score(&self, agent: &Agent, target: Option<&TargetInfo>) -> f32 {
+        self.considerations
+            .iter()
+            .map(|c| c.curve.eval(c.input.value(agent, target)))
+            .product::<f32>() * self.weight
+    }
+}
+
+/// Scorers for each kind of action, by `AiActionType::kind`
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct UtilityConfig {
+    pub scorers: BTreeMap<String, Scorer>,
+}
+
+impl UtilityConfig {
+    pub fn parse(text: &str) -> Result<UtilityConfig, String> {
+        ron::de::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// Load curves from a file, falling back to the built in ones
+    pub fn load<P>(path: P) -> UtilityConfig where P: AsRef<Path> {
+        data_file::load_or_builtin(path, BUILTIN_UTILITY, UtilityConfig::parse, "utility curves")
+    }
+
+    pub fn builtin() -> UtilityConfig {
+        data_file::parse_builtin(BUILTIN_UTILITY, UtilityConfig::parse, "utility curves")
+    }
+
+    /// Score an action. Actions without a scorer keep the utility they were made with.
+    pub fn score(&self, agent: &Agent, action: &AiAction) -> f32 {
+        match self.scorers.get(action.action_type.kind()) {
+            Some(scorer) => scorer.score(agent, action.target.as_ref()),
+            None => action.utility,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::{entities, AiFlags, Attributes, Position};
+
+    #[test]
+    fn curves_stay_in_range() {
+        let linear = Curve::Linear { slope: -1.0, intercept: 1.0 };
+        let quadratic = Curve::Quadratic { slope: 1.0, exponent: 2.0, intercept: 0.0 };
+        let logistic = Curve::Logistic { steepness: 10.0, midpoint: 0.5 };
+
+        assert_eq!(linear.eval(0.25), 0.75);
+        assert_eq!(linear.eval(2.0), 0.0);
+        assert_eq!(quadratic.eval(0.5), 0.25);
+        assert_eq!(logistic.eval(0.5), 0.5);
+        assert!(logistic.eval(0.0) < 0.01);
+        assert!(logistic.eval(1.0) > 0.99);
+    }
+
+    #[test]
+    fn hurt_agents_would_rather_flee_than_fight() {
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+        let config = UtilityConfig::builtin();
+        let target = TargetInfo { distance: 1, opinion: -1.0, threat: true };
+
+        let mut agent = Agent::new(Position::new(0, 0), Attributes { max_hp: 10, hp: 10, calmness: 0.3, ..Default::default() }, AiFlags::default(), Position::new(0, 0), vec![]);
+        let attack = AiAction::attack(&agent, player, 1.0).about(target.clone());
+        let flee = AiAction::flee(&agent, player, 1.0).about(target.clone());
+        assert!(config.score(&agent, &attack) > config.score(&agent, &flee));
+
+        // taking the hits shook it
+        agent.attributes.hp = 2;
+        agent.morale = 0.2;
+        assert!(config.score(&agent, &flee) > config.score(&agent, &attack));
+    }
+
+    #[test]
+    fn broken_agents_flee_even_when_unhurt() {
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+        let config = UtilityConfig::builtin();
+        let target = TargetInfo { distance: 1, opinion: -1.0, threat: true };
+
+        let mut agent = Agent::new(Position::new(0, 0), Attributes { max_hp: 10, hp: 10, calmness: 0.5, ..Default::default() }, AiFlags::default(), Position::new(0, 0), vec![]);
+        agent.morale = 0.5;
+        agent.broken = true;
+        let attack = AiAction::attack(&agent, player, 1.0).about(target.clone());
+        let flee = AiAction::flee(&agent, player, 1.0).about(target.clone());
+        assert_eq!(config.score(&agent, &attack), 0.0);
+        assert!(config.score(&agent, &flee) > 0.0);
+
+        // rallied at the same morale, it fights on
+        agent.broken = false;
+        assert!(config.score(&agent, &attack) > config.score(&agent, &flee));
+    }
+}
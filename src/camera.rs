@@ -0,0 +1,169 @@
+use crate::game::ecs::{Position, Rect};
+use crate::tween::{Tweenable, Tweener};
+
+/// How far the focus can stray from the middle of the view before the camera moves, in tiles
+pub const DEAD_ZONE: (i32, i32) = (4, 3);
+/// Fraction of the remaining distance the camera covers each frame when scrolling smoothly
+pub const SCROLL_FACTOR: f32 = 0.25;
+/// The smallest step taken when scrolling smoothly, in tiles
+pub const SCROLL_MIN_STEP: f32 = 0.1;
+
+/// Which part of the level is on screen. Everything is measured in tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    /// Top left of the view. Fractional while scrolling smoothly.
+    pub x: f32,
+    pub y: f32,
+    /// Size of the view
+    pub width: i32,
+    pub height: i32,
+    /// Half the size of the box around the middle of the view the focus can move about in
+    /// without scrolling. (0, 0) keeps the focus centred.
+    pub dead_zone: (i32, i32),
+    /// Ease towards the focus instead of jumping
+    pub smoothing: Option<(f32, f32)>,
+    /// False until the camera has been pointed at something, so the first frame doesn't scroll in
+    placed: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new(0, 0)
+    }
+}
+
+impl Camera {
+    pub fn new(width: i32, height: i32) -> Self {
+        Camera {
+            x: 0.0,
+            y: 0.0,
+            width: width,
+            height: height,
+            dead_zone: (0, 0),
+            smoothing: None,
+            placed: false,
+        }
+    }
+
+    pub fn with_dead_zone(mut self, width: i32, height: i32) -> Self {
+        self.dead_zone = (width, height);
+        self
+    }
+
+    pub fn with_smoothing(mut self, factor_step: f32, min_step: f32) -> Self {
+        self.smoothing = Some((factor_step, min_step));
+        self
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// The top left tile of the view
+    pub fn offset(&self) -> Position {
+        Position::new(self.x.round() as i32, self.y.round() as i32)
+    }
+
+    /// Where the view should be to keep the focus inside the dead zone (or centred, the first
+    /// time round) without showing anything past the edges of the level.
+    /// Levels smaller than the view are centred.
+    pub fn target(&self, focus: &Position, level_area: &Rect) -> (f32, f32) {
+        let (dead_x, dead_y) = if self.placed { self.dead_zone } else { (0, 0) };
+        let x = follow_axis(self.x, focus.x, self.width, dead_x, level_area.left(), level_area.width);
+        let y = follow_axis(self.y, focus.y, self.height, dead_y, level_area.top(), level_area.height);
+        (x, y)
+    }
+
+    /// Move towards the focus, easing if smoothing is on. Call once per frame.
+    pub fn follow(&mut self, focus: &Position, level_area: &Rect) {
+        let (x, y) = self.target(focus, level_area);
+
+        match self.smoothing {
+            Some((factor_step, min_step)) if self.placed => {
+                self.x.tween(&Tweener::Decay { to: x, factor_step: factor_step, min_step: min_step });
+                self.y.tween(&Tweener::Decay { to: y, factor_step: factor_step, min_step: min_step });
+            },
+            _ => {
+                self.x = x;
+                self.y = y;
+            },
+        }
+        self.placed = true;
+    }
+}
+
+/// One axis of `Camera::target`
+fn follow_axis(current: f32, focus: i32, view: i32, dead_zone: i32, level_start: i32, level_size: i32) -> f32 {
+    if level_size <= view {
+        return (level_start - (view - level_size) / 2) as f32;
+    }
+
+    let middle = current.round() as i32 + view / 2;
+    let start = if focus > middle + dead_zone {
+        focus - dead_zone - view / 2
+    } else if focus < middle - dead_zone {
+        focus + dead_zone - view / 2
+    } else {
+        current.round() as i32
+    };
+
+    start.max(level_start).min(level_start + level_size - view) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_centres_on_the_focus_within_the_level() {
+        let level = Rect::new_sized(80, 50);
+        let mut camera = Camera::new(20, 10);
+
+        camera.follow(&Position::new(40, 25), &level);
+        assert_eq!(camera.offset(), Position::new(30, 20));
+
+        camera.follow(&Position::new(2, 2), &level);
+        assert_eq!(camera.offset(), Position::new(0, 0));
+
+        camera.follow(&Position::new(79, 49), &level);
+        assert_eq!(camera.offset(), Position::new(60, 40));
+
+        // a level smaller than the view sits in the middle of it
+        camera.follow(&Position::new(5, 5), &Rect::new_sized(10, 10));
+        assert_eq!(camera.offset(), Position::new(-5, 0));
+    }
+
+    #[test]
+    fn it_only_scrolls_once_the_focus_leaves_the_dead_zone() {
+        let level = Rect::new_sized(80, 50);
+        let mut camera = Camera::new(20, 10).with_dead_zone(3, 2);
+
+        camera.follow(&Position::new(40, 25), &level);
+        assert_eq!(camera.offset(), Position::new(30, 20));
+
+        camera.follow(&Position::new(43, 27), &level);
+        assert_eq!(camera.offset(), Position::new(30, 20));
+
+        camera.follow(&Position::new(45, 27), &level);
+        assert_eq!(camera.offset(), Position::new(32, 20));
+    }
+
+    #[test]
+    fn smooth_scrolling_eases_towards_the_focus() {
+        let level = Rect::new_sized(80, 50);
+        let mut camera = Camera::new(20, 10).with_smoothing(SCROLL_FACTOR, SCROLL_MIN_STEP);
+
+        camera.follow(&Position::new(20, 20), &level);
+        assert_eq!(camera.offset(), Position::new(10, 15));
+
+        camera.follow(&Position::new(40, 20), &level);
+        let first_step = camera.x - 10.0;
+        assert!(first_step > 0.0 && first_step < 20.0);
+
+        for _ in 0..100 {
+            camera.follow(&Position::new(40, 20), &level);
+        }
+        assert_eq!(camera.offset(), Position::new(30, 15));
+    }
+}
@@ -1,63 +1,227 @@
-use piston::input::keyboard::ModifierKey;
-
-pub const MAP_EN_GB: &[char] = &[
-    /* 0x0  */ '\0', '\0',  '1',  '2',  '3',  '4',  '5',  '6',  '7',  '8',  '9',  '0',  '-',  '=', '\x08', '\0',
-    /* 0x10 */  'q',  'w',  'e',  'r',  't',  'y',  'u',  'i',  'o',  'p',  '[',  ']', '\n', '\0',  'a',  's',
-    /* 0x20 */  'd',  'f',  'g',  'h',  'j',  'k',  'l',  ';', '\'',  '`', '\0',  '#',  'z',  'x',  'c',  'v',
-    /* 0x30 */  'b',  'n',  'm',  ',',  '.',  '/', '\0', '\0', '\0',  ' ', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x40 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x50 */ '\0', '\0', '\0', '\0', '\0', '\0', '\\', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x60 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x70 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x80 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x90 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xa0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xb0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xc0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xd0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xe0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xf0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+use quicksilver::input::Key;
+use crate::input::Modifiers;
+
+// Characters normally come from what the window says was typed, which already follows the
+// layout set in the OS. These tables are only used when it says nothing, and take keys to be
+// named after where they sit on a US keyboard.
+
+/// What each key types on a UK keyboard: (key, unshifted, shifted)
+pub const MAP_EN_GB: &[(Key, char, char)] = &[
+    (Key::Key1, '1', '!'), (Key::Key2, '2', '"'), (Key::Key3, '3', '£'), (Key::Key4, '4', '$'),
+    (Key::Key5, '5', '%'), (Key::Key6, '6', '^'), (Key::Key7, '7', '&'), (Key::Key8, '8', '*'),
+    (Key::Key9, '9', '('), (Key::Key0, '0', ')'), (Key::Minus, '-', '_'), (Key::Equals, '=', '+'),
+    (Key::Q, 'q', 'Q'), (Key::W, 'w', 'W'), (Key::E, 'e', 'E'), (Key::R, 'r', 'R'),
+    (Key::T, 't', 'T'), (Key::Y, 'y', 'Y'), (Key::U, 'u', 'U'), (Key::I, 'i', 'I'),
+    (Key::O, 'o', 'O'), (Key::P, 'p', 'P'), (Key::LBracket, '[', '{'), (Key::RBracket, ']', '}'),
+    (Key::A, 'a', 'A'), (Key::S, 's', 'S'), (Key::D, 'd', 'D'), (Key::F, 'f', 'F'),
+    (Key::G, 'g', 'G'), (Key::H, 'h', 'H'), (Key::J, 'j', 'J'), (Key::K, 'k', 'K'),
+    (Key::L, 'l', 'L'), (Key::Semicolon, ';', ':'), (Key::Apostrophe, '\'', '@'), (Key::Grave, '`', '¬'),
+    (Key::Backslash, '#', '~'), (Key::Z, 'z', 'Z'), (Key::X, 'x', 'X'), (Key::C, 'c', 'C'),
+    (Key::V, 'v', 'V'), (Key::B, 'b', 'B'), (Key::N, 'n', 'N'), (Key::M, 'm', 'M'),
+    (Key::Comma, ',', '<'), (Key::Period, '.', '>'), (Key::Slash, '/', '?'), (Key::Space, ' ', ' '),
+];
+
+/// What each key types on a US keyboard
+pub const MAP_EN_US: &[(Key, char, char)] = &[
+    (Key::Key1, '1', '!'), (Key::Key2, '2', '@'), (Key::Key3, '3', '#'), (Key::Key4, '4', '$'),
+    (Key::Key5, '5', '%'), (Key::Key6, '6', '^'), (Key::Key7, '7', '&'), (Key::Key8, '8', '*'),
+    (Key::Key9, '9', '('), (Key::Key0, '0', ')'), (Key::Minus, '-', '_'), (Key::Equals, '=', '+'),
+    (Key::Q, 'q', 'Q'), (Key::W, 'w', 'W'), (Key::E, 'e', 'E'), (Key::R, 'r', 'R'),
+    (Key::T, 't', 'T'), (Key::Y, 'y', 'Y'), (Key::U, 'u', 'U'), (Key::I, 'i', 'I'),
+    (Key::O, 'o', 'O'), (Key::P, 'p', 'P'), (Key::LBracket, '[', '{'), (Key::RBracket, ']', '}'),
+    (Key::A, 'a', 'A'), (Key::S, 's', 'S'), (Key::D, 'd', 'D'), (Key::F, 'f', 'F'),
+    (Key::G, 'g', 'G'), (Key::H, 'h', 'H'), (Key::J, 'j', 'J'), (Key::K, 'k', 'K'),
+    (Key::L, 'l', 'L'), (Key::Semicolon, ';', ':'), (Key::Apostrophe, '\'', '"'),
+    (Key::Grave, '`', '~'), (Key::Backslash, '\\', '|'), (Key::Z, 'z', 'Z'), (Key::X, 'x', 'X'),
+    (Key::C, 'c', 'C'), (Key::V, 'v', 'V'), (Key::B, 'b', 'B'), (Key::N, 'n', 'N'),
+    (Key::M, 'm', 'M'), (Key::Comma, ',', '<'), (Key::Period, '.', '>'), (Key::Slash, '/', '?'),
+    (Key::Space, ' ', ' '),
+];
+
+/// What each key types on a German (QWERTZ) keyboard. Dead keys give their accent.
+pub const MAP_DE: &[(Key, char, char)] = &[
+    (Key::Key1, '1', '!'), (Key::Key2, '2', '"'), (Key::Key3, '3', '§'), (Key::Key4, '4', '$'),
+    (Key::Key5, '5', '%'), (Key::Key6, '6', '&'), (Key::Key7, '7', '/'), (Key::Key8, '8', '('),
+    (Key::Key9, '9', ')'), (Key::Key0, '0', '='), (Key::Minus, 'ß', '?'), (Key::Equals, '´', '`'),
+    (Key::Q, 'q', 'Q'), (Key::W, 'w', 'W'), (Key::E, 'e', 'E'), (Key::R, 'r', 'R'),
+    (Key::T, 't', 'T'), (Key::Y, 'z', 'Z'), (Key::U, 'u', 'U'), (Key::I, 'i', 'I'),
+    (Key::O, 'o', 'O'), (Key::P, 'p', 'P'), (Key::LBracket, 'ü', 'Ü'), (Key::RBracket, '+', '*'),
+    (Key::A, 'a', 'A'), (Key::S, 's', 'S'), (Key::D, 'd', 'D'), (Key::F, 'f', 'F'),
+    (Key::G, 'g', 'G'), (Key::H, 'h', 'H'), (Key::J, 'j', 'J'), (Key::K, 'k', 'K'),
+    (Key::L, 'l', 'L'), (Key::Semicolon, 'ö', 'Ö'), (Key::Apostrophe, 'ä', 'Ä'),
+    (Key::Grave, '^', '°'), (Key::Backslash, '#', '\''), (Key::Z, 'y', 'Y'), (Key::X, 'x', 'X'),
+    (Key::C, 'c', 'C'), (Key::V, 'v', 'V'), (Key::B, 'b', 'B'), (Key::N, 'n', 'N'),
+    (Key::M, 'm', 'M'), (Key::Comma, ',', ';'), (Key::Period, '.', ':'), (Key::Slash, '-', '_'),
+    (Key::Space, ' ', ' '),
 ];
 
-pub const SHIFT_MAP_EN_GB: &[char] = &[
-    /* 0x0  */ '\0', '\0',  '!',  '"',  '£',  '$',  '%',  '^',  '&',  '*',  '(',  ')',  '_',  '+', '\x08', '\0',
-    /* 0x10 */  'Q',  'W',  'E',  'R',  'T',  'Y',  'U',  'I',  'O',  'P',  '{',  '}', '\n', '\0',  'A',  'S',
-    /* 0x20 */  'D',  'F',  'G',  'H',  'J',  'K',  'L',  ':',  '@',  '¬', '\0',  '~',  'Z',  'X',  'C',  'V',
-    /* 0x30 */  'B',  'N',  'M',  '<',  '>',  '?', '\0', '\0', '\0',  ' ', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x40 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x50 */ '\0', '\0', '\0', '\0', '\0', '\0',  '|', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x60 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x70 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x80 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0x90 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xa0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xb0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xc0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xd0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xe0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
-    /* 0xf0 */ '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+/// What each key types on a French (AZERTY) keyboard. Dead keys give their accent.
+pub const MAP_FR: &[(Key, char, char)] = &[
+    (Key::Key1, '&', '1'), (Key::Key2, 'é', '2'), (Key::Key3, '"', '3'), (Key::Key4, '\'', '4'),
+    (Key::Key5, '(', '5'), (Key::Key6, '-', '6'), (Key::Key7, 'è', '7'), (Key::Key8, '_', '8'),
+    (Key::Key9, 'ç', '9'), (Key::Key0, 'à', '0'), (Key::Minus, ')', '°'), (Key::Equals, '=', '+'),
+    (Key::Q, 'a', 'A'), (Key::W, 'z', 'Z'), (Key::E, 'e', 'E'), (Key::R, 'r', 'R'),
+    (Key::T, 't', 'T'), (Key::Y, 'y', 'Y'), (Key::U, 'u', 'U'), (Key::I, 'i', 'I'),
+    (Key::O, 'o', 'O'), (Key::P, 'p', 'P'), (Key::LBracket, '^', '¨'), (Key::RBracket, '$', '£'),
+    (Key::A, 'q', 'Q'), (Key::S, 's', 'S'), (Key::D, 'd', 'D'), (Key::F, 'f', 'F'),
+    (Key::G, 'g', 'G'), (Key::H, 'h', 'H'), (Key::J, 'j', 'J'), (Key::K, 'k', 'K'),
+    (Key::L, 'l', 'L'), (Key::Semicolon, 'm', 'M'), (Key::Apostrophe, 'ù', '%'),
+    (Key::Grave, '²', '³'), (Key::Backslash, '*', 'µ'), (Key::Z, 'w', 'W'), (Key::X, 'x', 'X'),
+    (Key::C, 'c', 'C'), (Key::V, 'v', 'V'), (Key::B, 'b', 'B'), (Key::N, 'n', 'N'),
+    (Key::M, ',', '?'), (Key::Comma, ';', '.'), (Key::Period, ':', '/'), (Key::Slash, '!', '§'),
+    (Key::Space, ' ', ' '),
 ];
 
-pub fn modify(_key_code: i32, scancode: Option<i32>, modifiers: ModifierKey) -> Option<char> {
-    let scancode = scancode.unwrap_or(0);
+/// What each key types with the US Dvorak layout
+pub const MAP_DVORAK: &[(Key, char, char)] = &[
+    (Key::Key1, '1', '!'), (Key::Key2, '2', '@'), (Key::Key3, '3', '#'), (Key::Key4, '4', '$'),
+    (Key::Key5, '5', '%'), (Key::Key6, '6', '^'), (Key::Key7, '7', '&'), (Key::Key8, '8', '*'),
+    (Key::Key9, '9', '('), (Key::Key0, '0', ')'), (Key::Minus, '[', '{'), (Key::Equals, ']', '}'),
+    (Key::Q, '\'', '"'), (Key::W, ',', '<'), (Key::E, '.', '>'), (Key::R, 'p', 'P'),
+    (Key::T, 'y', 'Y'), (Key::Y, 'f', 'F'), (Key::U, 'g', 'G'), (Key::I, 'c', 'C'),
+    (Key::O, 'r', 'R'), (Key::P, 'l', 'L'), (Key::LBracket, '/', '?'), (Key::RBracket, '=', '+'),
+    (Key::A, 'a', 'A'), (Key::S, 'o', 'O'), (Key::D, 'e', 'E'), (Key::F, 'u', 'U'),
+    (Key::G, 'i', 'I'), (Key::H, 'd', 'D'), (Key::J, 'h', 'H'), (Key::K, 't', 'T'),
+    (Key::L, 'n', 'N'), (Key::Semicolon, 's', 'S'), (Key::Apostrophe, '-', '_'),
+    (Key::Grave, '`', '~'), (Key::Backslash, '\\', '|'), (Key::Z, ';', ':'), (Key::X, 'q', 'Q'),
+    (Key::C, 'j', 'J'), (Key::V, 'k', 'K'), (Key::B, 'x', 'X'), (Key::N, 'b', 'B'),
+    (Key::M, 'm', 'M'), (Key::Comma, 'w', 'W'), (Key::Period, 'v', 'V'), (Key::Slash, 'z', 'Z'),
+    (Key::Space, ' ', ' '),
+];
 
-    if scancode < 0 || (scancode as usize) >= MAP_EN_GB.len() {
-        return None;
+/// The keyboard layouts we know what the keys type on
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Layout {
+    EnGb,
+    EnUs,
+    De,
+    Fr,
+    Dvorak,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::EnGb
     }
+}
 
-    let c = if modifiers.contains(ModifierKey::SHIFT) {
-        SHIFT_MAP_EN_GB[scancode as usize] as char
-    } else {
-        MAP_EN_GB[scancode as usize] as char
-    };
+impl Layout {
+    pub const ALL: &'static [Layout] = &[Layout::EnGb, Layout::EnUs, Layout::De, Layout::Fr, Layout::Dvorak];
+
+    /// The name used for the layout in the key bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::EnGb => "en-gb",
+            Layout::EnUs => "en-us",
+            Layout::De => "de",
+            Layout::Fr => "fr",
+            Layout::Dvorak => "dvorak",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        Layout::ALL.iter().cloned().find(|layout| layout.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn map(self) -> &'static [(Key, char, char)] {
+        match self {
+            Layout::EnGb => MAP_EN_GB,
+            Layout::EnUs => MAP_EN_US,
+            Layout::De => MAP_DE,
+            Layout::Fr => MAP_FR,
+            Layout::Dvorak => MAP_DVORAK,
+        }
+    }
+
+    /// The character a key types with the given modifiers held, if any
+    pub fn modify(self, key: Key, modifiers: Modifiers) -> Option<char> {
+        self.map().iter()
+            .find(|(k, _, _)| *k == key)
+            .map(|&(_, plain, shifted)| if modifiers.shift { shifted } else { plain })
+    }
+}
 
-    if c == '\0' {
-        None
-    } else {
-        Some(c)
+/// The character a key typed, from what the window reported. Ctrl turns letters into
+/// control codes, which are turned back into the letter.
+pub fn typed(c: char, modifiers: Modifiers) -> Option<char> {
+    match c as u32 {
+        code @ 1..=26 if modifiers.ctrl => std::char::from_u32('a' as u32 + code - 1),
+        _ if is_printable(c) => Some(c),
+        _ => None,
     }
 }
 
 pub fn is_printable(c: char) -> bool {
     !c.is_ascii_control() && !c.is_control()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shifted(layout: Layout, key: Key) -> Option<char> {
+        layout.modify(key, Modifiers::SHIFT)
+    }
+
+    #[test]
+    fn shifted_symbols_on_each_layout() {
+        assert_eq!(shifted(Layout::EnGb, Key::Key2), Some('"'));
+        assert_eq!(shifted(Layout::EnGb, Key::Key3), Some('£'));
+        assert_eq!(shifted(Layout::EnGb, Key::Apostrophe), Some('@'));
+        assert_eq!(shifted(Layout::EnGb, Key::Slash), Some('?'));
+
+        assert_eq!(shifted(Layout::EnUs, Key::Key2), Some('@'));
+        assert_eq!(shifted(Layout::EnUs, Key::Key3), Some('#'));
+        assert_eq!(shifted(Layout::EnUs, Key::Apostrophe), Some('"'));
+        assert_eq!(shifted(Layout::EnUs, Key::Backslash), Some('|'));
+
+        assert_eq!(shifted(Layout::De, Key::Key7), Some('/'));
+        assert_eq!(shifted(Layout::De, Key::Key0), Some('='));
+        assert_eq!(shifted(Layout::De, Key::Minus), Some('?'));
+        assert_eq!(shifted(Layout::De, Key::Comma), Some(';'));
+
+        assert_eq!(shifted(Layout::Fr, Key::Key1), Some('1'));
+        assert_eq!(shifted(Layout::Fr, Key::M), Some('?'));
+        assert_eq!(shifted(Layout::Fr, Key::Period), Some('/'));
+        assert_eq!(shifted(Layout::Fr, Key::Apostrophe), Some('%'));
+
+        assert_eq!(shifted(Layout::Dvorak, Key::Q), Some('"'));
+        assert_eq!(shifted(Layout::Dvorak, Key::Z), Some(':'));
+        assert_eq!(shifted(Layout::Dvorak, Key::LBracket), Some('?'));
+        assert_eq!(shifted(Layout::Dvorak, Key::Apostrophe), Some('_'));
+    }
+
+    #[test]
+    fn letters_move_with_the_layout() {
+        assert_eq!(Layout::EnGb.modify(Key::Y, Modifiers::NONE), Some('y'));
+        assert_eq!(Layout::De.modify(Key::Y, Modifiers::NONE), Some('z'));
+        assert_eq!(Layout::Fr.modify(Key::Q, Modifiers::SHIFT), Some('A'));
+        assert_eq!(Layout::Dvorak.modify(Key::J, Modifiers::NONE), Some('h'));
+        assert_eq!(Layout::EnUs.modify(Key::Left, Modifiers::NONE), None);
+    }
+
+    #[test]
+    fn every_layout_covers_the_same_keys_once() {
+        let keys = |layout: Layout| -> Vec<Key> { layout.map().iter().map(|&(key, _, _)| key).collect() };
+        let expected = keys(Layout::default());
+
+        for layout in Layout::ALL {
+            let map = keys(*layout);
+            for (i, key) in map.iter().enumerate() {
+                assert!(!map[i + 1..].contains(key), "{:?} maps {:?} twice", layout, key);
+            }
+            assert_eq!(map.len(), expected.len(), "{:?} covers different keys", layout);
+            assert!(expected.iter().all(|key| map.contains(key)), "{:?} covers different keys", layout);
+            assert_eq!(Layout::from_name(layout.name()), Some(*layout));
+        }
+    }
+
+    #[test]
+    fn control_codes_are_typed_as_letters() {
+        assert_eq!(typed('\u{18}', Modifiers::CTRL), Some('x'));
+        assert_eq!(typed('\u{1}', Modifiers::CTRL), Some('a'));
+        assert_eq!(typed('ö', Modifiers::NONE), Some('ö'));
+        assert_eq!(typed('\u{18}', Modifiers::NONE), None);
+        assert_eq!(typed('\u{0}', Modifiers::CTRL), None);
+    }
+}
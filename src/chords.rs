@@ -1,9 +1,9 @@
-use piston::input::keyboard::ModifierKey;
-use piston::input::Key;
+use quicksilver::input::Key;
+use crate::input::{self, Modifiers};
 use std::collections::BTreeMap;
 use std::iter::IntoIterator;
 use std::cmp;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 
 #[derive(Copy, Clone, Debug)]
 pub enum ChordResult<A> {
@@ -13,8 +13,82 @@ pub enum ChordResult<A> {
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ChordKey {
-    Key(Key, ModifierKey),
-    Character(char, ModifierKey),
+    Key(Key, Modifiers),
+    /// A printable character. Shift is already accounted for by the character itself,
+    /// so it isn't part of the modifiers.
+    Character(char, Modifiers),
+}
+
+impl ChordKey {
+    /// Parse one key of a chord, e.g. `g`, `?`, `Left`, `C-x` or `S-F1`.
+    /// Modifiers are `C-` (ctrl), `A-` (alt) and `S-` (shift). Shift can't go with a
+    /// character; write the shifted character instead.
+    pub fn parse(text: &str) -> Result<ChordKey, String> {
+        let mut modifiers = Modifiers::NONE;
+        let mut rest = text;
+        while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+            match rest.as_bytes()[0] {
+                b'C' => modifiers.ctrl = true,
+                b'A' => modifiers.alt = true,
+                b'S' => modifiers.shift = true,
+                _ => break,
+            }
+            rest = &rest[2..];
+        }
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => Err(format!("missing key in {:?}", text)),
+            (Some(_), None) if modifiers.shift => Err(format!("{:?}: write the shifted character instead of S-", text)),
+            (Some(c), None) => Ok(ChordKey::Character(c, modifiers)),
+            // space types a character, so it has to be bound as one
+            _ if rest.eq_ignore_ascii_case("Space") => Ok(ChordKey::Character(' ', modifiers)),
+            _ => input::key_from_name(rest)
+                .map(|key| ChordKey::Key(key, modifiers))
+                .ok_or_else(|| format!("unknown key {:?} in {:?}", rest, text)),
+        }
+    }
+}
+
+impl fmt::Display for ChordKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let modifiers = match self {
+            ChordKey::Key(_, modifiers) | ChordKey::Character(_, modifiers) => modifiers,
+        };
+        if modifiers.ctrl {
+            write!(f, "C-")?;
+        }
+        if modifiers.alt {
+            write!(f, "A-")?;
+        }
+        if modifiers.shift {
+            write!(f, "S-")?;
+        }
+
+        match self {
+            ChordKey::Character(' ', _) => write!(f, "Space"),
+            ChordKey::Character(c, _) => write!(f, "{}", c),
+            ChordKey::Key(key, _) => match input::key_name(*key) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "{:?}", key),
+            },
+        }
+    }
+}
+
+/// Parse a chord: keys separated by spaces, e.g. `C-x C-c`
+pub fn parse_chord(text: &str) -> Result<Vec<ChordKey>, String> {
+    let keys = text.split_whitespace().map(ChordKey::parse).collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        Err("empty chord".to_string())
+    } else {
+        Ok(keys)
+    }
+}
+
+/// Write a chord the way `parse_chord` reads it
+pub fn chord_to_string(keys: &[ChordKey]) -> String {
+    keys.iter().map(|key| key.to_string()).collect::<Vec<_>>().join(" ")
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -70,6 +144,18 @@ impl <A> ChordTree<A> where A: Debug {
     pub fn clear(&mut self) {
         self.keys.clear();
     }
+
+    /// Every chord in the tree under `prefix`, with its action
+    fn collect(&self, prefix: &mut Vec<ChordKey>, out: &mut Vec<(Vec<ChordKey>, A)>) where A: Copy {
+        if let Some(ref action) = self.action {
+            out.push((prefix.clone(), action.action));
+        }
+        for (key, sub_tree) in self.keys.iter() {
+            prefix.push(key.clone());
+            sub_tree.collect(prefix, out);
+            prefix.pop();
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -124,18 +210,6 @@ impl <A> Chords<A> where A: Copy + cmp::Ord + Debug {
         }
     }
 
-    pub fn current_status(&self) -> Option<ChordResult<A>> {
-        if let Some(chord) = self.chords.get(&self.keys) {
-            if let Some(ref action) = chord.action {
-                Some(ChordResult::Action(action.action))
-            } else {
-                Some(ChordResult::Building)
-            }
-        } else {
-            None
-        }
-    }
-
     pub fn get_keys<'a>(&'a self) -> &'a [ChordKey] {
         &self.keys
     }
@@ -147,4 +221,70 @@ impl <A> Chords<A> where A: Copy + cmp::Ord + Debug {
     pub fn clear_chord(&mut self) {
         self.keys.clear();
     }
+
+    /// True while a chord has been started but not finished
+    pub fn is_building(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// The keys that can come next in the chord being typed, each with the action it runs,
+    /// or None if it only leads on to longer chords
+    pub fn continuations(&self) -> Vec<(ChordKey, Option<A>)> {
+        self.chords.get(&self.keys)
+            .map(|chord| chord.keys.iter()
+                .map(|(key, sub_tree)| (key.clone(), sub_tree.action.as_ref().map(|a| a.action)))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Every chord and the action it runs
+    pub fn bindings(&self) -> Vec<(Vec<ChordKey>, A)> {
+        let mut bindings = vec![];
+        self.chords.collect(&mut vec![], &mut bindings);
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_parse_and_print() {
+        let keys = parse_chord("C-x C-c").unwrap();
+        assert_eq!(keys, vec![ChordKey::Character('x', Modifiers::CTRL), ChordKey::Character('c', Modifiers::CTRL)]);
+        assert_eq!(chord_to_string(&keys), "C-x C-c");
+
+        assert_eq!(ChordKey::parse("?"), Ok(ChordKey::Character('?', Modifiers::NONE)));
+        assert_eq!(ChordKey::parse("-"), Ok(ChordKey::Character('-', Modifiers::NONE)));
+        assert_eq!(ChordKey::parse("space"), Ok(ChordKey::Character(' ', Modifiers::NONE)));
+        assert_eq!(ChordKey::parse("S-Left"), Ok(ChordKey::Key(Key::Left, Modifiers::SHIFT)));
+        assert_eq!(ChordKey::parse("Numpad8").map(|k| k.to_string()), Ok("Numpad8".to_string()));
+
+        assert!(ChordKey::parse("S-a").is_err());
+        assert!(ChordKey::parse("Sideways").is_err());
+        assert!(parse_chord("  ").is_err());
+    }
+
+    #[test]
+    fn it_lists_what_can_finish_a_chord() {
+        let mut chords = Chords::new();
+        let keys = |text| parse_chord(text).unwrap();
+        chords.register(&keys("C-x C-c"), 1, "quit");
+        chords.register(&keys("C-x C-s"), 2, "save");
+        chords.register(&keys("C-x r t"), 3, "rectangle");
+        chords.register(&keys("g"), 4, "get");
+
+        assert!(!chords.is_building());
+        assert_eq!(chords.bindings().len(), 4);
+
+        chords.perform(ChordKey::Character('x', Modifiers::CTRL));
+        assert!(chords.is_building());
+        assert_eq!(chords.continuations(), vec![
+            (ChordKey::Character('c', Modifiers::CTRL), Some(1)),
+            (ChordKey::Character('r', Modifiers::NONE), None),
+            (ChordKey::Character('s', Modifiers::CTRL), Some(2)),
+        ]);
+        assert_eq!(chords.get_action_name(2).map(|s| s.as_str()), Some("save"));
+    }
 }
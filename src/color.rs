@@ -1,8 +1,9 @@
 use quicksilver::graphics::Color as QsColor;
 use tint::Color as TintColor;
+use serde::{Deserialize, Serialize};
 use crate::tween::{Tweenable, Tweener};
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Color {
     hue: Hue,
     value: u8,
@@ -25,7 +26,7 @@ impl Color {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Hue {
     Red = 0,
     Orange = 1,
@@ -1,32 +1,248 @@
-use crate::chords::{Chords, ChordKey, ChordResult};
-use crate::input::{InputEventKey, InputEventType};
-use crate::charmap;
-use piston::input::Key;
-use piston::input::keyboard::ModifierKey;
-
-pub const NEW_LINE: &'static str = "nl";
-pub const BACKSPACE: &'static str = "bs";
-pub const LEFT: &'static str = "left";
-pub const RIGHT: &'static str = "right";
-pub const UP: &'static str = "up";
-pub const DOWN: &'static str = "down";
-pub const QUIT: &'static str = "quit";
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use crate::data_file;
+use quicksilver::input::Key;
+use serde::Deserialize;
+use crate::chords::{self, Chords, ChordKey, ChordResult};
+use crate::input::{InputEventKey, InputEventType, KeyRepeat, Modifiers};
+use crate::charmap::{self, Layout};
+
+pub const KEYS_FILE: &str = "static/keys.ron";
+
+const BUILTIN_KEYS: &str = include_str!("../static/keys.ron");
+
+/// Which set of key bindings is in use. Each scene picks one, so the scene stack doubles
+/// as the mode stack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mode {
+    Menu,
+    Play,
+    Look,
+    Targeting,
+    Inventory,
+    Help,
+    GameOver,
+}
+
+impl Mode {
+    pub const ALL: &'static [Mode] = &[
+        Mode::Menu, Mode::Play, Mode::Look, Mode::Targeting, Mode::Inventory, Mode::Help, Mode::GameOver,
+    ];
+
+    /// The name of the mode's section in the key bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Menu => "menu",
+            Mode::Play => "play",
+            Mode::Look => "look",
+            Mode::Targeting => "targeting",
+            Mode::Inventory => "inventory",
+            Mode::Help => "help",
+            Mode::GameOver => "game-over",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Mode> {
+        Mode::ALL.iter().cloned().find(|mode| mode.name() == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
 pub enum Command {
-    NewLine,
-    Backspace,
-    Left,
-    Right,
-    Up,
-    Down,
+    /// Move or attack when playing, move the cursor when looking, move the selection in menus
+    Move(i32, i32),
+    Look,
+    PickUp,
+    Inventory,
+    Help,
+    Minimap,
+    DebugAi,
+    /// Companion orders
+    Follow,
+    Stay,
+    Attack,
+    Confirm,
+    Cancel,
     Quit,
-    Key(char),
+}
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Move(-1, 0) => "left",
+            Command::Move(1, 0) => "right",
+            Command::Move(0, -1) => "up",
+            Command::Move(0, 1) => "down",
+            Command::Move(-1, -1) => "up-left",
+            Command::Move(1, -1) => "up-right",
+            Command::Move(-1, 1) => "down-left",
+            Command::Move(1, 1) => "down-right",
+            Command::Move(_, _) => "move",
+            Command::Look => "look",
+            Command::PickUp => "pick-up",
+            Command::Inventory => "inventory",
+            Command::Help => "help",
+            Command::Minimap => "minimap",
+            Command::DebugAi => "debug-ai",
+            Command::Follow => "follow",
+            Command::Stay => "stay",
+            Command::Attack => "attack",
+            Command::Confirm => "confirm",
+            Command::Cancel => "cancel",
+            Command::Quit => "quit",
+        }
+    }
+
+    /// Whether holding down the keys for the command runs it over and over
+    pub fn repeats(self) -> bool {
+        match self {
+            Command::Move(_, _) => true,
+            _ => false,
+        }
+    }
+
+    /// What the command does in a mode, for the help screen
+    pub fn description(self, mode: Mode) -> String {
+        match self {
+            Command::Move(_, _) => match mode {
+                Mode::Play => format!("move {}, or attack what's in the way", self.name()),
+                Mode::Look | Mode::Targeting => format!("move the cursor {}", self.name()),
+                Mode::Menu | Mode::Inventory => format!("move the selection {}", self.name()),
+                Mode::Help | Mode::GameOver => format!("move {}", self.name()),
+            },
+            Command::Look => "look around".to_string(),
+            Command::PickUp => "pick up an item".to_string(),
+            Command::Inventory => "inventory".to_string(),
+            Command::Help => "this help".to_string(),
+            Command::Minimap => "switch between the small and the full map".to_string(),
+            Command::DebugAi => "show what the monster under the cursor is thinking".to_string(),
+            Command::Follow => "companions follow you".to_string(),
+            Command::Stay => "companions stay put".to_string(),
+            Command::Attack => "pick a target for your companions, again to attack it".to_string(),
+            Command::Confirm => "confirm".to_string(),
+            Command::Cancel => "go back, or open the menu".to_string(),
+            Command::Quit => "quit".to_string(),
+        }
+    }
+}
+
+/// Keys, e.g. `"C-x C-c"`, and the command they run
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Binding {
+    pub keys: String,
+    pub command: Command,
+}
+
+/// The bindings for each mode, keyed by mode name
+pub type ModeBindings = BTreeMap<String, Vec<Binding>>;
+
+/// The key bindings file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KeyConfig {
+    /// The keyboard layout, for when the window doesn't say what a key typed
+    #[serde(default = "default_layout")]
+    pub layout: String,
+    /// How held keys repeat
+    #[serde(default)]
+    pub repeat: KeyRepeat,
+    /// Which of the presets to use on top of the common bindings
+    pub preset: String,
+    pub presets: BTreeMap<String, ModeBindings>,
+    /// Bindings shared by every preset
+    pub modes: ModeBindings,
+}
+
+fn default_layout() -> String {
+    Layout::default().name().to_string()
+}
+
+impl KeyConfig {
+    /// Parse and check key bindings
+    pub fn parse(text: &str) -> Result<KeyConfig, String> {
+        let config: KeyConfig = ron::de::from_str(text).map_err(|e| e.to_string())?;
+
+        if Layout::from_name(&config.layout).is_none() {
+            return Err(format!("unknown keyboard layout {}", config.layout));
+        }
+
+        if !config.presets.contains_key(&config.preset) {
+            return Err(format!("unknown preset {}", config.preset));
+        }
+
+        for bindings in config.presets.values().chain(std::iter::once(&config.modes)) {
+            for (mode, bindings) in bindings {
+                if Mode::from_name(mode).is_none() {
+                    return Err(format!("unknown mode {}", mode));
+                }
+                for binding in bindings {
+                    chords::parse_chord(&binding.keys).map_err(|e| format!("{} in mode {}", e, mode))?;
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Load key bindings from a file, falling back to the built in ones
+    pub fn load<P>(path: P) -> KeyConfig where P: AsRef<Path> {
+        data_file::load_or_builtin(path, BUILTIN_KEYS, KeyConfig::parse, "key bindings")
+    }
+
+    pub fn builtin() -> KeyConfig {
+        data_file::parse_builtin(BUILTIN_KEYS, KeyConfig::parse, "key bindings")
+    }
+
+    pub fn keyboard_layout(&self) -> Layout {
+        Layout::from_name(&self.layout).unwrap_or_default()
+    }
+
+    /// The bindings from the common section and then the preset, so the preset can override them
+    pub fn sources(&self) -> Vec<Vec<(Mode, Vec<ChordKey>, Command)>> {
+        let preset = self.presets.get(&self.preset);
+        std::iter::once(&self.modes)
+            .chain(preset)
+            .map(|bindings| {
+                bindings.iter()
+                    .flat_map(|(mode, bindings)| bindings.iter().map(move |binding| (mode, binding)))
+                    .filter_map(|(mode, binding)| {
+                        let mode = Mode::from_name(mode)?;
+                        let keys = chords::parse_chord(&binding.keys).ok()?;
+                        Some((mode, keys, binding.command))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Bindings that get in each other's way
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// The same keys bound to two commands in one section. The later one wins.
+    Duplicate { mode: Mode, keys: Vec<ChordKey>, first: Command, second: Command },
+    /// A chord that can never be finished, because the keys it starts with run a command
+    Shadowed { mode: Mode, keys: Vec<ChordKey>, by: Vec<ChordKey> },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conflict::Duplicate { mode, keys, first, second } => write!(
+                f, "in {} mode, {} is bound to both {} and {}; using {}",
+                mode.name(), chords::chord_to_string(keys), first.name(), second.name(), second.name()
+            ),
+            Conflict::Shadowed { mode, keys, by } => write!(
+                f, "in {} mode, {} can never be typed because {} runs a command first",
+                mode.name(), chords::chord_to_string(keys), chords::chord_to_string(by)
+            ),
+        }
+    }
 }
 
 pub struct Commands {
-    pub chords: Chords<Command>,
-    pub stack: Vec<Chords<Command>>,
+    pub modes: BTreeMap<Mode, Chords<Command>>,
+    pub mode: Mode,
 }
 
 impl Default for Commands {
@@ -36,81 +252,355 @@ impl Default for Commands {
 }
 
 impl Commands {
+    /// No bindings at all
+    pub fn empty() -> Self {
+        Commands {
+            modes: BTreeMap::new(),
+            mode: Mode::Menu,
+        }
+    }
+
+    /// The built in bindings
     pub fn new() -> Self {
-        let mut chords = Chords::new();
-
-        chords.register(&[ChordKey::Key(Key::Return, ModifierKey::NO_MODIFIER)], Command::NewLine, NEW_LINE);
-        chords.register(&[ChordKey::Key(Key::Backspace, ModifierKey::NO_MODIFIER)], Command::Backspace, BACKSPACE);
-        chords.register(&[ChordKey::Key(Key::Left, ModifierKey::NO_MODIFIER)], Command::Left, LEFT);
-        chords.register(&[ChordKey::Key(Key::Right, ModifierKey::NO_MODIFIER)], Command::Right, RIGHT);
-        chords.register(&[ChordKey::Key(Key::Up, ModifierKey::NO_MODIFIER)], Command::Up, UP);
-        chords.register(&[ChordKey::Key(Key::Down, ModifierKey::NO_MODIFIER)], Command::Down, DOWN);
-        chords.register(&[
-            ChordKey::Character('x', ModifierKey::CTRL),
-            ChordKey::Character('c', ModifierKey::CTRL),
-        ], Command::Quit, QUIT);
+        Commands::from_config(&KeyConfig::builtin()).0
+    }
 
-        Commands {
-            chords: chords,
-            stack: vec![],
+    /// Set up the bindings in a config, reporting any conflicts between them
+    pub fn configure(config: &KeyConfig) -> Self {
+        let (commands, conflicts) = Commands::from_config(config);
+        for conflict in conflicts {
+            warn!("key binding conflict: {}", conflict);
         }
+        commands
     }
 
-    pub fn register(&mut self, keys: &[ChordKey], command: Command, name: &'static str) {
-        self.chords.register(keys, command, name);
+    /// Set up the bindings in a config, along with the conflicts found between them
+    pub fn from_config(config: &KeyConfig) -> (Self, Vec<Conflict>) {
+        let mut commands = Commands::empty();
+        let mut conflicts = vec![];
+        let mut bound: BTreeMap<(Mode, Vec<ChordKey>), Command> = BTreeMap::new();
+
+        for bindings in config.sources() {
+            // the preset overriding a common binding is what presets are for, so only clashes
+            // within one section are reported
+            let mut seen: BTreeMap<(Mode, Vec<ChordKey>), Command> = BTreeMap::new();
+            for (mode, keys, command) in bindings {
+                if let Some(first) = seen.insert((mode, keys.clone()), command) {
+                    if first != command {
+                        conflicts.push(Conflict::Duplicate { mode: mode, keys: keys.clone(), first: first, second: command });
+                    }
+                }
+                bound.insert((mode, keys.clone()), command);
+                commands.register(mode, &keys, command);
+            }
+        }
+
+        for (mode, keys) in bound.keys() {
+            for (other_mode, other_keys) in bound.keys() {
+                if mode == other_mode && other_keys.len() < keys.len() && keys.starts_with(other_keys) {
+                    conflicts.push(Conflict::Shadowed { mode: *mode, keys: keys.clone(), by: other_keys.clone() });
+                }
+            }
+        }
+
+        (commands, conflicts)
     }
 
-    pub fn reset(&mut self) {
-        self.pop();
-        self.push();
+    pub fn register(&mut self, mode: Mode, keys: &[ChordKey], command: Command) {
+        self.modes.entry(mode).or_insert_with(Chords::new).register(keys, command, command.name());
     }
 
-    pub fn push(&mut self) {
-        self.chords.clear_chord();
-        self.stack.push(self.chords.clone());
+    /// The bindings in use
+    pub fn chords(&self) -> Option<&Chords<Command>> {
+        self.modes.get(&self.mode)
     }
 
-    pub fn pop(&mut self) {
-        if let Some(chords) = self.stack.pop() {
-            self.chords = chords;
-        } else {
-            *self = Self::new();
+    /// The keys of an unfinished chord, and what each key that could come next does
+    pub fn pending(&self) -> Option<(Vec<ChordKey>, Vec<(ChordKey, String)>)> {
+        let chords = self.chords().filter(|chords| chords.is_building())?;
+        let next = chords.continuations()
+            .into_iter()
+            .map(|(key, command)| {
+                let name = command
+                    .and_then(|command| chords.get_action_name(command).cloned())
+                    .unwrap_or_else(|| "+more".to_string());
+                (key, name)
+            })
+            .collect();
+
+        Some((chords.get_keys().to_vec(), next))
+    }
+
+    /// A line of hints for a mode such as "Enter: drink  Escape/i: close", giving the keys
+    /// bound to each command. Commands with no keys are left out.
+    pub fn hints(&self, mode: Mode, hints: &[(Command, &str)]) -> String {
+        let bindings = self.bindings(mode);
+        hints.iter()
+            .filter_map(|(command, what)| {
+                let (_, keys) = bindings.iter().find(|(bound, _)| bound == command)?;
+                let keys: Vec<String> = keys.iter().map(|keys| chords::chord_to_string(keys)).collect();
+                Some(format!("{}: {}", keys.join("/"), what))
+            })
+            .collect::<Vec<String>>()
+            .join("  ")
+    }
+
+    /// The commands bound in a mode, each with every chord that runs it
+    pub fn bindings(&self, mode: Mode) -> Vec<(Command, Vec<Vec<ChordKey>>)> {
+        let mut by_command: BTreeMap<Command, Vec<Vec<ChordKey>>> = BTreeMap::new();
+        for (keys, command) in self.modes.get(&mode).map(|chords| chords.bindings()).unwrap_or_default() {
+            by_command.entry(command).or_default().push(keys);
         }
+        by_command.into_iter().collect()
     }
 
-    fn get_action_for_key<'a>(&'a mut self, key: Key, modifiers: ModifierKey) -> Option<ChordResult<Command>> {
-        match key {
-            Key::LCtrl => None,
-            Key::RCtrl => None,
-            Key::LShift => None,
-            Key::RShift => None,
-            Key::LAlt => None,
-            Key::RAlt => None,
-            _ => {
-                self.chords.perform(ChordKey::Key(key, modifiers))
-            }
+    /// Switch to another set of bindings, dropping any chord that was half typed
+    pub fn set_mode(&mut self, mode: Mode) {
+        if mode != self.mode {
+            self.reset();
+            self.mode = mode;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        if let Some(chords) = self.modes.get_mut(&self.mode) {
+            chords.clear_chord();
         }
     }
 
-    fn get_action_for_char<'a>(&'a mut self, c: char, modifiers: ModifierKey) -> Option<ChordResult<Command>> {
-        self.chords.perform(ChordKey::Character(c, modifiers))
+    fn get_action_for_key<'a>(&'a mut self, key: Key, modifiers: Modifiers) -> Option<ChordResult<Command>> {
+        self.modes.get_mut(&self.mode)?.perform(ChordKey::Key(key, modifiers))
+    }
+
+    fn get_action_for_char<'a>(&'a mut self, c: char, modifiers: Modifiers) -> Option<ChordResult<Command>> {
+        let modifiers = Modifiers { shift: false, ..modifiers };
+        self.modes.get_mut(&self.mode)?.perform(ChordKey::Character(c, modifiers))
     }
 
     pub fn key_event<'a>(&'a mut self, state: InputEventType, key: InputEventKey) -> Option<ChordResult<Command>> {
-        if state == InputEventType::KeyUp {
-            return None
+        match state {
+            InputEventType::KeyUp => None,
+            InputEventType::KeyDown => self.lookup(key),
+            InputEventType::KeyRepeat => {
+                // only whole commands repeat, never keys halfway through a chord
+                if self.chords().map(|chords| chords.is_building()).unwrap_or(false) {
+                    return None;
+                }
+                match self.lookup(key) {
+                    Some(ChordResult::Action(command)) if command.repeats() => Some(ChordResult::Action(command)),
+                    Some(ChordResult::Building) => {
+                        self.reset();
+                        None
+                    },
+                    _ => None,
+                }
+            },
         }
+    }
 
+    fn lookup(&mut self, key: InputEventKey) -> Option<ChordResult<Command>> {
         match key {
-            InputEventKey::KeyboardKey { character: Some(c), modifiers, .. } if charmap::is_printable(c) => { 
+            InputEventKey::KeyboardKey { character: Some(c), modifiers, .. } if charmap::is_printable(c) => {
                 self.get_action_for_char(c, modifiers)
             },
-            InputEventKey::KeyboardKey { key, modifiers, .. } => { 
+            InputEventKey::KeyboardKey { key, modifiers, .. } => {
                 self.get_action_for_key(key, modifiers)
-            }
-            _ => {
-                None
-            }
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(commands: &mut Commands, key: Key, modifiers: Modifiers) -> Option<ChordResult<Command>> {
+        commands.key_event(InputEventType::KeyDown, InputEventKey::new(key, modifiers, Layout::default()))
+    }
+
+    fn action(result: Option<ChordResult<Command>>) -> Option<Command> {
+        match result {
+            Some(ChordResult::Action(command)) => Some(command),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn hints_come_from_the_bindings() {
+        let mut config = KeyConfig::builtin();
+        config.preset = "arrows".to_string();
+        let commands = Commands::configure(&config);
+
+        let hints = commands.hints(Mode::Inventory, &[(Command::Confirm, "drink"), (Command::Quit, "quit"), (Command::Cancel, "close")]);
+        assert_eq!(hints, "Enter: drink  Escape/i: close");
+    }
+
+    #[test]
+    fn moves_are_described_by_mode() {
+        let left = Command::Move(-1, 0);
+        assert_eq!(left.description(Mode::Play), "move left, or attack what's in the way");
+        assert_eq!(left.description(Mode::Look), "move the cursor left");
+        assert_eq!(left.description(Mode::Targeting), "move the cursor left");
+        assert_eq!(Command::Move(0, 1).description(Mode::Inventory), "move the selection down");
+        assert_eq!(Command::Look.description(Mode::Look), Command::Look.description(Mode::Play));
+    }
+
+    #[test]
+    fn it_looks_up_keys_in_the_current_mode() {
+        let mut commands = Commands::new();
+
+        commands.set_mode(Mode::Play);
+        assert_eq!(action(press(&mut commands, Key::Left, Modifiers::NONE)), Some(Command::Move(-1, 0)));
+        assert_eq!(action(press(&mut commands, Key::I, Modifiers::NONE)), Some(Command::Inventory));
+        assert_eq!(action(press(&mut commands, Key::Slash, Modifiers::SHIFT)), Some(Command::Help));
+
+        commands.set_mode(Mode::Inventory);
+        assert_eq!(action(press(&mut commands, Key::I, Modifiers::NONE)), Some(Command::Cancel));
+        assert_eq!(action(press(&mut commands, Key::Left, Modifiers::NONE)), None);
+    }
+
+    #[test]
+    fn chords_build_up_over_several_keys() {
+        let mut commands = Commands::new();
+        commands.set_mode(Mode::Play);
+
+        match press(&mut commands, Key::X, Modifiers::CTRL) {
+            Some(ChordResult::Building) => {},
+            other => panic!("expected a chord in progress, got {:?}", other),
+        }
+        assert_eq!(action(press(&mut commands, Key::C, Modifiers::CTRL)), Some(Command::Quit));
+
+        // switching modes forgets a half typed chord
+        press(&mut commands, Key::X, Modifiers::CTRL);
+        commands.set_mode(Mode::Look);
+        commands.set_mode(Mode::Play);
+        assert_eq!(action(press(&mut commands, Key::C, Modifiers::CTRL)), None);
+    }
+
+    #[test]
+    fn it_describes_pending_chords_and_bindings() {
+        let mut commands = Commands::new();
+        commands.set_mode(Mode::Play);
+        assert_eq!(commands.pending(), None);
+
+        press(&mut commands, Key::X, Modifiers::CTRL);
+        let (keys, next) = commands.pending().unwrap();
+        assert_eq!(chords::chord_to_string(&keys), "C-x");
+        assert_eq!(next, vec![(ChordKey::Character('c', Modifiers::CTRL), "quit".to_string())]);
+
+        let bindings = commands.bindings(Mode::Play);
+        let help = bindings.iter().find(|(command, _)| *command == Command::Help).unwrap();
+        let keys: Vec<String> = help.1.iter().map(|keys| chords::chord_to_string(keys)).collect();
+        assert_eq!(keys, vec!["F1", "?"]);
+    }
+
+    #[test]
+    fn only_movement_repeats() {
+        let mut commands = Commands::new();
+        commands.set_mode(Mode::Play);
+        let repeat = |commands: &mut Commands, key: Key, modifiers: Modifiers| {
+            action(commands.key_event(InputEventType::KeyRepeat, InputEventKey::new(key, modifiers, Layout::default())))
+        };
+
+        assert_eq!(repeat(&mut commands, Key::Left, Modifiers::NONE), Some(Command::Move(-1, 0)));
+        assert_eq!(repeat(&mut commands, Key::G, Modifiers::NONE), None);
+
+        // a held chord prefix doesn't start the chord over and over
+        assert_eq!(repeat(&mut commands, Key::X, Modifiers::CTRL), None);
+        assert_eq!(commands.pending(), None);
+
+        press(&mut commands, Key::X, Modifiers::CTRL);
+        assert_eq!(repeat(&mut commands, Key::Left, Modifiers::NONE), None);
+        assert_eq!(action(press(&mut commands, Key::C, Modifiers::CTRL)), Some(Command::Quit));
+    }
+
+    #[test]
+    fn every_builtin_preset_loads_without_conflicts() {
+        let mut config = KeyConfig::builtin();
+        for preset in ["arrows", "vi", "numpad"].iter() {
+            config.preset = preset.to_string();
+            let (_, conflicts) = Commands::from_config(&config);
+            assert_eq!(conflicts, vec![], "conflicts in the {} preset", preset);
+        }
+
+        config.preset = "vi".to_string();
+        let (mut commands, _) = Commands::from_config(&config);
+        commands.set_mode(Mode::Play);
+        assert_eq!(action(press(&mut commands, Key::H, Modifiers::NONE)), Some(Command::Move(-1, 0)));
+        assert_eq!(action(press(&mut commands, Key::U, Modifiers::NONE)), Some(Command::Move(1, -1)));
+    }
+
+    #[test]
+    fn conflicting_bindings_are_reported() {
+        let config = KeyConfig::parse(r#"(
+            preset: "mine",
+            presets: {
+                "mine": {
+                    "play": [(keys: "g", command: Look)],
+                },
+            },
+            modes: {
+                "play": [
+                    (keys: "g", command: PickUp),
+                    (keys: "g g", command: Inventory),
+                    (keys: "C-x C-c", command: Quit),
+                    (keys: "C-x C-c", command: Help),
+                ],
+                "look": [(keys: "g", command: Look)],
+            },
+        )"#).unwrap();
+
+        let (mut commands, conflicts) = Commands::from_config(&config);
+        let g = chords::parse_chord("g").unwrap();
+        assert_eq!(conflicts, vec![
+            Conflict::Duplicate { mode: Mode::Play, keys: chords::parse_chord("C-x C-c").unwrap(), first: Command::Quit, second: Command::Help },
+            Conflict::Shadowed { mode: Mode::Play, keys: chords::parse_chord("g g").unwrap(), by: g },
+        ]);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "in play mode, C-x C-c is bound to both quit and help; using help"
+        );
+
+        // the preset wins, without that being a conflict
+        commands.set_mode(Mode::Play);
+        assert_eq!(action(press(&mut commands, Key::G, Modifiers::NONE)), Some(Command::Look));
+    }
+
+    #[test]
+    fn bad_bindings_are_rejected() {
+        let config = |mode: &str, keys: &str| format!(
+            r#"(preset: "p", presets: {{ "p": {{}} }}, modes: {{ "{}": [(keys: "{}", command: Look)] }})"#,
+            mode, keys
+        );
+
+        assert!(KeyConfig::parse(&config("play", "C-l")).is_ok());
+        assert!(KeyConfig::parse(&config("dancing", "l")).is_err());
+        assert!(KeyConfig::parse(&config("play", "Hyper-l")).is_err());
+        assert!(KeyConfig::parse(r#"(preset: "vi", presets: {}, modes: {})"#).is_err());
+    }
+
+    #[test]
+    fn the_layout_can_be_chosen() {
+        let config = |layout: &str| format!(r#"(layout: "{}", preset: "p", presets: {{ "p": {{}} }}, modes: {{}})"#, layout);
+
+        assert_eq!(KeyConfig::parse(&config("de")).unwrap().keyboard_layout(), Layout::De);
+        assert_eq!(KeyConfig::parse(&config("Dvorak")).unwrap().keyboard_layout(), Layout::Dvorak);
+        assert!(KeyConfig::parse(&config("auto")).is_err());
+        assert!(KeyConfig::parse(&config("klingon")).is_err());
+        assert_eq!(KeyConfig::builtin().keyboard_layout(), Layout::EnGb);
+    }
+
+    #[test]
+    fn characters_are_looked_up_with_the_layout() {
+        let mut commands = Commands::new();
+        commands.set_mode(Mode::Play);
+
+        // ? is shift and the key right of 0 on a German keyboard
+        let help = InputEventKey::new(Key::Minus, Modifiers::SHIFT, Layout::De);
+        assert_eq!(action(commands.key_event(InputEventType::KeyDown, help)), Some(Command::Help));
+
+        // and x is where b is on a US keyboard with Dvorak
+        let look = InputEventKey::new(Key::B, Modifiers::NONE, Layout::Dvorak);
+        assert_eq!(action(commands.key_event(InputEventType::KeyDown, look)), Some(Command::Look));
+    }
+}
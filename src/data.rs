@@ -2,18 +2,26 @@ use quicksilver::prelude::*;
 use quicksilver::lifecycle::Asset;
 use specs::prelude::*;
 use crate::color::{Palette};
-use crate::game::level::{Level};
-use crate::game::level_gen;
+use std::collections::BTreeSet;
+use crate::game::level::{Level, TileType};
+use crate::game::level_gen::{self, MapLayout};
 use crate::game::fov::Fov;
 use crate::game::system::{GameActor, GameActionQueue, GameAction, GameActionType, GameEventQueue, GameEvent};
 use crate::game::events::{Time};
 use crate::game::ecs::{Position, Rect};
-use crate::game::factions::Factions;
+use crate::game::factions::{FactionConfig, Factions, FACTIONS_FILE};
+use crate::game::monsters::MonsterBook;
+use crate::game::items::ItemBook;
+use crate::game::deeds::Deeds;
+use crate::ai::companion::CompanionOrder;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum InputMode {
     Edit,
     Play,
     Look,
+    /// Picking a target for the player's companions
+    Targeting,
 }
 
 pub struct GameText {
@@ -21,7 +29,6 @@ pub struct GameText {
     pub title: String,
     pub mononoki_info: String,
     pub square_info: Image,
-    pub inventory: Image,
 }
 
 impl GameText {
@@ -41,17 +48,11 @@ impl GameText {
                 &FontStyle::new(20.0, Color::BLACK),
             )?;
 
-            let inventory = font.render(
-                "Inventory:\n[A] Sword\n[B] Shield\n[C] Darts",
-                &FontStyle::new(20.0, Color::BLACK),
-            )?;
-
             Ok(GameText {
                 font,
                 title,
                 mononoki_info,
                 square_info,
-                inventory,
             })
         }))
     }
@@ -71,15 +72,26 @@ pub struct Data {
     pub input_mode: InputMode,
     pub palette: Palette,
     pub factions: Factions,
+    /// What creatures have been doing to each other lately
+    pub deeds: Deeds,
+    /// What the player last told their companions to do
+    pub companion_order: CompanionOrder,
+    pub depth: u32,
+    pub layout: MapLayout,
+    pub monsters: MonsterBook,
+    pub items: ItemBook,
 }
 
 impl Data {
     pub fn new(world: &mut World) -> Self {
         let mut level = Level::empty(Rect::new_sized(40, 30));
         let palette = Palette::new();
-        let entities = level_gen::make_map(&palette, &mut level, world);
+        let depth = 1;
+        let (monsters, items) = level_gen::load_books();
+        let (layout, entities) = level_gen::make_map(&mut level, world, depth, &monsters, &items);
         let mut fov = Fov::new(&level);
         let player = level_gen::create_player(&palette, &mut level, &mut fov, world);
+        let companions = level_gen::create_companions(&monsters, &mut level, world);
         
         let mut data = Data {
             level,
@@ -94,12 +106,19 @@ impl Data {
             cursor: None,
             input_mode: InputMode::Play,
             palette: palette,
-            factions: Factions::new(),
+            factions: Factions::new(&FactionConfig::load(FACTIONS_FILE)),
+            deeds: Deeds::default(),
+            companion_order: CompanionOrder::default(),
+            depth: depth,
+            layout: layout,
+            monsters: monsters,
+            items: items,
         };
 
         data.new_turn(GameActor::Player(player));
+        data.remember_seen();
 
-        for entity in entities {
+        for entity in entities.into_iter().chain(companions) {
             data.schedule_turn(Time::new(1, 0), GameActor::NonPlayer(entity));
         }
 
@@ -193,12 +212,35 @@ impl Data {
         }
     }
 
+    /// Update the player's memory of the cells they can see
+    pub fn remember_seen(&mut self) {
+        self.fov.remember(&self.level, self.player);
+    }
+
+    /// Every glyph the game draws: the terrain, the player, and everything in the monster
+    /// and item books
+    pub fn glyphs(&self) -> String {
+        let glyphs: BTreeSet<char> = TileType::ALL.iter()
+            .map(|tile| tile.glyph())
+            .chain(std::iter::once(level_gen::PLAYER_GLYPH))
+            .chain(self.monsters.glyphs())
+            .chain(self.items.glyphs())
+            .collect();
+        glyphs.into_iter().collect()
+    }
+
     pub fn look_mode(&mut self, cursor: Position, _path: Option<Vec<Position>>) {
         debug!("[{:?}] look at: {:?}", self.time, cursor);
         self.input_mode = InputMode::Look;
         self.cursor = Some(cursor.clone());
     }
 
+    pub fn target_mode(&mut self, cursor: Position) {
+        debug!("[{:?}] target: {:?}", self.time, cursor);
+        self.input_mode = InputMode::Targeting;
+        self.cursor = Some(cursor);
+    }
+
     pub fn play_mode(&mut self) {
         self.input_mode = InputMode::Play;
         self.cursor = None;
@@ -0,0 +1,40 @@
+//! The data files in static/ are read when the game starts, so they can be edited without a
+//! recompile. Each is also built into the game, to fall back on if the copy on disk is broken.
+
+use std::fs;
+use std::path::Path;
+
+/// Parse a data file, falling back to the built in copy if it can't be read or parsed.
+/// `what` names the contents for the log.
+pub fn load_or_builtin<T, P>(path: P, builtin: &str, parse: fn(&str) -> Result<T, String>, what: &str) -> T where P: AsRef<Path> {
+    let loaded = fs::read_to_string(path.as_ref())
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse(&text));
+
+    match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warn!("could not load {} from {:?}: {}. using built in {}", what, path.as_ref(), e, what);
+            parse_builtin(builtin, parse, what)
+        }
+    }
+}
+
+/// Parse the built in copy of a data file, which has to be valid
+pub fn parse_builtin<T>(builtin: &str, parse: fn(&str) -> Result<T, String>, what: &str) -> T {
+    parse(builtin).unwrap_or_else(|e| panic!("built in {}: {}", what, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_number(text: &str) -> Result<u32, String> {
+        text.trim().parse().map_err(|e| format!("{:?}: {}", text, e))
+    }
+
+    #[test]
+    fn a_missing_file_falls_back_to_the_builtin_copy() {
+        assert_eq!(load_or_builtin("static/no-such-file.ron", "42", parse_number, "numbers"), 42);
+    }
+}
@@ -0,0 +1,91 @@
+use specs::Entity;
+use super::ecs::Position;
+use super::events::Time;
+
+/// Turns a deed stays fresh enough for creatures to notice it
+pub const DEED_TURNS: u32 = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeedKind {
+    Attack,
+    Kill,
+}
+
+/// Something one creature did to another, that others nearby may have seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deed {
+    pub id: u64,
+    pub kind: DeedKind,
+    pub actor: Entity,
+    pub actor_faction: String,
+    pub victim: Entity,
+    pub victim_faction: String,
+    pub position: Position,
+    pub time: Time,
+    /// Done to someone the actor's faction wasn't at war with
+    pub crime: bool,
+}
+
+/// Recent deeds, oldest first
+#[derive(Default, Debug, Clone)]
+pub struct Deeds {
+    pub recent: Vec<Deed>,
+    last_id: u64,
+}
+
+impl Deeds {
+    /// Remember a deed, forgetting any that have gone stale. Returns its id.
+    pub fn record(&mut self, mut deed: Deed) -> u64 {
+        let now = deed.time;
+        self.recent.retain(|d| d.time + DEED_TURNS > now);
+
+        self.last_id += 1;
+        deed.id = self.last_id;
+        self.recent.push(deed);
+        self.last_id
+    }
+
+    /// The id of the latest deed, to pass to `since` later
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Deeds done after the one with the given id
+    pub fn since(&self, id: u64) -> impl Iterator<Item=&Deed> {
+        self.recent.iter().filter(move |d| d.id > id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::entities;
+
+    #[test]
+    fn it_forgets_stale_deeds() {
+        let (_world, ids) = entities(2);
+        let (orc, gnome) = (ids[0], ids[1]);
+        let deed = |time| Deed {
+            id: 0,
+            kind: DeedKind::Attack,
+            actor: orc,
+            actor_faction: "monster".to_owned(),
+            victim: gnome,
+            victim_faction: "neutral".to_owned(),
+            position: Position::new(1, 1),
+            time: Time::new(time, 0),
+            crime: true,
+        };
+        let mut deeds = Deeds::default();
+
+        let first = deeds.record(deed(1));
+        assert_eq!(deeds.since(0).count(), 1);
+        assert_eq!(deeds.since(first).count(), 0);
+
+        deeds.record(deed(2));
+        assert_eq!(deeds.since(first).count(), 1);
+
+        deeds.record(deed(1 + DEED_TURNS));
+        assert_eq!(deeds.recent.len(), 2);
+    }
+}
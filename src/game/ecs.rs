@@ -3,7 +3,10 @@ use hibitset::BitSetLike;
 use std::time::Duration;
 use std::collections::BTreeMap;
 use std::cmp;
+use serde::{Deserialize, Serialize};
 use super::level::{Level};
+use super::items::Item;
+use crate::ai::state::AiState;
 use crate::color::{Color};
 
 pub struct Index<T> {
@@ -60,7 +63,8 @@ impl Component for AiController {
 	type Storage = specs::NullStorage<Self>;
 }
 
-#[derive(Default, Debug, Clone, PartialEq)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Attributes {
     pub name: String,
     pub blocks: bool,
@@ -88,7 +92,62 @@ impl Component for Liquid {
     type Storage = VecStorage<Self>;
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Traits that change which terrain a creature can cross
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementTraits {
+    pub swimmer: bool,
+    pub flier: bool,
+    pub fire_immune: bool,
+}
+
+impl Component for MovementTraits {
+    type Storage = VecStorage<Self>;
+}
+
+/// Behaviour switches for AI controlled creatures
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiFlags {
+    /// Attacks hostile factions on sight
+    pub aggressive: bool,
+    /// Roams about when there's nothing else to do
+    pub wander: bool,
+    /// Walks a route around where it started
+    pub patrol: bool,
+    /// Gives orders to the rest of its faction nearby
+    pub leader: bool,
+    /// Takes crime reports from lawful creatures and goes after the culprits
+    pub guard: bool,
+}
+
+impl Default for AiFlags {
+    fn default() -> Self {
+        AiFlags {
+            aggressive: true,
+            wander: true,
+            patrol: false,
+            leader: false,
+            guard: false,
+        }
+    }
+}
+
+impl Component for AiFlags {
+    type Storage = VecStorage<Self>;
+}
+
+/// Items carried by a creature. Carried items have no `Position`.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Inventory {
+    pub items: Vec<Entity>,
+}
+
+impl Component for Inventory {
+    type Storage = VecStorage<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Fighter {
     pub defense: i32,
     pub attack: i32,
@@ -101,7 +160,7 @@ impl Component for Fighter {
 #[derive(Default)]
 pub struct DeltaTime(Duration);
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Rect {
     pub x: i32,
     pub y: i32,
@@ -209,9 +268,14 @@ impl Rect {
         && other.top() < self.bottom()
         && other.bottom() > self.top()
     }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        pos.x >= self.left() && pos.x < self.right()
+        && pos.y >= self.top() && pos.y < self.bottom()
+    }
 }
 
-#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -433,5 +497,19 @@ pub fn setup(world: &mut World) {
     world.register::<Attributes>();
     world.register::<Liquid>();
     world.register::<Fighter>();
+    world.register::<MovementTraits>();
+    world.register::<AiFlags>();
+    world.register::<Item>();
+    world.register::<Inventory>();
+    world.register::<AiState>();
     world.register::<Character>();
-}
\ No newline at end of file
+}
+/// A world with `n` bare entities, for tests that only need entity ids
+#[cfg(test)]
+pub fn entities(n: usize) -> (World, Vec<Entity>) {
+    use specs::Builder;
+
+    let mut world = World::new();
+    let entities = (0..n).map(|_| world.create_entity().build()).collect();
+    (world, entities)
+}
@@ -2,8 +2,9 @@ use std::ops;
 use std::fmt;
 use std::cmp::{self, Ordering};
 use std::collections::BinaryHeap;
+use serde::{Deserialize, Serialize};
 
-#[derive(Ord, PartialOrd, PartialEq, Eq, Copy, Clone, Debug)]
+#[derive(Ord, PartialOrd, PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Time {
     pub ticks: u32,
     pub micro_ticks: u32
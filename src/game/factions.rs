@@ -1,19 +1,36 @@
 use std::collections::HashMap as Map;
+use std::path::Path;
+use crate::data_file;
+use serde::{Deserialize, Serialize};
+use super::events::Time;
 
 pub const PLAYER: &str = "player";
 pub const MONSTER: &str = "monster";
 pub const NEUTRAL: &str = "neutral";
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub const FACTIONS_FILE: &str = "static/factions.ron";
+
+const BUILTIN_FACTIONS: &str = include_str!("../../static/factions.ron");
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct OpinionKey {
     pub subject: String,
     pub object: String,
 }
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Opinion(f32);
 
 impl Opinion {
+    /// Opinions run from -1 (hostile) to 1 (friendly)
+    pub fn new(value: f32) -> Self {
+        Opinion(value.max(-1.0).min(1.0))
+    }
+
+    pub fn value(self) -> f32 {
+        self.0
+    }
+
     pub fn is_positive(self) -> bool {
         self.0 > 0.0
     }
@@ -21,28 +38,136 @@ impl Opinion {
     pub fn is_negative(self) -> bool {
         self.0 < 0.0
     }
+}
+
+/// Where opinion turns into friendship or hostility
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Thresholds {
+    pub friendly: f32,
+    pub hostile: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            friendly: 0.5,
+            hostile: -0.5,
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn is_friendly(&self, opinion: Opinion) -> bool {
+        opinion.value() > self.friendly
+    }
+
+    pub fn is_hostile(&self, opinion: Opinion) -> bool {
+        opinion.value() < self.hostile
+    }
+}
+
+/// How much opinions change when one faction hurts another
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Reputation {
+    /// A faction's opinion of whoever attacks one of its members
+    pub attacked: f32,
+    /// A faction's opinion of whoever kills one of its members
+    pub member_killed: f32,
+    /// A faction's opinion of whoever kills one of its enemies
+    pub enemy_killed: f32,
+}
+
+/// A starting opinion one faction holds of another
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OpinionEntry {
+    pub subject: String,
+    pub object: String,
+    pub opinion: f32,
+    /// The object feels the same way about the subject
+    #[serde(default)]
+    pub symmetric: bool,
+}
+
+/// Factions as read from the data file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FactionConfig {
+    pub player_faction: String,
+    pub factions: Vec<String>,
+    #[serde(default)]
+    pub opinions: Vec<OpinionEntry>,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    /// How far opinions drift back to where they started each turn
+    #[serde(default)]
+    pub decay: f32,
+    #[serde(default)]
+    pub reputation: Reputation,
+}
+
+impl FactionConfig {
+    pub fn parse(text: &str) -> Result<FactionConfig, String> {
+        let config: FactionConfig = ron::de::from_str(text).map_err(|e| e.to_string())?;
+
+        if !config.factions.contains(&config.player_faction) {
+            return Err(format!("player faction {} is not defined", config.player_faction));
+        }
+        for entry in config.opinions.iter() {
+            for name in [&entry.subject, &entry.object].iter() {
+                if !config.factions.contains(*name) {
+                    return Err(format!("opinion refers to unknown faction {}", name));
+                }
+            }
+        }
+
+        Ok(config)
+    }
 
-    pub fn is_friendly(self) -> bool {
-        self.0 > 0.5
+    /// Load factions from a file, falling back to the built in definitions
+    pub fn load<P>(path: P) -> FactionConfig where P: AsRef<Path> {
+        data_file::load_or_builtin(path, BUILTIN_FACTIONS, FactionConfig::parse, "factions")
     }
 
-    pub fn is_hostile(self) -> bool {
-        self.0 < -0.5
+    pub fn builtin() -> FactionConfig {
+        data_file::parse_builtin(BUILTIN_FACTIONS, FactionConfig::parse, "factions")
     }
 }
 
 pub struct Factions {
     pub player_faction: String,
+    pub names: Vec<String>,
     pub opinions: Map<OpinionKey, Opinion>,
+    /// The opinions factions started with, which they drift back to over time
+    pub baseline: Map<OpinionKey, Opinion>,
+    pub thresholds: Thresholds,
+    pub decay: f32,
+    pub reputation: Reputation,
+    /// When opinions last drifted
+    last_decay: Time,
 }
 
 impl Factions {
-    pub fn new() -> Self {
+    pub fn new(config: &FactionConfig) -> Self {
         let mut f = Factions {
-            player_faction: PLAYER.to_string(),
-            opinions: Default::default()
+            player_faction: config.player_faction.clone(),
+            names: config.factions.clone(),
+            opinions: Default::default(),
+            baseline: Default::default(),
+            thresholds: config.thresholds.clone(),
+            decay: config.decay,
+            reputation: config.reputation.clone(),
+            last_decay: Time::default(),
         };
-        f.set_symmetric(PLAYER, MONSTER, Opinion(-1.0));
+
+        for entry in config.opinions.iter() {
+            let opinion = Opinion::new(entry.opinion);
+            if entry.symmetric {
+                f.set_symmetric(entry.subject.clone(), entry.object.clone(), opinion);
+            } else {
+                f.set(entry.subject.clone(), entry.object.clone(), opinion);
+            }
+        }
+        f.baseline = f.opinions.clone();
         f
     }
 
@@ -61,4 +186,143 @@ impl Factions {
         self.set(subject.clone(), object.clone(), opinion);
         self.set(object, subject, opinion);
     }
-}
\ No newline at end of file
+
+    pub fn is_friendly(&self, subject: &str, object: &str) -> bool {
+        self.thresholds.is_friendly(self.get(subject, object))
+    }
+
+    pub fn is_hostile(&self, subject: &str, object: &str) -> bool {
+        self.thresholds.is_hostile(self.get(subject, object))
+    }
+
+    /// Change how the subject regards the object
+    pub fn adjust(&mut self, subject: &str, object: &str, change: f32) {
+        if subject == object || change == 0.0 {
+            return;
+        }
+
+        let opinion = Opinion::new(self.get(subject, object).value() + change);
+        debug!("{} now regard {} at {:.2}", subject, object, opinion.value());
+        self.set(subject, object, opinion);
+    }
+
+    /// A member of the victim faction was attacked
+    pub fn attacked(&mut self, attacker: &str, victim: &str) {
+        let change = self.reputation.attacked;
+        self.adjust(victim, attacker, change);
+    }
+
+    /// A member of the victim faction was killed. Its enemies think better of the killer.
+    pub fn killed(&mut self, killer: &str, victim: &str) {
+        let change = self.reputation.member_killed;
+        self.adjust(victim, killer, change);
+
+        let enemies: Vec<String> = self.names
+            .iter()
+            .filter(|name| name.as_str() != killer && name.as_str() != victim && self.is_hostile(name, victim))
+            .cloned()
+            .collect();
+        let change = self.reputation.enemy_killed;
+        for enemy in enemies {
+            self.adjust(&enemy, killer, change);
+        }
+    }
+
+    /// Every opinion, in order, for saving
+    pub fn snapshot(&self) -> Vec<(OpinionKey, Opinion)> {
+        let mut opinions: Vec<_> = self.opinions.iter().map(|(key, opinion)| (key.clone(), *opinion)).collect();
+        opinions.sort_by(|a, b| a.0.cmp(&b.0));
+        opinions
+    }
+
+    /// Put back the opinions from a saved game, as they stood at `now`
+    pub fn restore(&mut self, opinions: Vec<(OpinionKey, Opinion)>, now: Time) {
+        self.opinions = opinions.into_iter().collect();
+        self.last_decay = now;
+    }
+
+    /// Let opinions drift back towards the baseline for the turns passed since the last call
+    pub fn decay(&mut self, now: Time) {
+        let turns = now.ticks.saturating_sub(self.last_decay.ticks);
+        if turns == 0 {
+            return;
+        }
+        self.last_decay = now;
+
+        let step = self.decay * turns as f32;
+        for (key, opinion) in self.opinions.iter_mut() {
+            let baseline = self.baseline.get(key).cloned().unwrap_or_default().value();
+            let value = opinion.value();
+            let drifted = if value > baseline {
+                (value - step).max(baseline)
+            } else {
+                (value + step).min(baseline)
+            };
+            *opinion = Opinion::new(drifted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factions() -> Factions {
+        let config = FactionConfig::parse(r#"(
+            player_faction: "player",
+            factions: ["player", "monster", "gnome"],
+            opinions: [
+                (subject: "player", object: "monster", opinion: -1.0, symmetric: true),
+                (subject: "gnome", object: "monster", opinion: -0.8),
+            ],
+            thresholds: (friendly: 0.4, hostile: -0.3),
+            decay: 0.1,
+            reputation: (attacked: -0.2, member_killed: -0.5, enemy_killed: 0.25),
+        )"#).unwrap();
+        Factions::new(&config)
+    }
+
+    #[test]
+    fn builtin_factions_make_players_and_monsters_enemies() {
+        let factions = Factions::new(&FactionConfig::builtin());
+        assert!(factions.is_hostile(PLAYER, MONSTER));
+        assert!(factions.is_hostile(MONSTER, PLAYER));
+        assert!(!factions.is_hostile(NEUTRAL, PLAYER));
+    }
+
+    #[test]
+    fn it_rejects_unknown_factions() {
+        let text = r#"(player_faction: "player", factions: ["player"], opinions: [(subject: "player", object: "elf", opinion: 1.0)])"#;
+        assert!(FactionConfig::parse(text).is_err());
+    }
+
+    #[test]
+    fn attacks_and_kills_change_opinions() {
+        let mut factions = factions();
+
+        factions.attacked(PLAYER, "gnome");
+        assert_eq!(factions.get("gnome", PLAYER), Opinion::new(-0.2));
+        assert!(!factions.is_hostile("gnome", PLAYER));
+
+        factions.attacked(PLAYER, "gnome");
+        assert!(factions.is_hostile("gnome", PLAYER));
+
+        // gnomes hate monsters, so killing one goes down well with them
+        factions.killed(PLAYER, MONSTER);
+        assert!(!factions.is_hostile("gnome", PLAYER));
+        assert_eq!(factions.get(MONSTER, PLAYER), Opinion::new(-1.0));
+    }
+
+    #[test]
+    fn opinions_drift_back_over_time() {
+        let mut factions = factions();
+        factions.adjust("gnome", PLAYER, -0.5);
+
+        factions.decay(Time::new(2, 0));
+        assert!((factions.get("gnome", PLAYER).value() + 0.3).abs() < 0.001);
+
+        factions.decay(Time::new(10, 0));
+        assert_eq!(factions.get("gnome", PLAYER), Opinion::new(0.0));
+        assert_eq!(factions.get("gnome", MONSTER), Opinion::new(-0.8));
+    }
+}
@@ -1,12 +1,42 @@
 use doryen_fov::{FovAlgorithm, FovRecursiveShadowCasting, MapData};
-use super::level::Level;
-use super::ecs::{Position, Rect};
+use specs::{Entities, ReadStorage};
+use specs::Entity as SpecsEntity;
+use serde::{Deserialize, Serialize};
+use crate::color::Color;
+use super::level::{self, Level};
+use super::ecs::{Attributes, Position, Rect};
+use super::factions::Factions;
 use super::grid::Grid;
 
+/// What the player saw of a cell the last time it was in view. Only what was drawn is kept,
+/// so memories outlive the entities in them and can be saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Remembered {
+    pub glyph: char,
+    pub color: Color,
+    pub item: Option<(char, Color)>,
+    pub monster: Option<(char, Color)>,
+}
+
+impl Remembered {
+    /// The glyph and color to show for the cell: the monster, else the item, else the terrain
+    pub fn top(&self) -> (char, Color) {
+        self.monster
+            .or(self.item)
+            .unwrap_or((self.glyph, self.color))
+    }
+}
+
+fn glimpse(entity: &level::Entity) -> (char, Color) {
+    (entity.character, entity.color)
+}
+
 pub struct Fov {
     area: Rect,
+    /// The cells within the radius of the last compute, the only ones that can be in view
+    view: Rect,
     map: MapData,
-    explored: Grid<bool>,
+    explored: Grid<Option<Remembered>>,
 }
 
 impl Fov {
@@ -15,6 +45,7 @@ impl Fov {
 
         let mut fov = Fov {
             area: r.clone(),
+            view: Rect::new(0, 0, 0, 0),
             map: MapData::new(r.width as usize, r.height as usize),
             explored: Grid::default(),
         };
@@ -29,23 +60,66 @@ impl Fov {
         self.map.clear_fov();
         let mut fov = FovRecursiveShadowCasting::new();
         fov.compute_fov(&mut self.map, position.x as usize, position.y as usize, radius as usize, true);
-        self.save_mapped();
+
+        let radius = radius as i32;
+        let left = (position.x - radius).max(self.area.left());
+        let top = (position.y - radius).max(self.area.top());
+        let right = (position.x + radius + 1).min(self.area.right());
+        let bottom = (position.y + radius + 1).min(self.area.bottom());
+        self.view = Rect::new(left, top, (right - left).max(0), (bottom - top).max(0));
     }
 
-    fn save_mapped(&mut self) {
-        let r = &self.area;
+    /// Note down what is on every cell in view, apart from the viewer
+    pub fn remember(&mut self, level: &Level, viewer: SpecsEntity) {
+        let r = self.view.clone();
 
         for i in r.left()..r.right() {
             for j in r.top()..r.bottom() {
                 if self.is_in_fov(i, j) {
-                    *self.explored.get_mut(i, j) = true;
+                    let cell = level.get(i, j);
+                    let others = || cell.entities.iter().filter(|e| e.id != viewer);
+                    *self.explored.get_mut(i, j) = Some(Remembered {
+                        glyph: cell.glyph,
+                        color: cell.color,
+                        item: others().find(|e| !e.blocked).map(glimpse),
+                        monster: others().find(|e| e.blocked).map(glimpse),
+                    });
                 }
             }
         }
     }
 
+    /// The living entities in view that are hostile to the viewer, nearest first
+    pub fn hostiles_in_view<'a>(
+        &self,
+        viewer: SpecsEntity,
+        factions: &Factions,
+        entities: &Entities<'a>,
+        positions: &ReadStorage<'a, Position>,
+        attributes: &ReadStorage<'a, Attributes>,
+    ) -> Vec<SpecsEntity> {
+        use specs::Join;
+
+        let faction = attributes.get(viewer).map(|a| a.faction.clone()).unwrap_or_default();
+        let viewer_pos = positions.get(viewer).cloned().unwrap_or_default();
+
+        let mut hostiles: Vec<(u32, SpecsEntity)> = (&**entities, positions, attributes)
+            .join()
+            .filter(|(e, pos, attr)| {
+                *e != viewer
+                    && attr.alive
+                    && self.is_in_fov(pos.x, pos.y)
+                    && factions.is_hostile(&attr.faction, &faction)
+            })
+            .map(|(e, pos, _)| (pos.distance(&viewer_pos), e))
+            .collect();
+        hostiles.sort_by_key(|(distance, _)| *distance);
+
+        hostiles.into_iter().map(|(_, e)| e).collect()
+    }
+
     pub fn reset_explored(&mut self) {
-        self.explored = Grid::filled(self.area.clone(), &false);
+        self.explored = Grid::filled(self.area.clone(), &None);
     }
 
     pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
@@ -53,7 +127,26 @@ impl Fov {
     }
 
     pub fn is_explored(&self, x: i32, y: i32) -> bool {
-        *self.explored.get(x, y)
+        self.explored.get(x, y).is_some()
+    }
+
+    /// What the cell looked like when it was last in view
+    pub fn remembered(&self, x: i32, y: i32) -> Option<&Remembered> {
+        self.explored.get(x, y).as_ref()
+    }
+
+    /// Every cell's memory, row by row, for saving
+    pub fn memories(&self) -> &[Option<Remembered>] {
+        self.explored.content()
+    }
+
+    /// Put back memories from a saved game. They have to cover the whole level.
+    pub fn restore_memories(&mut self, memories: Vec<Option<Remembered>>) -> Result<(), String> {
+        if memories.len() != self.explored.content.len() {
+            return Err(format!("{} cells remembered on a level of {}", memories.len(), self.explored.content.len()));
+        }
+        self.explored.content = memories;
+        Ok(())
     }
 
     fn load_level(&mut self, level: &Level) {
@@ -75,4 +168,97 @@ impl Fov {
             x += 1;
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{Builder, World, WorldExt};
+    use crate::game::ecs::entities;
+    use crate::color::Hue;
+    use crate::game::level::Tile;
+    use crate::game::factions::{self, FactionConfig};
+
+    #[test]
+    fn out_of_view_cells_show_what_was_last_seen() {
+        // two rooms either side of a wall
+        let mut level = Level::filled(Rect::new_sized(11, 3), &Tile::floor());
+        for y in 0..3 {
+            *level.get_mut(5, y) = Tile::wall();
+        }
+
+        let (_world, ids) = entities(2);
+        let (player, orc) = (ids[0], ids[1]);
+        level.get_mut(8, 1).entities.push(level::Entity {
+            character: 'o',
+            blocked: true,
+            id: orc,
+            color: Color::new(Hue::Green, 128),
+        });
+
+        let mut fov = Fov::new(&level);
+        fov.reset_explored();
+        fov.compute(&Position::new(7, 1), 10);
+        fov.remember(&level, player);
+        assert_eq!(fov.remembered(8, 1).unwrap().top().0, 'o');
+
+        // the orc leaves while the player is in the other room
+        level.get_mut(8, 1).entities.clear();
+        fov.compute(&Position::new(1, 1), 10);
+        fov.remember(&level, player);
+
+        assert!(!fov.is_in_fov(8, 1));
+        assert_eq!(fov.remembered(8, 1).unwrap().top().0, 'o');
+        assert!(fov.remembered(1, 1).unwrap().monster.is_none());
+    }
+
+    #[test]
+    fn only_cells_within_the_radius_are_remembered() {
+        let level = Level::filled(Rect::new_sized(20, 3), &Tile::floor());
+        let (_world, ids) = entities(1);
+        let player = ids[0];
+
+        let mut fov = Fov::new(&level);
+        fov.reset_explored();
+        fov.compute(&Position::new(2, 1), 3);
+        fov.remember(&level, player);
+
+        assert!(fov.is_explored(0, 1));
+        assert!(fov.is_explored(5, 1));
+        assert!(!fov.is_explored(6, 1));
+        assert!(!fov.is_explored(19, 1));
+    }
+
+    #[test]
+    fn hostiles_in_view_are_listed_nearest_first() {
+        // a wall hides the far end of the room
+        let mut level = Level::filled(Rect::new_sized(11, 3), &Tile::floor());
+        for y in 0..3 {
+            *level.get_mut(7, y) = Tile::wall();
+        }
+
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Attributes>();
+        let mut spawn = |name: &str, x: i32, faction: &str, alive: bool| {
+            world.create_entity()
+                .with(Position::new(x, 1))
+                .with(Attributes { name: name.to_string(), alive: alive, faction: faction.to_string(), ..Default::default() })
+                .build()
+        };
+        let player = spawn("player", 1, factions::PLAYER, true);
+        let far = spawn("far orc", 6, factions::MONSTER, true);
+        let near = spawn("near orc", 3, factions::MONSTER, true);
+        spawn("hidden orc", 9, factions::MONSTER, true);
+        spawn("dead orc", 2, factions::MONSTER, false);
+        spawn("dog", 4, factions::PLAYER, true);
+
+        let mut fov = Fov::new(&level);
+        fov.compute(&Position::new(1, 1), 20);
+        let factions = Factions::new(&FactionConfig::builtin());
+
+        let (entities, positions, attributes): (Entities, ReadStorage<Position>, ReadStorage<Attributes>) = world.system_data();
+        let hostiles = fov.hostiles_in_view(player, &factions, &entities, &positions, &attributes);
+
+        assert_eq!(hostiles, vec![near, far]);
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::data_file;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use specs::{Builder, Component, Entity, VecStorage};
+use crate::color::Color;
+use super::ecs::{Character, Liquid, Position};
+use super::level::{self, Level};
+use super::spawn::SpawnTable;
+
+pub const ITEMS_FILE: &str = "static/items.ron";
+
+const BUILTIN_ITEMS: &str = include_str!("../../static/items.ron");
+
+/// How hard an item is to come by. Each loot roll picks a tier first, then an item of that tier.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Legendary,
+}
+
+impl Default for Rarity {
+    fn default() -> Self {
+        Rarity::Common
+    }
+}
+
+/// What an item does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ItemKind {
+    Potion { heal: i32 },
+    Weapon { attack: i32 },
+    Armour { defense: i32 },
+    Scroll { spell: String },
+    Gold { min: u32, max: u32 },
+}
+
+/// Everything needed to create an item
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    #[serde(default)]
+    pub rarity: Rarity,
+    pub kind: ItemKind,
+}
+
+/// An item lying on the floor or carried by someone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub name: String,
+    pub rarity: Rarity,
+    pub kind: ItemKind,
+    /// How many coins for gold, one for everything else
+    pub quantity: u32,
+}
+
+impl Component for Item {
+    type Storage = VecStorage<Self>;
+}
+
+/// Rolls for items, either when a monster dies or when a floor is populated
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LootTable {
+    /// Chance that each roll drops anything at all
+    pub chance: f32,
+    pub rolls: u32,
+    pub table: SpawnTable,
+    /// Always dropped, on top of whatever the rolls produce
+    pub guaranteed: Vec<String>,
+}
+
+impl Default for LootTable {
+    fn default() -> Self {
+        LootTable {
+            chance: 1.0,
+            rolls: 1,
+            table: SpawnTable::default(),
+            guaranteed: vec![],
+        }
+    }
+}
+
+impl LootTable {
+    pub fn roll<R>(&self, items: &ItemBook, depth: u32, rng: &mut R) -> Vec<String> where R: Rng {
+        let mut drops = self.guaranteed.clone();
+
+        for _ in 0..self.rolls {
+            if rng.gen::<f32>() >= self.chance {
+                continue;
+            }
+            if let Some(name) = items.roll_from(&self.table, depth, rng) {
+                drops.push(name);
+            }
+        }
+
+        drops
+    }
+}
+
+/// An item placed on every floor that matches, regardless of the loot rolls
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GuaranteedDrop {
+    pub name: String,
+    /// Placed on every nth floor
+    #[serde(default = "every_floor")]
+    pub every: u32,
+    #[serde(default)]
+    pub min_depth: u32,
+}
+
+fn every_floor() -> u32 {
+    1
+}
+
+impl GuaranteedDrop {
+    pub fn applies_at(&self, depth: u32) -> bool {
+        depth >= self.min_depth && self.every > 0 && (depth - self.min_depth) % self.every == 0
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemFile {
+    items: Vec<ItemTemplate>,
+    rarity: SpawnTable<Rarity>,
+    level_loot: LootTable,
+    #[serde(default)]
+    floor_guaranteed: Vec<GuaranteedDrop>,
+}
+
+/// The item templates plus the tables deciding what turns up where
+#[derive(Default, Debug, Clone)]
+pub struct ItemBook {
+    pub templates: BTreeMap<String, ItemTemplate>,
+    /// Weight of each rarity tier by depth
+    pub rarity: SpawnTable<Rarity>,
+    /// Rolled once per spawn region when a floor is populated
+    pub level_loot: LootTable,
+    pub floor_guaranteed: Vec<GuaranteedDrop>,
+}
+
+impl ItemBook {
+    pub fn parse(text: &str) -> Result<ItemBook, String> {
+        let file: ItemFile = ron::de::from_str(text).map_err(|e| e.to_string())?;
+
+        let mut templates = BTreeMap::new();
+        for template in file.items {
+            if templates.contains_key(&template.name) {
+                return Err(format!("item {} is defined twice", template.name));
+            }
+            templates.insert(template.name.clone(), template);
+        }
+
+        let book = ItemBook {
+            templates: templates,
+            rarity: file.rarity,
+            level_loot: file.level_loot,
+            floor_guaranteed: file.floor_guaranteed,
+        };
+
+        book.check_loot(&book.level_loot, "level loot")?;
+        for drop in book.floor_guaranteed.iter() {
+            if !book.templates.contains_key(&drop.name) {
+                return Err(format!("floor guarantees unknown item {}", drop.name));
+            }
+        }
+
+        Ok(book)
+    }
+
+    /// Make sure every name in a loot table is a known item
+    pub fn check_loot(&self, loot: &LootTable, owner: &str) -> Result<(), String> {
+        let names = loot.table.entries.iter().map(|e| &e.name).chain(loot.guaranteed.iter());
+        for name in names {
+            if !self.templates.contains_key(name) {
+                return Err(format!("{} refers to unknown item {}", owner, name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load items from a file, falling back to the built in definitions
+    pub fn load<P>(path: P) -> ItemBook where P: AsRef<Path> {
+        data_file::load_or_builtin(path, BUILTIN_ITEMS, ItemBook::parse, "items")
+    }
+
+    pub fn builtin() -> ItemBook {
+        data_file::parse_builtin(BUILTIN_ITEMS, ItemBook::parse, "items")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ItemTemplate> {
+        self.templates.get(name)
+    }
+
+    /// The glyph of every item in the book
+    pub fn glyphs(&self) -> Vec<char> {
+        self.templates.values().map(|template| template.glyph).collect()
+    }
+
+    /// Pick a rarity tier for the depth, then an item of that tier from the table.
+    /// Falls back to the whole table if it has nothing of the chosen tier.
+    pub fn roll_from<R>(&self, table: &SpawnTable, depth: u32, rng: &mut R) -> Option<String> where R: Rng {
+        if let Some(rarity) = self.rarity.roll(depth, rng).cloned() {
+            let tier = SpawnTable::new(
+                table.entries
+                    .iter()
+                    .filter(|e| self.get(&e.name).map(|t| t.rarity == rarity).unwrap_or(false))
+                    .cloned()
+                    .collect()
+            );
+
+            if let Some(name) = tier.roll(depth, rng) {
+                return Some(name.clone());
+            }
+        }
+
+        table.roll(depth, rng).cloned()
+    }
+
+    /// Create an item by name and put it on the level. Takes any entity builder
+    /// so items can be made while generating a floor or lazily from inside a system.
+    pub fn spawn<B, R>(&self, builder: B, name: &str, pos: &Position, level_map: &mut Level, rng: &mut R) -> Option<Entity> where B: Builder, R: Rng {
+        let template = match self.get(name) {
+            Some(template) => template,
+            None => {
+                warn!("unknown item: {}", name);
+                return None;
+            }
+        };
+
+        let quantity = match template.kind {
+            ItemKind::Gold { min, max } => rng.gen_range(min, max.max(min) + 1),
+            _ => 1,
+        };
+
+        let builder = builder
+            .with(pos.clone())
+            .with(Character { glyph: template.glyph, color: template.color })
+            .with(Item {
+                name: template.name.clone(),
+                rarity: template.rarity,
+                kind: template.kind.clone(),
+                quantity: quantity,
+            });
+
+        let e = match template.kind {
+            ItemKind::Potion { .. } => builder.with(Liquid { potable: true }).build(),
+            _ => builder.build(),
+        };
+
+        level_map.get_mut(pos.x, pos.y).entities.push(level::Entity {
+            character: template.glyph,
+            blocked: false,
+            id: e,
+            color: template.color,
+        });
+
+        Some(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::game::spawn::SpawnEntry;
+
+    #[test]
+    fn builtin_items_parse() {
+        let book = ItemBook::builtin();
+        let potion = book.get("healing potion").unwrap();
+
+        assert_eq!(potion.glyph, '!');
+        assert_eq!(potion.kind, ItemKind::Potion { heal: 8 });
+        assert!(book.rarity.total_weight(1) > 0);
+        assert!(book.level_loot.table.total_weight(1) > 0);
+    }
+
+    #[test]
+    fn it_rejects_unknown_loot() {
+        let err = ItemBook::parse("(items: [], rarity: [], level_loot: (guaranteed: [\"crown\"]))").unwrap_err();
+        assert!(err.contains("crown"));
+    }
+
+    #[test]
+    fn loot_includes_guaranteed_drops() {
+        let book = ItemBook::builtin();
+        let loot = LootTable {
+            chance: 0.0,
+            guaranteed: vec!["gold".to_owned()],
+            ..LootTable::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(loot.roll(&book, 1, &mut rng), vec!["gold".to_owned()]);
+    }
+
+    #[test]
+    fn rarer_tiers_only_appear_deeper() {
+        let book = ItemBook::builtin();
+        let table = SpawnTable::new(
+            book.templates.keys().map(|name| SpawnEntry::new(name.as_str(), 10)).collect()
+        );
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for _ in 0..100 {
+            let name = book.roll_from(&table, 1, &mut rng).unwrap();
+            assert!(book.get(&name).unwrap().rarity <= Rarity::Uncommon, "{} at depth 1", name);
+        }
+
+        let deep_rolls: Vec<Rarity> = (0..200)
+            .map(|_| book.roll_from(&table, 12, &mut rng).unwrap())
+            .map(|name| book.get(&name).unwrap().rarity)
+            .collect();
+        assert!(deep_rolls.contains(&Rarity::Rare));
+    }
+
+    #[test]
+    fn guaranteed_drops_repeat_every_nth_floor() {
+        let drop = GuaranteedDrop { name: "sword".to_owned(), every: 3, min_depth: 2 };
+
+        assert!(!drop.applies_at(1));
+        assert!(drop.applies_at(2));
+        assert!(!drop.applies_at(4));
+        assert!(drop.applies_at(5));
+    }
+}
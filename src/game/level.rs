@@ -1,7 +1,9 @@
 use std::fmt;
 use specs::Entity as SpecsEntity;
+use serde::{Deserialize, Serialize};
 use crate::color::{Color, Hue};
 use crate::game::grid::Grid;
+use crate::game::ecs::MovementTraits;
 
 pub const BLANK: char = ' ';
 pub const LINE_BLOCK: char = '#';
@@ -22,6 +24,11 @@ pub const LINE_LIGHT_RIGHT: char = '╶';
 pub const LINE_LIGHT_UP: char = '╵';
 pub const LINE_LIGHT_DOWN: char = '╷';
 pub const MIDDLE_DOT: char = '.';
+pub const TILDE: char = '~';
+pub const EQUALS: char = '=';
+pub const COLON: char = ':';
+pub const DOUBLE_QUOTE: char = '"';
+pub const CARET: char = '^';
 pub const GLYPHS: &'static [char] = &[
             BLANK,
             LINE_BLOCK,
@@ -42,6 +49,11 @@ pub const GLYPHS: &'static [char] = &[
             LINE_LIGHT_UP,
             LINE_LIGHT_DOWN,
             MIDDLE_DOT,
+            TILDE,
+            EQUALS,
+            COLON,
+            DOUBLE_QUOTE,
+            CARET,
         ];
 
 #[derive(Debug, Clone, PartialEq)]
@@ -58,11 +70,16 @@ impl fmt::Display for Entity {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TileType {
     Floor,
     Wall,
-    Void,   
+    Void,
+    ShallowWater,
+    DeepWater,
+    Rubble,
+    Grass,
+    Lava,
 }
 
 impl Default for TileType {
@@ -71,6 +88,103 @@ impl Default for TileType {
     }
 }
 
+impl TileType {
+    pub const ALL: &'static [TileType] = &[
+        TileType::Floor,
+        TileType::Wall,
+        TileType::Void,
+        TileType::ShallowWater,
+        TileType::DeepWater,
+        TileType::Rubble,
+        TileType::Grass,
+        TileType::Lava,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TileType::Floor => "floor",
+            TileType::Wall => "wall",
+            TileType::Void => "void",
+            TileType::ShallowWater => "shallow_water",
+            TileType::DeepWater => "deep_water",
+            TileType::Rubble => "rubble",
+            TileType::Grass => "grass",
+            TileType::Lava => "lava",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TileType> {
+        TileType::ALL.iter().cloned().find(|t| t.name() == name)
+    }
+
+    /// Cost in turns of stepping onto this terrain, or None if the creature can't enter it
+    pub fn move_cost(self, traits: &MovementTraits) -> Option<u32> {
+        match self {
+            TileType::Wall | TileType::Void => None,
+            TileType::Floor | TileType::Grass => Some(1),
+            TileType::ShallowWater | TileType::Rubble => {
+                if traits.flier || (self == TileType::ShallowWater && traits.swimmer) {
+                    Some(1)
+                } else {
+                    Some(2)
+                }
+            },
+            TileType::DeepWater => {
+                if traits.flier {
+                    Some(1)
+                } else if traits.swimmer {
+                    Some(2)
+                } else {
+                    None
+                }
+            },
+            TileType::Lava => {
+                if traits.flier || traits.fire_immune {
+                    Some(1)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+
+    /// True if a creature without any movement traits can't enter this terrain
+    pub fn blocks_movement(self) -> bool {
+        self.move_cost(&MovementTraits::default()).is_none()
+    }
+
+    pub fn blocks_sight(self) -> bool {
+        match self {
+            TileType::Wall => true,
+            _ => false,
+        }
+    }
+
+    pub fn glyph(self) -> char {
+        match self {
+            TileType::Floor => MIDDLE_DOT,
+            TileType::Wall => LINE_BLOCK,
+            TileType::Void => BLANK,
+            TileType::ShallowWater => TILDE,
+            TileType::DeepWater => EQUALS,
+            TileType::Rubble => COLON,
+            TileType::Grass => DOUBLE_QUOTE,
+            TileType::Lava => CARET,
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            TileType::Floor | TileType::Wall | TileType::Void => Color::new(Hue::White, 128),
+            TileType::ShallowWater => Color::new(Hue::Azure, 192),
+            TileType::DeepWater => Color::new(Hue::Blue, 192),
+            TileType::Rubble => Color::new(Hue::Orange, 96),
+            TileType::Grass => Color::new(Hue::Green, 160),
+            TileType::Lava => Color::new(Hue::Red, 255),
+        }
+    }
+}
+
 /// A buffer cell
 #[derive(Debug, Clone, PartialEq)]
 pub struct Tile {
@@ -87,28 +201,38 @@ impl Tile {
         self.entities.clear();
     }
 
-    pub fn floor() -> Self {
+    pub fn new(cell_type: TileType) -> Self {
         Tile {
-            cell_type: TileType::Floor,
-            glyph: MIDDLE_DOT,
+            blocked: cell_type.blocks_movement(),
+            block_sight: cell_type.blocks_sight(),
+            glyph: cell_type.glyph(),
+            color: cell_type.color(),
+            cell_type: cell_type,
             ..Default::default()
         }
     }
 
+    pub fn floor() -> Self {
+        Tile::new(TileType::Floor)
+    }
+
     pub fn wall() -> Self {
-        Tile {
-            blocked: true,
-            block_sight: true,
-            glyph: LINE_BLOCK,
-            cell_type: TileType::Wall,
-            ..Default::default()
-        }
+        Tile::new(TileType::Wall)
+    }
+
+    pub fn move_cost(&self, traits: &MovementTraits) -> Option<u32> {
+        self.cell_type.move_cost(traits)
     }
 
     pub fn add_entity(&mut self, entity: Entity) {
         self.entities.push(entity);
     }
 
+    /// The entity drawn for this tile: whoever is standing here, else the first item
+    pub fn top_entity(&self) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.blocked).or_else(|| self.entities.get(0))
+    }
+
     pub fn remove_entity(&mut self, id: SpecsEntity) -> Option<Entity> {
         if let Some(index) = self.entities.iter().position(|e| e.id == id) {
             Some(self.entities.remove(index))
@@ -133,7 +257,7 @@ impl Default for Tile {
 
 impl fmt::Display for Tile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(entity) = self.entities.get(0) {
+        if let Some(entity) = self.top_entity() {
             write!(f, "{}", entity.character)
         } else {
             write!(f, "{}", self.glyph)
@@ -354,6 +478,28 @@ mod tests {
         assert_eq!(buf.index_of(249, 179), buf.content.len() - 1);
     }
 
+    #[test]
+    fn terrain_costs_depend_on_movement_traits() {
+        let walker = MovementTraits::default();
+        let swimmer = MovementTraits { swimmer: true, ..Default::default() };
+        let flier = MovementTraits { flier: true, ..Default::default() };
+        let fire_immune = MovementTraits { fire_immune: true, ..Default::default() };
+
+        assert_eq!(TileType::Floor.move_cost(&walker), Some(1));
+        assert_eq!(TileType::Wall.move_cost(&flier), None);
+        assert_eq!(TileType::ShallowWater.move_cost(&walker), Some(2));
+        assert_eq!(TileType::ShallowWater.move_cost(&swimmer), Some(1));
+        assert_eq!(TileType::DeepWater.move_cost(&walker), None);
+        assert_eq!(TileType::DeepWater.move_cost(&swimmer), Some(2));
+        assert_eq!(TileType::DeepWater.move_cost(&flier), Some(1));
+        assert_eq!(TileType::Lava.move_cost(&walker), None);
+        assert_eq!(TileType::Lava.move_cost(&fire_immune), Some(1));
+
+        assert!(Tile::new(TileType::Lava).blocked);
+        assert!(!Tile::new(TileType::Rubble).blocked);
+        assert!(Tile::wall().block_sight);
+    }
+
     #[test]
     #[should_panic(expected = "outside the buffer")]
     fn pos_of_panics_on_out_of_bounds() {
@@ -0,0 +1,89 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::cmp;
+use crate::game::level::Level;
+use crate::game::ecs::{Position, Rect};
+use super::{MapGenerator, MapLayout, MapParams};
+use super::{clear_level, create_room, create_terrain_patch, create_tunnel};
+
+/// Binary space partitioning: split the level into leaves, put a room in each
+/// and join sibling subtrees with tunnels
+pub struct BspRooms;
+
+impl BspRooms {
+    fn split<R>(&self, params: &MapParams, rect: &Rect, rng: &mut R) -> Option<(Rect, Rect)> where R: Rng {
+        let min = params.room_min_size;
+        let can_split_v = rect.width > params.room_max_size && rect.width >= 2 * min;
+        let can_split_h = rect.height > params.room_max_size && rect.height >= 2 * min;
+
+        let vertical = match (can_split_v, can_split_h) {
+            (false, false) => return None,
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => rect.width > rect.height || (rect.width == rect.height && rng.gen()),
+        };
+
+        if vertical {
+            let at = rng.gen_range(min, rect.width - min + 1);
+            Some((
+                Rect::new(rect.x, rect.y, at, rect.height),
+                Rect::new(rect.x + at, rect.y, rect.width - at, rect.height),
+            ))
+        } else {
+            let at = rng.gen_range(min, rect.height - min + 1);
+            Some((
+                Rect::new(rect.x, rect.y, rect.width, at),
+                Rect::new(rect.x, rect.y + at, rect.width, rect.height - at),
+            ))
+        }
+    }
+
+    /// Fill a subtree with rooms and return the centre of one of them
+    fn build<R>(&self, params: &MapParams, leaf: &Rect, level_map: &mut Level, rooms: &mut Vec<Rect>, rng: &mut R) -> Position where R: Rng {
+        if let Some((a, b)) = self.split(params, leaf, rng) {
+            let a_center = self.build(params, &a, level_map, rooms, rng);
+            let b_center = self.build(params, &b, level_map, rooms, rng);
+            create_tunnel(&a_center, &b_center, level_map, rng);
+            a_center
+        } else {
+            let w = rng.gen_range(cmp::min(params.room_min_size, leaf.width), leaf.width + 1);
+            let h = rng.gen_range(cmp::min(params.room_min_size, leaf.height), leaf.height + 1);
+            let x = rng.gen_range(leaf.left(), leaf.right() - w + 1);
+            let y = rng.gen_range(leaf.top(), leaf.bottom() - h + 1);
+            let room = Rect::new(x, y, w, h);
+
+            create_room(&room, level_map);
+
+            if rng.gen::<f32>() < params.terrain_patch_chance {
+                create_terrain_patch(&room, level_map, rng);
+            }
+
+            let center = room.center();
+            rooms.push(room);
+            center
+        }
+    }
+}
+
+impl MapGenerator for BspRooms {
+    fn name(&self) -> &'static str {
+        "bsp rooms"
+    }
+
+    fn generate(&self, params: &MapParams, seed: u64, level_map: &mut Level) -> MapLayout {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        clear_level(level_map);
+
+        let area = level_map.area().clone();
+        let mut rooms = vec![];
+        let start = self.build(params, &area, level_map, &mut rooms, &mut rng);
+
+        MapLayout {
+            start: start,
+            spawn_regions: rooms.iter().map(|room| room.inner(1, 1)).collect(),
+            rooms: rooms,
+            ..Default::default()
+        }
+    }
+}
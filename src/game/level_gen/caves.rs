@@ -0,0 +1,73 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::game::level::{Tile, Level};
+use super::{MapGenerator, MapLayout, MapParams};
+use super::{chunk_regions, hollow_walls, nearest_floor};
+
+/// Cellular automata caves: random noise smoothed until it forms open caverns
+pub struct CellularCaves;
+
+impl CellularCaves {
+    fn wall_count(&self, walls: &[bool], width: i32, height: i32, x: i32, y: i32) -> usize {
+        let mut count = 0;
+        for j in (y - 1)..=(y + 1) {
+            for i in (x - 1)..=(x + 1) {
+                // the edge of the map counts as rock
+                if i < 0 || j < 0 || i >= width || j >= height || walls[(j * width + i) as usize] {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+impl MapGenerator for CellularCaves {
+    fn name(&self) -> &'static str {
+        "cellular caves"
+    }
+
+    fn generate(&self, params: &MapParams, seed: u64, level_map: &mut Level) -> MapLayout {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let area = level_map.area().clone();
+        let (width, height) = (area.width, area.height);
+
+        let mut walls: Vec<bool> = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                x == 0 || y == 0 || x == width - 1 || y == height - 1 || rng.gen::<f32>() < params.cave_wall_chance
+            })
+            .collect();
+
+        for _ in 0..params.cave_smoothing_passes {
+            walls = (0..width * height)
+                .map(|i| self.wall_count(&walls, width, height, i % width, i / width) >= 5)
+                .collect();
+        }
+
+        for i in 0..width * height {
+            let (x, y) = (area.x + i % width, area.y + i / width);
+            *level_map.get_mut(x, y) = if walls[i as usize] {
+                Tile::wall()
+            } else {
+                Tile::floor()
+            };
+        }
+
+        hollow_walls(level_map);
+
+        let start = nearest_floor(level_map, &area.center()).unwrap_or_else(|| {
+            // nothing survived the smoothing, so open up the middle
+            let center = area.center();
+            *level_map.get_mut(center.x, center.y) = Tile::floor();
+            center
+        });
+
+        MapLayout {
+            start: start,
+            spawn_regions: chunk_regions(level_map, params.spawn_region_size),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::game::level::{Tile, TileType, Level};
+use super::{MapGenerator, MapLayout, MapParams};
+use super::{chunk_regions, fill_rect, hollow_walls};
+
+/// Drunkard's walk: stagger around the level carving floor until enough is open
+pub struct DrunkardsWalk;
+
+impl MapGenerator for DrunkardsWalk {
+    fn name(&self) -> &'static str {
+        "drunkard's walk"
+    }
+
+    fn generate(&self, params: &MapParams, seed: u64, level_map: &mut Level) -> MapLayout {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let area = level_map.area().clone();
+        fill_rect(&area, level_map, |tile| {
+            *tile = Tile::wall();
+        });
+
+        // keep a border of rock around the map
+        let bounds = area.inner(1, 1);
+        let start = area.center();
+        let target = (bounds.area() as f32 * params.drunkard_floor_ratio) as usize;
+
+        let mut carved = vec![start.clone()];
+        *level_map.get_mut(start.x, start.y) = Tile::floor();
+
+        while carved.len() < target {
+            // each drunkard sets off from somewhere already dug
+            let mut pos = carved[rng.gen_range(0, carved.len())].clone();
+
+            for _ in 0..params.drunkard_walk_length {
+                let (dx, dy) = match rng.gen_range(0, 4) {
+                    0 => (-1, 0),
+                    1 => (1, 0),
+                    2 => (0, -1),
+                    _ => (0, 1),
+                };
+                let next = pos.delta(dx, dy);
+                if next.x < bounds.left() || next.x >= bounds.right() || next.y < bounds.top() || next.y >= bounds.bottom() {
+                    continue;
+                }
+                pos = next;
+
+                let tile = level_map.get_mut(pos.x, pos.y);
+                if tile.cell_type != TileType::Floor {
+                    *tile = Tile::floor();
+                    carved.push(pos.clone());
+                    if carved.len() >= target {
+                        break;
+                    }
+                }
+            }
+        }
+
+        hollow_walls(level_map);
+
+        MapLayout {
+            start: start,
+            spawn_regions: chunk_regions(level_map, params.spawn_region_size),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,720 @@
+use std::cmp;
+use rand::{self, Rng, SeedableRng};
+use rand::rngs::StdRng;
+use specs::{Entity, World, Builder};
+use specs::world::WorldExt;
+use crate::game::factions;
+use super::level::{self, Tile, TileType, Level};
+use crate::color::{Hue, Palette};
+use super::ecs::{Attributes, Character, Position, Rect, EntityIndex, Fighter, Inventory, PlayerController};
+use super::monsters::{MonsterBook, MONSTERS_FILE};
+use super::items::{ItemBook, ITEMS_FILE};
+use super::fov::Fov;
+
+pub mod rooms;
+pub mod bsp;
+pub mod caves;
+pub mod drunkard;
+pub mod vault;
+pub mod regions;
+
+pub use rooms::RoomsAndTunnels;
+pub use bsp::BspRooms;
+pub use caves::CellularCaves;
+pub use drunkard::DrunkardsWalk;
+pub use vault::{Vault, VaultPlacement, VaultSpawn};
+pub use regions::{Connectivity, RegionMap};
+
+/// Where designers keep their vault files
+pub const VAULT_DIR: &str = "static/vaults";
+
+/// Tuning knobs shared by the map generators. Each algorithm reads the ones it needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapParams {
+    pub max_rooms: i32,
+    pub room_min_size: i32,
+    pub room_max_size: i32,
+    pub terrain_patch_chance: f32,
+    pub cave_wall_chance: f32,
+    pub cave_smoothing_passes: u32,
+    pub drunkard_floor_ratio: f32,
+    pub drunkard_walk_length: u32,
+    pub spawn_region_size: i32,
+    pub max_room_monsters: i32,
+    pub vault_chance: f32,
+    pub max_vaults: u32,
+    pub vault_attempts: u32,
+    pub connectivity: Connectivity,
+}
+
+impl Default for MapParams {
+    fn default() -> Self {
+        MapParams {
+            max_rooms: 30,
+            room_min_size: 6,
+            room_max_size: 10,
+            terrain_patch_chance: 0.4,
+            cave_wall_chance: 0.45,
+            cave_smoothing_passes: 4,
+            drunkard_floor_ratio: 0.4,
+            drunkard_walk_length: 200,
+            spawn_region_size: 8,
+            max_room_monsters: 3,
+            vault_chance: 0.5,
+            max_vaults: 2,
+            vault_attempts: 50,
+            connectivity: Connectivity::Regenerate { attempts: 3 },
+        }
+    }
+}
+
+/// What a generator reports back about the map it carved
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct MapLayout {
+    pub start: Position,
+    pub rooms: Vec<Rect>,
+    pub spawn_regions: Vec<Rect>,
+    pub vaults: Vec<VaultPlacement>,
+    pub regions: RegionMap,
+}
+
+pub trait MapGenerator {
+    fn name(&self) -> &'static str;
+
+    /// Carve a new map into the level, replacing whatever was there
+    fn generate(&self, params: &MapParams, seed: u64, level: &mut Level) -> MapLayout;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapAlgorithm {
+    RoomsAndTunnels,
+    Bsp,
+    Caves,
+    DrunkardsWalk,
+}
+
+impl MapAlgorithm {
+    pub fn generator(self) -> Box<dyn MapGenerator> {
+        match self {
+            MapAlgorithm::RoomsAndTunnels => Box::new(RoomsAndTunnels),
+            MapAlgorithm::Bsp => Box::new(BspRooms),
+            MapAlgorithm::Caves => Box::new(CellularCaves),
+            MapAlgorithm::DrunkardsWalk => Box::new(DrunkardsWalk),
+        }
+    }
+
+    /// Shallow floors are built from rooms, deeper ones may be caves
+    pub fn for_depth<R>(depth: u32, rng: &mut R) -> MapAlgorithm where R: Rng {
+        let choices: &[MapAlgorithm] = match depth {
+            0..=1 => &[MapAlgorithm::RoomsAndTunnels],
+            2..=3 => &[MapAlgorithm::RoomsAndTunnels, MapAlgorithm::Bsp],
+            4..=5 => &[MapAlgorithm::Bsp, MapAlgorithm::DrunkardsWalk, MapAlgorithm::Caves],
+            _ => &[MapAlgorithm::Bsp, MapAlgorithm::DrunkardsWalk, MapAlgorithm::Caves, MapAlgorithm::Caves],
+        };
+        choices[rng.gen_range(0, choices.len())]
+    }
+}
+
+/// Builds a floor of the dungeon: picks an algorithm, carves the map and populates it
+pub struct DungeonBuilder {
+    pub params: MapParams,
+    pub seed: u64,
+    pub vaults: Vec<Vault>,
+    pub monsters: MonsterBook,
+    pub items: ItemBook,
+}
+
+impl DungeonBuilder {
+    pub fn new(seed: u64) -> Self {
+        DungeonBuilder {
+            params: MapParams::default(),
+            seed: seed,
+            vaults: vec![],
+            monsters: MonsterBook::default(),
+            items: ItemBook::default(),
+        }
+    }
+
+    pub fn with_vaults(mut self, vaults: Vec<Vault>) -> Self {
+        self.vaults = vaults;
+        self
+    }
+
+    pub fn with_monsters(mut self, monsters: MonsterBook) -> Self {
+        self.monsters = monsters;
+        self
+    }
+
+    pub fn with_items(mut self, items: ItemBook) -> Self {
+        self.items = items;
+        self
+    }
+
+    /// The seed for a given floor, so each depth gets its own map
+    pub fn floor_seed(&self, depth: u32) -> u64 {
+        self.seed.wrapping_add((depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    pub fn generate(&self, depth: u32, level_map: &mut Level) -> MapLayout {
+        let floor_seed = self.floor_seed(depth);
+        let mut rng = StdRng::seed_from_u64(floor_seed);
+        let algorithm = MapAlgorithm::for_depth(depth, &mut rng);
+        let generator = algorithm.generator();
+
+        let attempts = match self.params.connectivity {
+            Connectivity::Regenerate { attempts } => cmp::max(attempts, 1),
+            _ => 1,
+        };
+
+        let mut layout = MapLayout::default();
+        for attempt in 0..attempts {
+            let seed = floor_seed.wrapping_add(attempt as u64);
+            debug!("generating depth {} with {} (seed {})", depth, generator.name(), seed);
+
+            layout = generator.generate(&self.params, seed, level_map);
+            self.place_vaults(depth, &mut rng, level_map, &mut layout);
+            layout.regions = RegionMap::analyse(level_map);
+
+            if layout.regions.len() <= 1 {
+                break;
+            }
+            debug!("depth {} has {} disconnected regions", depth, layout.regions.len());
+        }
+
+        if layout.regions.len() > 1 && self.params.connectivity != Connectivity::Ignore {
+            layout.regions = regions::connect_regions(level_map, &layout.start, &mut rng);
+        }
+
+        level_map.start = layout.start.clone();
+        layout
+    }
+
+    /// Stamp a few vaults into unused rock and tunnel each one to the nearest room
+    fn place_vaults<R>(&self, depth: u32, rng: &mut R, level_map: &mut Level, layout: &mut MapLayout) where R: Rng {
+        let candidates: Vec<&Vault> = self.vaults.iter().filter(|v| v.allowed_at(depth)).collect();
+        if candidates.is_empty() {
+            return;
+        }
+
+        for _ in 0..self.params.max_vaults {
+            if rng.gen::<f32>() >= self.params.vault_chance {
+                continue;
+            }
+
+            let vault = candidates[rng.gen_range(0, candidates.len())];
+            if let Some(placement) = vault.place(level_map, rng, self.params.vault_attempts) {
+                debug!("placed vault {} at {:?}", placement.name, placement.area);
+
+                let entrance = nearest_open(level_map, &placement.area, &placement.area.center());
+                let target = layout.rooms
+                    .iter()
+                    .map(|room| room.center())
+                    .min_by_key(|center| center.distance(&entrance))
+                    .unwrap_or_else(|| layout.start.clone());
+                create_tunnel(&entrance, &target, level_map, rng);
+
+                layout.vaults.push(placement);
+            }
+        }
+    }
+
+    pub fn build(&self, depth: u32, level_map: &mut Level, world: &mut World) -> (MapLayout, Vec<Entity>) {
+        let layout = self.generate(depth, level_map);
+        let mut rng = StdRng::seed_from_u64(self.floor_seed(depth).wrapping_add(1));
+        let mut entities = vec![];
+
+        for region in layout.spawn_regions.iter() {
+            place_objects(&self.params, &mut rng, &self.monsters, depth, region, &layout.regions, level_map, world, &mut entities);
+            place_items(&mut rng, &self.items, depth, region, &layout.regions, level_map, world);
+        }
+
+        for drop in self.items.floor_guaranteed.iter().filter(|drop| drop.applies_at(depth)) {
+            match random_reachable(&mut rng, &layout.regions, level_map) {
+                Some(pos) => {
+                    debug!("guaranteed {} at {:?}", drop.name, pos);
+                    self.items.spawn(world.create_entity(), &drop.name, &pos, level_map, &mut rng);
+                },
+                None => warn!("no room for guaranteed {} on depth {}", drop.name, depth),
+            }
+        }
+
+        for placement in layout.vaults.iter() {
+            for (pos, spawn) in placement.spawns.iter() {
+                if !layout.regions.is_reachable(&layout.start, pos) {
+                    debug!("vault {} spawn {:?} at {:?} is unreachable", placement.name, spawn, pos);
+                    continue;
+                }
+
+                match spawn {
+                    VaultSpawn::Monster(name) => {
+                        if let Some(e) = self.monsters.spawn(name, pos, level_map, world) {
+                            entities.push(e);
+                        }
+                    },
+                    VaultSpawn::Item(name) => {
+                        self.items.spawn(world.create_entity(), name, pos, level_map, &mut rng);
+                    },
+                }
+            }
+        }
+
+        (layout, entities)
+    }
+}
+
+pub fn make_map(level_map: &mut Level, world: &mut World, depth: u32, monsters: &MonsterBook, items: &ItemBook) -> (MapLayout, Vec<Entity>) {
+    DungeonBuilder::new(rand::random())
+        .with_vaults(vault::load_vaults(VAULT_DIR))
+        .with_monsters(monsters.clone())
+        .with_items(items.clone())
+        .build(depth, level_map, world)
+}
+
+/// Load the monster and item definitions used to populate floors
+pub fn load_books() -> (MonsterBook, ItemBook) {
+    (MonsterBook::load(MONSTERS_FILE), ItemBook::load(ITEMS_FILE))
+}
+
+pub fn fill_rect<F>(room: &Rect, map: &mut Level, f: F) where F: Fn(&mut Tile) {
+    for x in (room.left())..(room.right()) {
+        for y in (room.top())..(room.bottom()) {
+            let tile = map.get_mut(x, y);
+            f(tile);
+        }
+    }
+}
+
+pub fn create_room(room: &Rect, map: &mut Level) {
+    fill_rect(&room, map, |tile| {
+        match tile.cell_type {
+            TileType::Void => {
+                *tile = Tile::wall();
+            },
+            _ => {}
+        }
+    });
+    fill_rect(&room.inner(1, 1), map, |tile| {
+        *tile = Tile::floor();
+    });
+}
+
+/// Scatter a patch of terrain into a room's interior.
+/// Impassable terrain is kept away from the walls and the centre so tunnels still connect.
+pub fn create_terrain_patch<R>(room: &Rect, map: &mut Level, rng: &mut R) where R: Rng {
+    let cell_type = match rng.gen_range(0, 10) {
+        0..=2 => TileType::Grass,
+        3..=4 => TileType::ShallowWater,
+        5..=6 => TileType::Rubble,
+        7..=8 => TileType::DeepWater,
+        _ => TileType::Lava,
+    };
+
+    let bounds = if cell_type.blocks_movement() {
+        room.inner(2, 2)
+    } else {
+        room.inner(1, 1)
+    };
+
+    if bounds.width < 1 || bounds.height < 1 {
+        return;
+    }
+
+    let w = rng.gen_range(1, bounds.width + 1);
+    let h = rng.gen_range(1, bounds.height + 1);
+    let x = rng.gen_range(bounds.left(), bounds.right() - w + 1);
+    let y = rng.gen_range(bounds.top(), bounds.bottom() - h + 1);
+    let patch = Rect::new(x, y, w, h);
+
+    let center = room.center();
+    if cell_type.blocks_movement() && patch.intersects(&Rect::new(center.x, center.y, 1, 1)) {
+        return;
+    }
+
+    fill_rect(&patch, map, |tile| {
+        *tile = Tile::new(cell_type);
+    });
+}
+
+pub fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Level) {
+    let left = cmp::min(x1, x2);
+    let right = cmp::max(x1, x2);
+    let top = y - 1;
+    let bottom = y + 1;
+    let rect = Rect::new(left, top, right - left + 1, bottom - top + 1);
+    create_room(&rect, map);
+}
+
+pub fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Level) {
+    let top = cmp::min(y1, y2);
+    let bottom = cmp::max(y1, y2);
+    let left = x - 1;
+    let right = x + 1;
+    let rect = Rect::new(left, top, right - left + 1, bottom - top + 1);
+    create_room(&rect, map);
+}
+
+/// Join two points with an L-shaped tunnel, turning the corner at random
+pub fn create_tunnel<R>(from: &Position, to: &Position, map: &mut Level, rng: &mut R) where R: Rng {
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_h_tunnel(from.x, to.x, from.y, map);
+        create_v_tunnel(from.y, to.y, to.x, map);
+    } else {
+        // first move vertically, then horizontally
+        create_v_tunnel(from.y, to.y, from.x, map);
+        create_h_tunnel(from.x, to.x, to.y, map);
+    }
+}
+
+/// Reset every tile in the level to void
+pub fn clear_level(level_map: &mut Level) {
+    let area = level_map.area().clone();
+    fill_rect(&area, level_map, |tile| {
+        *tile = Tile::default();
+    });
+}
+
+/// Turn walls buried in rock into void so only the walls bordering open ground are drawn
+pub fn hollow_walls(level_map: &mut Level) {
+    let area = level_map.area().clone();
+    let mut buried = vec![];
+
+    for x in area.left()..area.right() {
+        for y in area.top()..area.bottom() {
+            if is_wall(level_map, x, y) {
+                let open = Position::new(x, y)
+                    .neighbours()
+                    .iter()
+                    .any(|p| match get_cell_type(level_map, p.x, p.y) {
+                        Some(TileType::Wall) | Some(TileType::Void) | None => false,
+                        _ => true,
+                    });
+                if !open {
+                    buried.push((x, y));
+                }
+            }
+        }
+    }
+
+    for (x, y) in buried {
+        *level_map.get_mut(x, y) = Tile::default();
+    }
+}
+
+/// Split the level into square chunks and return the ones with somewhere to stand
+pub fn chunk_regions(level_map: &Level, size: i32) -> Vec<Rect> {
+    let area = level_map.area().clone();
+    let mut regions = vec![];
+
+    let mut y = area.top();
+    while y < area.bottom() {
+        let mut x = area.left();
+        while x < area.right() {
+            let chunk = Rect::new(x, y, size, size).intersection(&area);
+            let mut open = false;
+            fill_rect_ref(&chunk, level_map, |tile| {
+                open = open || (tile.cell_type == TileType::Floor && !tile.blocked);
+            });
+            if open {
+                regions.push(chunk);
+            }
+            x += size;
+        }
+        y += size;
+    }
+
+    regions
+}
+
+/// The floor tile closest to the given position
+pub fn nearest_floor(level_map: &Level, pos: &Position) -> Option<Position> {
+    let area = level_map.area();
+    let mut best: Option<(u32, Position)> = None;
+
+    for x in area.left()..area.right() {
+        for y in area.top()..area.bottom() {
+            if level_map.get(x, y).cell_type == TileType::Floor {
+                let candidate = Position::new(x, y);
+                let d = candidate.distance(pos);
+                if best.as_ref().map(|(bd, _)| d < *bd).unwrap_or(true) {
+                    best = Some((d, candidate));
+                }
+            }
+        }
+    }
+
+    best.map(|(_, p)| p)
+}
+
+/// The open tile inside an area closest to the given position, or the position itself
+pub fn nearest_open(level_map: &Level, area: &Rect, pos: &Position) -> Position {
+    let mut best: Option<(u32, Position)> = None;
+
+    for x in area.left()..area.right() {
+        for y in area.top()..area.bottom() {
+            let tile = level_map.get(x, y);
+            if !tile.blocked && tile.cell_type != TileType::Void {
+                let candidate = Position::new(x, y);
+                let d = candidate.distance(pos);
+                if best.as_ref().map(|(bd, _)| d < *bd).unwrap_or(true) {
+                    best = Some((d, candidate));
+                }
+            }
+        }
+    }
+
+    best.map(|(_, p)| p).unwrap_or_else(|| pos.clone())
+}
+
+fn fill_rect_ref<F>(room: &Rect, map: &Level, mut f: F) where F: FnMut(&Tile) {
+    for x in (room.left())..(room.right()) {
+        for y in (room.top())..(room.bottom()) {
+            f(map.get(x, y));
+        }
+    }
+}
+
+fn get_cell_type<'a>(level_map: &'a Level, x: i32, y: i32) -> Option<&'a TileType> {
+    let area = level_map.area();
+    if x < area.left() || x >= area.right() || y < area.top() || y >= area.bottom() {
+        None
+    } else {
+        Some(&level_map.get(x, y).cell_type)
+    }
+}
+
+fn is_wall(level_map: &Level, x: i32, y: i32) -> bool {
+    match get_cell_type(level_map, x, y) {
+        Some(TileType::Wall) => true,
+        _ => false,
+    }
+}
+
+pub fn carve_walls(level_map: &mut Level) {
+    let area = level_map.area().to_owned();
+    for i in area.left()..area.right() {
+        for j in area.top()..area.bottom() {
+            if is_wall(level_map, i as i32, j as i32) {
+                let above = is_wall(level_map, i as i32, j as i32 - 1);
+                let below = is_wall(level_map, i as i32, j as i32 + 1);
+                let left = is_wall(level_map, i as i32 - 1, j as i32);
+                let right = is_wall(level_map, i as i32 + 1, j as i32);
+                let above_right = is_wall(level_map, i as i32 + 1, j as i32 - 1);
+                let below_right = is_wall(level_map, i as i32 + 1, j as i32 + 1);
+                let above_left = is_wall(level_map, i as i32 - 1, j as i32 - 1);
+                let below_left = is_wall(level_map, i as i32 - 1, j as i32 + 1);
+
+                let wall_character = match (left, right, above, below, above_right, below_right, above_left, below_left) {
+                    (true, true, true, true, true, true, true, true) => ' ',
+                    (true, true, true, true, true, true, true, false) => level::LINE_LIGHT_DOWN_AND_LEFT,
+                    (true, true, true, true, true, true, false, true) => level::LINE_LIGHT_UP_AND_LEFT,
+                    (true, true, true, true, true, true, false, false) => level::LINE_LIGHT_VERTICAL_AND_LEFT,
+                    (true, true, true, true, true, false, true, true) => level::LINE_LIGHT_DOWN_AND_RIGHT,
+                    (true, true, true, true, true, false, true, false) => level::LINE_LIGHT_HORIZONTAL_AND_DOWN,
+                    (true, true, true, true, false, true, true, true) => level::LINE_LIGHT_UP_AND_RIGHT,
+                    (true, true, true, true, false, true, false, true) => level::LINE_LIGHT_VERTICAL_AND_LEFT,
+                    (true, true, true, true, false, false, true, true) => level::LINE_LIGHT_VERTICAL_AND_RIGHT,
+                    (true, true, true, true, _, _, _, _) => level::LINE_LIGHT_VERTICAL_AND_HORIZONTAL,
+                    
+                    (true, true, true, false, true, _, true, _) => level::LINE_LIGHT_HORIZONTAL,
+                    (true, true, true, false, _, _, _, _) => level::LINE_LIGHT_HORIZONTAL_AND_UP,
+                    (true, true, false, true, _, true, _, true) => level::LINE_LIGHT_HORIZONTAL,
+                    (true, true, false, true, _, _, _, _) => level::LINE_LIGHT_HORIZONTAL_AND_DOWN,
+                    (true, true, false, false, _, _, _, _) => level::LINE_LIGHT_HORIZONTAL,
+                    (true, false, true, true, _, _, true, true) => level::LINE_LIGHT_VERTICAL,
+                    (true, false, true, true, _, _, _, _) => level::LINE_LIGHT_VERTICAL_AND_LEFT,
+                    (true, false, true, false, _, _, _, _) => level::LINE_LIGHT_UP_AND_LEFT,
+                    (true, false, false, true, _, _, _, _) => level::LINE_LIGHT_DOWN_AND_LEFT,
+                    (true, false, false, false, _, _, _, _) => level::LINE_LIGHT_HORIZONTAL,
+                    (false, true, true, true, true, true, _, _) => level::LINE_LIGHT_VERTICAL,
+                    (false, true, true, true, _, _, _, _) => level::LINE_LIGHT_VERTICAL_AND_RIGHT,
+                    (false, true, true, false, _, _, _, _) => level::LINE_LIGHT_UP_AND_RIGHT,
+                    (false, true, false, true, _, _, _, _) => level::LINE_LIGHT_DOWN_AND_RIGHT,
+                    (false, true, false, false, _, _, _, _) => level::LINE_LIGHT_HORIZONTAL,
+                    (false, false, true, true, _, _, _, _) => level::LINE_LIGHT_VERTICAL,
+                    (false, false, true, false, _, _, _, _) => level::LINE_LIGHT_VERTICAL,
+                    (false, false, false, true, _, _, _, _) => level::LINE_LIGHT_VERTICAL,
+                    (false, false, false, false, _, _, _, _) => level::LINE_LIGHT_BOX,
+                };
+
+                let tile = level_map.get_mut(i, j);
+                tile.cell_type = TileType::Wall;
+                tile.glyph = wall_character;
+            }
+        }
+    }
+}
+
+fn place_objects<R>(params: &MapParams, rng: &mut R, monsters: &MonsterBook, depth: u32, region: &Rect, regions: &RegionMap, level_map: &mut Level, world: &mut World, entities: &mut Vec<Entity>) where R: Rng {
+    if region.width < 1 || region.height < 1 {
+        return;
+    }
+
+    // choose random number of monsters
+    let num_monsters = rng.gen_range(0, params.max_room_monsters + 1);
+    let mut index = EntityIndex::new();
+
+    for _ in 0..num_monsters {
+        // choose random spot for this monster
+        let x = rng.gen_range(region.left(), region.right());
+        let y = rng.gen_range(region.top(), region.bottom());
+
+        let pos = Position::new(x, y);
+        let occupied = level_map.get(x, y).entities.iter().any(|e| e.blocked);
+        let reachable = regions.is_reachable(&level_map.start, &pos) && pos != level_map.start;
+        if !index.is_blocked(&pos) && !occupied && reachable {
+
+            let name = match monsters.spawn_table.roll(depth, rng) {
+                Some(name) => name,
+                // nothing can spawn at this depth, so no later roll will find anything either
+                None => break,
+            };
+
+            if let Some(e) = monsters.spawn(name, &pos, level_map, world) {
+                entities.push(e);
+                index.add(pos, e);
+            }
+        }
+    }
+}
+
+fn place_items<R>(rng: &mut R, items: &ItemBook, depth: u32, region: &Rect, regions: &RegionMap, level_map: &mut Level, world: &mut World) where R: Rng {
+    if region.width < 1 || region.height < 1 {
+        return;
+    }
+
+    for name in items.level_loot.roll(items, depth, rng) {
+        let x = rng.gen_range(region.left(), region.right());
+        let y = rng.gen_range(region.top(), region.bottom());
+
+        let pos = Position::new(x, y);
+        if regions.is_reachable(&level_map.start, &pos) {
+            items.spawn(world.create_entity(), &name, &pos, level_map, rng);
+        }
+    }
+}
+
+/// A random tile reachable from the start that nothing is standing on
+fn random_reachable<R>(rng: &mut R, regions: &RegionMap, level_map: &Level) -> Option<Position> where R: Rng {
+    let region = regions.region_at(&level_map.start)?;
+    let tiles: Vec<Position> = regions.tiles(region)
+        .into_iter()
+        .filter(|pos| *pos != level_map.start && level_map.get(pos.x, pos.y).entities.is_empty())
+        .collect();
+
+    if tiles.is_empty() {
+        None
+    } else {
+        Some(tiles[rng.gen_range(0, tiles.len())].clone())
+    }
+}
+
+/// How the player is drawn
+pub const PLAYER_GLYPH: char = '@';
+
+pub fn create_player(palette: &Palette, level: &mut Level, fov: &mut Fov, world: &mut World) -> Entity {
+    let vision_radius = 20;
+    let start = level.start();
+
+    fov.compute(&start, vision_radius);
+
+    let col = palette.color(Hue::White, 255);
+    let e = world
+        .create_entity()
+        .with(start.clone())
+        .with(Character { glyph: PLAYER_GLYPH, color: col.clone() })
+        .with(PlayerController {})
+        .with(Attributes {
+            name: "player".to_owned(),
+            blocks: true,
+            alive: true,
+            max_hp: 30,
+            hp: 30,
+            vision_radius: vision_radius,
+            faction: factions::PLAYER.into(),
+            ..Default::default()
+        })
+        .with(Fighter {
+            defense: 2,
+            attack: 5,
+        })
+        .with(Inventory::default())
+        .build();
+    
+    level.get_mut(start.x, start.y).entities.push(level::Entity {
+        character: PLAYER_GLYPH,
+        blocked: true,
+        id: e,
+        color: col,
+    });
+
+    e
+}
+
+/// Put the player's companions on free floor next to where the player starts
+pub fn create_companions(monsters: &MonsterBook, level: &mut Level, world: &mut World) -> Vec<Entity> {
+    let start = level.start();
+    let mut companions = vec![];
+
+    for name in monsters.companions.iter() {
+        let free = start
+            .neighbours()
+            .into_iter()
+            .find(|pos| level.get(pos.x, pos.y).cell_type == TileType::Floor && level.get(pos.x, pos.y).entities.is_empty());
+        match free {
+            Some(pos) => companions.extend(monsters.spawn(name, &pos, level, world)),
+            None => warn!("no room next to the player for {}", name),
+        }
+    }
+
+    companions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: &[MapAlgorithm] = &[
+        MapAlgorithm::RoomsAndTunnels,
+        MapAlgorithm::Bsp,
+        MapAlgorithm::Caves,
+        MapAlgorithm::DrunkardsWalk,
+    ];
+
+    #[test]
+    fn generators_start_on_open_ground() {
+        for algorithm in ALGORITHMS {
+            let mut level = Level::empty(Rect::new_sized(40, 30));
+            let layout = algorithm.generator().generate(&MapParams::default(), 42, &mut level);
+
+            let tile = level.get(layout.start.x, layout.start.y);
+            assert!(!tile.blocked && tile.cell_type != TileType::Void, "{:?} started on {:?}", algorithm, tile.cell_type);
+            assert!(!layout.spawn_regions.is_empty(), "{:?} has no spawn regions", algorithm);
+        }
+    }
+
+    #[test]
+    fn generators_are_deterministic_for_a_seed() {
+        for algorithm in ALGORITHMS {
+            let mut a = Level::empty(Rect::new_sized(40, 30));
+            let mut b = Level::empty(Rect::new_sized(40, 30));
+            let layout_a = algorithm.generator().generate(&MapParams::default(), 7, &mut a);
+            let layout_b = algorithm.generator().generate(&MapParams::default(), 7, &mut b);
+
+            assert_eq!(layout_a, layout_b);
+            assert!(a == b, "{:?} produced different maps for the same seed", algorithm);
+        }
+    }
+
+    #[test]
+    fn builder_leaves_every_floor_reachable() {
+        let builder = DungeonBuilder::new(3);
+        for depth in 1..8 {
+            let mut level = Level::empty(Rect::new_sized(40, 30));
+            let layout = builder.generate(depth, &mut level);
+
+            assert_eq!(layout.regions.len(), 1, "depth {} is disconnected", depth);
+            assert!(layout.regions.region_at(&layout.start).is_some());
+        }
+    }
+}
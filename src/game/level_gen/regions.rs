@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use rand::Rng;
+use crate::game::level::{TileType, Level};
+use crate::game::ecs::{MovementTraits, Position};
+use crate::game::grid::Grid;
+use super::create_tunnel;
+
+/// Label for tiles a walker can't stand on
+pub const NO_REGION: usize = 0;
+
+/// Connected areas of the level as seen by a creature without movement traits.
+/// Regions are numbered from 1; tiles that can't be walked on are `NO_REGION`.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct RegionMap {
+    pub labels: Grid<usize>,
+    pub sizes: Vec<usize>,
+}
+
+fn is_walkable(level_map: &Level, x: i32, y: i32) -> bool {
+    let tile = level_map.get(x, y);
+    tile.cell_type != TileType::Void && tile.move_cost(&MovementTraits::default()).is_some()
+}
+
+impl RegionMap {
+    /// Flood fill the level and label every connected walkable area
+    pub fn analyse(level_map: &Level) -> RegionMap {
+        let area = level_map.area().clone();
+        let mut labels = Grid::filled(area.clone(), &NO_REGION);
+        let mut sizes = vec![];
+
+        for x in area.left()..area.right() {
+            for y in area.top()..area.bottom() {
+                if *labels.get(x, y) != NO_REGION || !is_walkable(level_map, x, y) {
+                    continue;
+                }
+
+                let region = sizes.len() + 1;
+                let mut size = 0;
+                let mut queue = VecDeque::new();
+                *labels.get_mut(x, y) = region;
+                queue.push_back(Position::new(x, y));
+
+                while let Some(pos) = queue.pop_front() {
+                    size += 1;
+                    for next in pos.neighbours() {
+                        if next.x < area.left() || next.x >= area.right() || next.y < area.top() || next.y >= area.bottom() {
+                            continue;
+                        }
+                        if *labels.get(next.x, next.y) == NO_REGION && is_walkable(level_map, next.x, next.y) {
+                            *labels.get_mut(next.x, next.y) = region;
+                            queue.push_back(next);
+                        }
+                    }
+                }
+
+                sizes.push(size);
+            }
+        }
+
+        RegionMap {
+            labels: labels,
+            sizes: sizes,
+        }
+    }
+
+    /// Number of separate regions
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    pub fn region_at(&self, pos: &Position) -> Option<usize> {
+        let area = self.labels.area();
+        if pos.x < area.left() || pos.x >= area.right() || pos.y < area.top() || pos.y >= area.bottom() {
+            return None;
+        }
+
+        match *self.labels.get(pos.x, pos.y) {
+            NO_REGION => None,
+            region => Some(region),
+        }
+    }
+
+    /// True if a walker could get from one position to the other
+    pub fn is_reachable(&self, from: &Position, to: &Position) -> bool {
+        match (self.region_at(from), self.region_at(to)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    pub fn tiles(&self, region: usize) -> Vec<Position> {
+        let mut tiles = vec![];
+        for i in 0..self.labels.content().len() {
+            if self.labels.content()[i] == region {
+                let (x, y) = self.labels.pos_of(i);
+                tiles.push(Position::new(x, y));
+            }
+        }
+        tiles
+    }
+}
+
+/// How to deal with parts of a map that can't be reached from the start
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Connectivity {
+    /// Leave the map as it was generated
+    Ignore,
+    /// Dig corridors from each stranded region to the start's region
+    Connect,
+    /// Throw the map away and try another seed, connecting as a last resort
+    Regenerate { attempts: u32 },
+}
+
+/// Dig a corridor from every region to the one containing `start`,
+/// joining the closest pair of tiles each time. Returns the repaired region map.
+pub fn connect_regions<R>(level_map: &mut Level, start: &Position, rng: &mut R) -> RegionMap where R: Rng {
+    let mut regions = RegionMap::analyse(level_map);
+
+    while regions.len() > 1 {
+        let main = match regions.region_at(start) {
+            Some(main) => main,
+            None => break,
+        };
+        let main_tiles = regions.tiles(main);
+
+        let stranded = (1..=regions.len()).find(|r| *r != main).unwrap();
+        let stranded_tiles = regions.tiles(stranded);
+
+        let mut best: Option<(u32, Position, Position)> = None;
+        for a in stranded_tiles.iter() {
+            for b in main_tiles.iter() {
+                let d = a.distance(b);
+                if best.as_ref().map(|(bd, _, _)| d < *bd).unwrap_or(true) {
+                    best = Some((d, a.clone(), b.clone()));
+                }
+            }
+        }
+
+        match best {
+            Some((_, a, b)) => {
+                debug!("connecting region {} to {} from {:?} to {:?}", stranded, main, a, b);
+                create_tunnel(&a, &b, level_map, rng);
+            },
+            None => break,
+        }
+
+        let before = regions.len();
+        regions = RegionMap::analyse(level_map);
+        if regions.len() >= before {
+            warn!("corridor failed to join region {}, giving up", stranded);
+            break;
+        }
+    }
+
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::game::ecs::Rect;
+    use crate::game::level_gen::create_room;
+
+    fn two_rooms() -> Level {
+        let mut level = Level::empty(Rect::new_sized(30, 12));
+        create_room(&Rect::new(1, 1, 6, 6), &mut level);
+        create_room(&Rect::new(20, 4, 6, 6), &mut level);
+        level
+    }
+
+    #[test]
+    fn it_labels_separate_regions() {
+        let level = two_rooms();
+        let regions = RegionMap::analyse(&level);
+
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions.sizes[0], 16);
+        assert!(regions.is_reachable(&Position::new(2, 2), &Position::new(5, 5)));
+        assert!(!regions.is_reachable(&Position::new(2, 2), &Position::new(22, 6)));
+        assert_eq!(regions.region_at(&Position::new(0, 0)), None);
+    }
+
+    #[test]
+    fn it_connects_stranded_regions() {
+        let mut level = two_rooms();
+        let mut rng = StdRng::seed_from_u64(1);
+        let regions = connect_regions(&mut level, &Position::new(3, 3), &mut rng);
+
+        assert_eq!(regions.len(), 1);
+        assert!(regions.is_reachable(&Position::new(3, 3), &Position::new(22, 6)));
+    }
+}
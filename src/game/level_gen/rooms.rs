@@ -0,0 +1,79 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::game::level::Level;
+use crate::game::ecs::{Position, Rect};
+use super::{MapGenerator, MapLayout, MapParams};
+use super::{clear_level, create_room, create_terrain_patch, create_tunnel};
+
+/// Random non-overlapping rooms, each joined to the previous one by a tunnel
+pub struct RoomsAndTunnels;
+
+impl MapGenerator for RoomsAndTunnels {
+    fn name(&self) -> &'static str {
+        "rooms and tunnels"
+    }
+
+    fn generate(&self, params: &MapParams, seed: u64, level_map: &mut Level) -> MapLayout {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        clear_level(level_map);
+
+        let area = level_map.area().clone();
+        let mut rooms: Vec<Rect> = vec![];
+        let mut starting_position = Position::new(0, 0);
+
+        for _ in 0..params.max_rooms {
+            // random width and height
+            let w = rng.gen_range(params.room_min_size, params.room_max_size + 1);
+            let h = rng.gen_range(params.room_min_size, params.room_max_size + 1);
+
+            if w >= area.width || h >= area.height {
+                continue;
+            }
+
+            // random position without going out of the boundaries of the map
+            let x = rng.gen_range(area.x, area.right() - w);
+            let y = rng.gen_range(area.y, area.bottom() - h);
+
+            let new_room = Rect::new(x, y, w, h);
+
+            // run through the other rooms and see if they intersect with this one
+            let failed = rooms
+                .iter()
+                .any(|other_room| new_room.intersects(other_room));
+
+            if !failed {
+                // this means there are no intersections, so this room is valid
+
+                // "paint" it to the map's tiles
+                create_room(&new_room, level_map);
+
+                if rng.gen::<f32>() < params.terrain_patch_chance {
+                    create_terrain_patch(&new_room, level_map, &mut rng);
+                }
+
+                // center coordinates of the new room, will be useful later
+                let new_center = new_room.center();
+
+                if rooms.is_empty() {
+                    // this is the first room, where the player starts at
+                    starting_position = new_center;
+                } else {
+                    // all rooms after the first:
+                    // connect it to the previous room with a tunnel
+                    let prev_center = rooms[rooms.len() - 1].center();
+                    create_tunnel(&prev_center, &new_center, level_map, &mut rng);
+                }
+
+                rooms.push(new_room);
+            }
+        }
+
+        MapLayout {
+            start: starting_position,
+            spawn_regions: rooms.iter().map(|room| room.inner(1, 1)).collect(),
+            rooms: rooms,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use rand::Rng;
+use crate::game::level::{Tile, TileType, Level};
+use crate::game::ecs::{Position, Rect};
+use crate::game::grid::Grid;
+
+/// Map character meaning "leave whatever is already there"
+pub const TRANSPARENT: char = ' ';
+
+/// What a vault character turns into when stamped
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LegendEntry {
+    pub tile: Option<TileType>,
+    pub monster: Option<String>,
+    pub item: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VaultSpawn {
+    Monster(String),
+    Item(String),
+}
+
+/// A hand drawn room template.
+///
+/// Vault files are plain text made of `[vault]`, `[legend]` and `[map]` sections,
+/// one group per vault. Lines starting with `;` are comments outside of maps.
+///
+/// ```text
+/// [vault]
+/// name = flooded shrine
+/// min_depth = 2
+///
+/// [legend]
+/// o = floor monster:orc
+/// ! = floor item:potion
+///
+/// [map]
+/// #######
+/// #~~o~~#
+/// #~.!.~#
+/// ###.###
+/// ```
+///
+/// `#`, `.` and the terrain glyphs mean what they look like unless the legend
+/// says otherwise, and spaces leave the level untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vault {
+    pub name: String,
+    pub min_depth: u32,
+    pub max_depth: u32,
+    pub rotate: bool,
+    pub mirror: bool,
+    pub legend: BTreeMap<char, LegendEntry>,
+    pub map: Grid<char>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl VaultError {
+    fn new<S>(line: usize, message: S) -> Self where S: Into<String> {
+        VaultError {
+            line: line,
+            message: message.into(),
+        }
+    }
+}
+
+/// Where a vault ended up and what it wants spawned
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultPlacement {
+    pub name: String,
+    pub area: Rect,
+    pub spawns: Vec<(Position, VaultSpawn)>,
+}
+
+fn default_legend() -> BTreeMap<char, LegendEntry> {
+    let mut legend = BTreeMap::new();
+    for cell_type in TileType::ALL.iter().cloned() {
+        if cell_type != TileType::Void {
+            legend.insert(cell_type.glyph(), LegendEntry { tile: Some(cell_type), ..Default::default() });
+        }
+    }
+    legend
+}
+
+fn parse_legend_entry(line_no: usize, text: &str) -> Result<LegendEntry, VaultError> {
+    let mut entry = LegendEntry::default();
+
+    for word in text.split_whitespace() {
+        if let Some(name) = strip_prefix(word, "monster:") {
+            entry.monster = Some(name.to_owned());
+        } else if let Some(name) = strip_prefix(word, "item:") {
+            entry.item = Some(name.to_owned());
+        } else if let Some(cell_type) = TileType::from_name(word) {
+            entry.tile = Some(cell_type);
+        } else {
+            return Err(VaultError::new(line_no, format!("unknown legend value: {}", word)));
+        }
+    }
+
+    Ok(entry)
+}
+
+fn strip_prefix<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    if s.starts_with(prefix) {
+        Some(&s[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Section {
+    None,
+    Vault,
+    Legend,
+    Map,
+}
+
+impl Vault {
+    pub fn new<S>(name: S, rows: &[&str]) -> Vault where S: Into<String> {
+        let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+        let mut map = Grid::filled(Rect::new_sized(width as i32, rows.len() as i32), &TRANSPARENT);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                *map.get_mut(x as i32, y as i32) = c;
+            }
+        }
+
+        Vault {
+            name: name.into(),
+            min_depth: 0,
+            max_depth: std::u32::MAX,
+            rotate: true,
+            mirror: true,
+            legend: default_legend(),
+            map: map,
+        }
+    }
+
+    /// Parse every vault in a text file
+    pub fn parse_all(text: &str) -> Result<Vec<Vault>, VaultError> {
+        let mut vaults = vec![];
+        let mut current: Option<(usize, Vault, Vec<String>)> = None;
+        let mut section = Section::None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let trimmed = line.trim();
+
+            let header = match trimmed {
+                "[vault]" => Some(Section::Vault),
+                "[legend]" => Some(Section::Legend),
+                "[map]" => Some(Section::Map),
+                _ => None,
+            };
+
+            if let Some(header) = header {
+                if header == Section::Vault {
+                    if let Some(finished) = current.take() {
+                        vaults.push(Vault::finish(finished)?);
+                    }
+                    current = Some((line_no, Vault::new("", &[]), vec![]));
+                } else if current.is_none() {
+                    return Err(VaultError::new(line_no, format!("{} before [vault]", trimmed)));
+                }
+                section = header;
+                continue;
+            }
+
+            if section != Section::Map && (trimmed.is_empty() || trimmed.starts_with(';')) {
+                continue;
+            }
+
+            let (_, vault, rows) = match current.as_mut() {
+                Some(current) => current,
+                None => return Err(VaultError::new(line_no, "expected [vault]")),
+            };
+
+            match section {
+                Section::Vault => {
+                    let mut parts = trimmed.splitn(2, '=');
+                    let key = parts.next().unwrap_or("").trim();
+                    let value = match parts.next() {
+                        Some(value) => value.trim(),
+                        None => return Err(VaultError::new(line_no, "expected key = value")),
+                    };
+                    let number = || value.parse::<u32>().map_err(|_| VaultError::new(line_no, format!("not a number: {}", value)));
+                    let flag = || value.parse::<bool>().map_err(|_| VaultError::new(line_no, format!("not true or false: {}", value)));
+                    match key {
+                        "name" => vault.name = value.to_owned(),
+                        "min_depth" => vault.min_depth = number()?,
+                        "max_depth" => vault.max_depth = number()?,
+                        "rotate" => vault.rotate = flag()?,
+                        "mirror" => vault.mirror = flag()?,
+                        _ => return Err(VaultError::new(line_no, format!("unknown vault setting: {}", key))),
+                    }
+                },
+                Section::Legend => {
+                    // the key is the first character, so '=' and '#' can be mapped too
+                    let line = line.trim_start();
+                    let mut chars = line.chars();
+                    let key = chars.next().unwrap();
+                    let rest = chars.as_str().trim_start();
+                    if !rest.starts_with('=') {
+                        return Err(VaultError::new(line_no, "expected <char> = <tile> [monster:<name>] [item:<name>]"));
+                    }
+                    let entry = parse_legend_entry(line_no, &rest[1..])?;
+                    vault.legend.insert(key, entry);
+                },
+                Section::Map => {
+                    rows.push(line.trim_end().to_owned());
+                },
+                Section::None => {
+                    return Err(VaultError::new(line_no, "expected a section header"));
+                },
+            }
+        }
+
+        if let Some(finished) = current.take() {
+            vaults.push(Vault::finish(finished)?);
+        }
+
+        Ok(vaults)
+    }
+
+    fn finish((line_no, vault, rows): (usize, Vault, Vec<String>)) -> Result<Vault, VaultError> {
+        let first = rows.iter().position(|r| !r.is_empty()).unwrap_or(rows.len());
+        let last = rows.iter().rposition(|r| !r.is_empty()).map(|i| i + 1).unwrap_or(first);
+        let rows: Vec<&str> = rows[first..last].iter().map(|r| r.as_str()).collect();
+
+        if rows.is_empty() {
+            return Err(VaultError::new(line_no, format!("vault '{}' has no map", vault.name)));
+        }
+
+        for row in rows.iter() {
+            if let Some(c) = row.chars().find(|c| *c != TRANSPARENT && !vault.legend.contains_key(c)) {
+                return Err(VaultError::new(line_no, format!("vault '{}' uses '{}' which is not in its legend", vault.name, c)));
+            }
+        }
+
+        let mut parsed = Vault::new(vault.name.clone(), &rows);
+        parsed.min_depth = vault.min_depth;
+        parsed.max_depth = vault.max_depth;
+        parsed.rotate = vault.rotate;
+        parsed.mirror = vault.mirror;
+        parsed.legend = vault.legend;
+        Ok(parsed)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.map.area().width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.map.area().height
+    }
+
+    pub fn allowed_at(&self, depth: u32) -> bool {
+        depth >= self.min_depth && depth <= self.max_depth
+    }
+
+    /// Rotate clockwise by a number of quarter turns
+    pub fn rotated(&self, quarter_turns: u32) -> Vault {
+        let mut vault = self.clone();
+        for _ in 0..(quarter_turns % 4) {
+            let (w, h) = (vault.width(), vault.height());
+            let mut map = Grid::filled(Rect::new_sized(h, w), &TRANSPARENT);
+            for y in 0..w {
+                for x in 0..h {
+                    *map.get_mut(x, y) = *vault.map.get(y, h - 1 - x);
+                }
+            }
+            vault.map = map;
+        }
+        vault
+    }
+
+    /// Flip left to right
+    pub fn mirrored(&self) -> Vault {
+        let mut vault = self.clone();
+        let w = self.width();
+        for y in 0..self.height() {
+            for x in 0..w {
+                *vault.map.get_mut(x, y) = *self.map.get(w - 1 - x, y);
+            }
+        }
+        vault
+    }
+
+    /// A random orientation, as far as the vault allows
+    pub fn random_orientation<R>(&self, rng: &mut R) -> Vault where R: Rng {
+        let vault = if self.rotate {
+            self.rotated(rng.gen_range(0, 4))
+        } else {
+            self.clone()
+        };
+
+        if self.mirror && rng.gen() {
+            vault.mirrored()
+        } else {
+            vault
+        }
+    }
+
+    /// True if every drawn cell lands on unused rock inside the level's border
+    pub fn fits(&self, level_map: &Level, at: &Position) -> bool {
+        let bounds = level_map.area().inner(1, 1);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if *self.map.get(x, y) == TRANSPARENT {
+                    continue;
+                }
+                let (lx, ly) = (at.x + x, at.y + y);
+                if lx < bounds.left() || lx >= bounds.right() || ly < bounds.top() || ly >= bounds.bottom() {
+                    return false;
+                }
+                if level_map.get(lx, ly).cell_type != TileType::Void {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Write the vault's tiles into the level and return what it wants spawned
+    pub fn stamp(&self, level_map: &mut Level, at: &Position) -> VaultPlacement {
+        let mut spawns = vec![];
+
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let c = *self.map.get(x, y);
+                if c == TRANSPARENT {
+                    continue;
+                }
+                let pos = at.delta(x, y);
+                if let Some(entry) = self.legend.get(&c) {
+                    if let Some(cell_type) = entry.tile {
+                        *level_map.get_mut(pos.x, pos.y) = Tile::new(cell_type);
+                    }
+                    if let Some(ref monster) = entry.monster {
+                        spawns.push((pos.clone(), VaultSpawn::Monster(monster.clone())));
+                    }
+                    if let Some(ref item) = entry.item {
+                        spawns.push((pos.clone(), VaultSpawn::Item(item.clone())));
+                    }
+                }
+            }
+        }
+
+        VaultPlacement {
+            name: self.name.clone(),
+            area: Rect::new(at.x, at.y, self.width(), self.height()),
+            spawns: spawns,
+        }
+    }
+
+    /// Try random spots and orientations until the vault fits somewhere
+    pub fn place<R>(&self, level_map: &mut Level, rng: &mut R, attempts: u32) -> Option<VaultPlacement> where R: Rng {
+        let area = level_map.area().clone();
+
+        for _ in 0..attempts {
+            let vault = self.random_orientation(rng);
+            if vault.width() + 2 > area.width || vault.height() + 2 > area.height {
+                continue;
+            }
+            let x = rng.gen_range(area.left() + 1, area.right() - vault.width());
+            let y = rng.gen_range(area.top() + 1, area.bottom() - vault.height());
+            let at = Position::new(x, y);
+
+            if vault.fits(level_map, &at) {
+                return Some(vault.stamp(level_map, &at));
+            }
+        }
+
+        None
+    }
+}
+
+/// Load every `.txt` vault file in a directory, skipping (and logging) any that don't parse
+pub fn load_vaults<P>(dir: P) -> Vec<Vault> where P: AsRef<Path> {
+    let mut vaults = vec![];
+
+    let entries = match fs::read_dir(dir.as_ref()) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("could not read vaults from {:?}: {}", dir.as_ref(), e);
+            return vaults;
+        }
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map(|ext| ext == "txt").unwrap_or(false))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| Vault::parse_all(&text).map_err(|e| e.to_string())) {
+            Ok(mut parsed) => {
+                debug!("loaded {} vaults from {:?}", parsed.len(), path);
+                vaults.append(&mut parsed);
+            },
+            Err(e) => {
+                warn!("skipping vault file {:?}: {}", path, e);
+            }
+        }
+    }
+
+    vaults
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHRINE: &str = "
+; a test vault
+[vault]
+name = shrine
+min_depth = 2
+mirror = false
+
+[legend]
+o = floor monster:orc
+! = floor item:potion
+= = deep_water
+
+[map]
+#####
+#o.!#
+#===#
+";
+
+    #[test]
+    fn it_parses_a_vault() {
+        let vaults = Vault::parse_all(SHRINE).unwrap();
+        assert_eq!(vaults.len(), 1);
+
+        let vault = &vaults[0];
+        assert_eq!(vault.name, "shrine");
+        assert_eq!(vault.min_depth, 2);
+        assert!(!vault.mirror);
+        assert_eq!((vault.width(), vault.height()), (5, 3));
+        assert_eq!(vault.legend[&'='].tile, Some(TileType::DeepWater));
+        assert_eq!(vault.legend[&'o'].monster, Some("orc".to_owned()));
+    }
+
+    #[test]
+    fn shipped_vaults_parse() {
+        let vaults = Vault::parse_all(include_str!("../../../static/vaults/basic.txt")).unwrap();
+        assert!(!vaults.is_empty());
+    }
+
+    #[test]
+    fn it_reports_unknown_characters() {
+        let err = Vault::parse_all("[vault]\nname = bad\n[map]\n#?#\n").unwrap_err();
+        assert!(err.message.contains("'?'"));
+    }
+
+    #[test]
+    fn it_rotates_and_mirrors() {
+        let vault = Vault::new("l", &["ab", "cd", "ef"]);
+
+        let rotated = vault.rotated(1);
+        assert_eq!((rotated.width(), rotated.height()), (3, 2));
+        assert_eq!(*rotated.map.get(0, 0), 'e');
+        assert_eq!(*rotated.map.get(2, 1), 'b');
+        assert_eq!(vault.rotated(4), vault);
+
+        let mirrored = vault.mirrored();
+        assert_eq!(*mirrored.map.get(0, 0), 'b');
+        assert_eq!(mirrored.mirrored(), vault);
+    }
+
+    #[test]
+    fn it_stamps_into_void() {
+        let vault = Vault::parse_all(SHRINE).unwrap().remove(0);
+        let mut level = Level::empty(Rect::new_sized(10, 10));
+        let at = Position::new(2, 2);
+
+        assert!(vault.fits(&level, &at));
+        let placement = vault.stamp(&mut level, &at);
+
+        assert_eq!(level.get(2, 2).cell_type, TileType::Wall);
+        assert_eq!(level.get(3, 4).cell_type, TileType::DeepWater);
+        assert_eq!(placement.spawns, vec![
+            (Position::new(3, 3), VaultSpawn::Monster("orc".to_owned())),
+            (Position::new(5, 3), VaultSpawn::Item("potion".to_owned())),
+        ]);
+        assert!(!vault.fits(&level, &at));
+    }
+}
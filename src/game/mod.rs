@@ -10,4 +10,9 @@ pub mod path;
 pub mod events;
 pub mod system;
 pub mod grid;
-pub mod factions;
\ No newline at end of file
+pub mod factions;
+pub mod spawn;
+pub mod monsters;
+pub mod items;
+pub mod deeds;
+pub mod save;
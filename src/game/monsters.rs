@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::data_file;
+use serde::Deserialize;
+use specs::{Builder, Entity, World};
+use specs::world::WorldExt;
+use crate::color::Color;
+use super::ecs::{AiController, AiFlags, Attributes, Character, Fighter, Inventory, MovementTraits, Position};
+use super::factions;
+use super::items::LootTable;
+use crate::ai::state::{AiState, Awareness};
+use super::level::{self, Level};
+use super::spawn::SpawnTable;
+
+pub const MONSTERS_FILE: &str = "static/monsters.ron";
+
+const BUILTIN_MONSTERS: &str = include_str!("../../static/monsters.ron");
+
+fn monster_faction() -> String {
+    factions::MONSTER.to_owned()
+}
+
+/// Everything needed to create a monster
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    #[serde(default)]
+    pub attributes: Attributes,
+    pub fighter: Fighter,
+    #[serde(default = "monster_faction")]
+    pub faction: String,
+    #[serde(default)]
+    pub ai: AiFlags,
+    #[serde(default)]
+    pub movement: MovementTraits,
+    /// What the monster drops when it dies
+    #[serde(default)]
+    pub loot: LootTable,
+    /// How alert the monster is when the level starts
+    #[serde(default)]
+    pub awareness: Awareness,
+}
+
+#[derive(Debug, Deserialize)]
+struct MonsterFile {
+    monsters: Vec<MonsterTemplate>,
+    spawn_table: SpawnTable,
+    #[serde(default)]
+    companions: Vec<String>,
+}
+
+/// The monster templates and the table saying which ones appear at each depth
+#[derive(Default, Debug, Clone)]
+pub struct MonsterBook {
+    pub templates: BTreeMap<String, MonsterTemplate>,
+    pub spawn_table: SpawnTable,
+    /// Monsters that start the game at the player's side
+    pub companions: Vec<String>,
+}
+
+impl MonsterBook {
+    pub fn parse(text: &str) -> Result<MonsterBook, String> {
+        let file: MonsterFile = ron::de::from_str(text).map_err(|e| e.to_string())?;
+
+        let mut templates = BTreeMap::new();
+        for template in file.monsters {
+            if templates.contains_key(&template.name) {
+                return Err(format!("monster {} is defined twice", template.name));
+            }
+            templates.insert(template.name.clone(), template);
+        }
+
+        for entry in file.spawn_table.entries.iter() {
+            if !templates.contains_key(&entry.name) {
+                return Err(format!("spawn table refers to unknown monster {}", entry.name));
+            }
+        }
+        for name in file.companions.iter() {
+            if !templates.contains_key(name) {
+                return Err(format!("companion {} is not a known monster", name));
+            }
+        }
+
+        Ok(MonsterBook {
+            templates: templates,
+            spawn_table: file.spawn_table,
+            companions: file.companions,
+        })
+    }
+
+    /// Load monsters from a file, falling back to the built in definitions
+    pub fn load<P>(path: P) -> MonsterBook where P: AsRef<Path> {
+        data_file::load_or_builtin(path, BUILTIN_MONSTERS, MonsterBook::parse, "monsters")
+    }
+
+    pub fn builtin() -> MonsterBook {
+        data_file::parse_builtin(BUILTIN_MONSTERS, MonsterBook::parse, "monsters")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MonsterTemplate> {
+        self.templates.get(name)
+    }
+
+    /// The glyph of every monster in the book
+    pub fn glyphs(&self) -> Vec<char> {
+        self.templates.values().map(|template| template.glyph).collect()
+    }
+
+    /// Create a monster by name and put it on the level
+    pub fn spawn(&self, name: &str, pos: &Position, level_map: &mut Level, world: &mut World) -> Option<Entity> {
+        let template = match self.get(name) {
+            Some(template) => template,
+            None => {
+                warn!("unknown monster: {}", name);
+                return None;
+            }
+        };
+
+        let attributes = Attributes {
+            name: template.name.clone(),
+            blocks: true,
+            alive: true,
+            hp: template.attributes.max_hp,
+            faction: template.faction.clone(),
+            ..template.attributes.clone()
+        };
+
+        let e = world.create_entity()
+            .with(pos.clone())
+            .with(Character { glyph: template.glyph, color: template.color })
+            .with(attributes)
+            .with(AiController)
+            .with(template.ai.clone())
+            .with(template.movement.clone())
+            .with(template.fighter.clone())
+            .with(Inventory::default())
+            .with(AiState::new(template.awareness))
+            .build();
+
+        level_map.get_mut(pos.x, pos.y).entities.push(level::Entity {
+            character: template.glyph,
+            blocked: true,
+            id: e,
+            color: template.color,
+        });
+
+        Some(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::items::ItemBook;
+
+    #[test]
+    fn builtin_monsters_parse() {
+        let book = MonsterBook::builtin();
+        let orc = book.get("orc").unwrap();
+
+        assert_eq!(orc.glyph, 'o');
+        assert_eq!(orc.attributes.max_hp, 10);
+        assert_eq!(orc.faction, factions::MONSTER);
+        assert!(book.spawn_table.total_weight(1) > 0);
+
+        let dog = book.get(&book.companions[0]).unwrap();
+        assert_eq!(dog.faction, factions::PLAYER);
+    }
+
+    #[test]
+    fn glyphs_come_from_the_templates() {
+        let mut book = MonsterBook::builtin();
+        let mut dragon = book.get("orc").unwrap().clone();
+        dragon.name = "dragon".to_string();
+        dragon.glyph = 'D';
+        book.templates.insert(dragon.name.clone(), dragon);
+
+        let glyphs = book.glyphs();
+        assert!(glyphs.contains(&'o'));
+        assert!(glyphs.contains(&'D'));
+        assert_eq!(glyphs.len(), book.templates.len());
+    }
+
+    #[test]
+    fn builtin_loot_refers_to_known_items() {
+        let items = ItemBook::builtin();
+        for monster in MonsterBook::builtin().templates.values() {
+            items.check_loot(&monster.loot, &monster.name).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_rejects_unknown_spawns() {
+        let err = MonsterBook::parse("(monsters: [], spawn_table: [(name: \"dragon\", weight: 1)])").unwrap_err();
+        assert!(err.contains("dragon"));
+    }
+}
@@ -1,28 +1,34 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use pathfinding::prelude::*;
 use super::level::Level;
-use super::ecs::Position;
+use super::ecs::{MovementTraits, Position, Rect};
 
 pub struct PathFinder<'a> {
     pub level: &'a Level,
+    pub traits: MovementTraits,
 }
 
 impl <'a> PathFinder<'a> {
-    pub fn new(level: &'a Level) -> Self {
+    pub fn new(level: &'a Level, traits: &MovementTraits) -> Self {
         PathFinder {
             level: level,
+            traits: traits.clone(),
         }
     }
 
     fn move_cost(&self, _src: &Position, dst: &Position, target: &Position) -> u32 {
+        let area = self.level.area();
+        if dst.x < area.left() || dst.x >= area.right() || dst.y < area.top() || dst.y >= area.bottom() {
+            return std::u32::MAX;
+        }
+
         let level_cell = self.level.get(dst.x, dst.y);
-        if level_cell.blocked {
-            std::u32::MAX
-        } else if dst == target {
-            1
-        } else if level_cell.entities.iter().any(|e| e.blocked) {
-            std::u32::MAX
-        } else {
-            1
+        match level_cell.move_cost(&self.traits) {
+            None => std::u32::MAX,
+            Some(cost) if dst == target => cost,
+            Some(_) if level_cell.entities.iter().any(|e| e.blocked) => std::u32::MAX,
+            Some(cost) => cost,
         }
     }
 
@@ -42,4 +48,119 @@ impl <'a> PathFinder<'a> {
 
         astar(&src, successors, heuristic, success)
     }
-}
\ No newline at end of file
+}
+
+/// The cost of walking from each tile to the nearest goal, within some bounds.
+/// Creatures move by stepping to whichever neighbour is lowest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMap {
+    pub bounds: Rect,
+    pub values: HashMap<Position, i32>,
+}
+
+impl DistanceMap {
+    pub fn new(level: &Level, traits: &MovementTraits, goals: &[Position], bounds: Rect) -> Self {
+        let seeds = goals.iter().map(|goal| (goal.clone(), 0)).collect();
+        DistanceMap::scan(level, traits, seeds, bounds)
+    }
+
+    /// A map that leads away from the goals. Scaling by more than 1 before rescanning
+    /// makes open ground further on look better than the nearest dead end.
+    pub fn inverted(&self, level: &Level, traits: &MovementTraits, factor: f32) -> Self {
+        let seeds = self.values
+            .iter()
+            .map(|(pos, value)| (pos.clone(), (*value as f32 * -factor) as i32))
+            .collect();
+        DistanceMap::scan(level, traits, seeds, self.bounds.clone())
+    }
+
+    fn scan(level: &Level, traits: &MovementTraits, seeds: Vec<(Position, i32)>, bounds: Rect) -> Self {
+        let bounds = level.area().intersection(&bounds);
+        let mut values: HashMap<Position, i32> = HashMap::new();
+        let mut open = BinaryHeap::new();
+
+        for (pos, value) in seeds {
+            if bounds.contains(&pos) && values.get(&pos).map(|v| value < *v).unwrap_or(true) {
+                values.insert(pos.clone(), value);
+                open.push(Reverse((value, pos)));
+            }
+        }
+
+        while let Some(Reverse((value, pos))) = open.pop() {
+            if values.get(&pos).map(|v| value > *v).unwrap_or(false) {
+                continue;
+            }
+
+            for next in pos.neighbours() {
+                if !bounds.contains(&next) {
+                    continue;
+                }
+                if let Some(cost) = level.get(next.x, next.y).move_cost(traits) {
+                    let next_value = value + cost as i32;
+                    if values.get(&next).map(|v| next_value < *v).unwrap_or(true) {
+                        values.insert(next.clone(), next_value);
+                        open.push(Reverse((next_value, next)));
+                    }
+                }
+            }
+        }
+
+        DistanceMap {
+            bounds: bounds,
+            values: values,
+        }
+    }
+
+    pub fn get(&self, pos: &Position) -> Option<i32> {
+        self.values.get(pos).cloned()
+    }
+
+    /// The open neighbour lower than `from`, if there is one
+    pub fn downhill<F>(&self, from: &Position, is_open: F) -> Option<Position> where F: Fn(&Position) -> bool {
+        let here = self.get(from)?;
+        from.neighbours()
+            .into_iter()
+            .filter(|pos| is_open(pos))
+            .filter_map(|pos| self.get(&pos).map(|value| (value, pos)))
+            .filter(|(value, _)| *value < here)
+            .min_by_key(|(value, _)| *value)
+            .map(|(_, pos)| pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::level::Tile;
+
+    #[test]
+    fn fleeing_heads_for_open_ground_rather_than_the_nearest_corner() {
+        // a corridor running east into a dead end, with a room to the west
+        let mut level = Level::filled(Rect::new(0, 0, 20, 7), &Tile::wall());
+        for x in 1..8 {
+            for y in 1..6 {
+                *level.get_mut(x, y) = Tile::floor();
+            }
+        }
+        for x in 8..12 {
+            *level.get_mut(x, 3) = Tile::floor();
+        }
+
+        let traits = MovementTraits::default();
+        let threat = Position::new(9, 3);
+        let map = DistanceMap::new(&level, &traits, &[threat.clone()], level.area().clone());
+        assert_eq!(map.get(&threat), Some(0));
+        assert_eq!(map.get(&Position::new(11, 3)), Some(2));
+        assert_eq!(map.get(&Position::new(0, 0)), None);
+
+        // the plain map leads to the threat...
+        assert_eq!(map.downhill(&Position::new(11, 3), |_| true), Some(Position::new(10, 3)));
+
+        // ...and the flee map away from it, preferring the far side of the room to the dead end
+        let flee = map.inverted(&level, &traits, 1.2);
+        assert!(flee.get(&Position::new(1, 1)).unwrap() < flee.get(&Position::new(11, 3)).unwrap());
+        let from_room = Position::new(7, 3);
+        let step = flee.downhill(&from_room, |pos| *pos != threat).unwrap();
+        assert!(step.x < from_room.x);
+    }
+}
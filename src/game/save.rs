@@ -0,0 +1,377 @@
+//! Saving a game in progress and picking it up again. Only what can't be worked out again is
+//! kept: creatures forget what they were up to, and recent deeds are forgotten.
+
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use specs::{Builder, Entities, Entity, Join, ReadExpect, ReadStorage, World, WorldExt};
+use crate::ai::companion::CompanionOrder;
+use crate::ai::state::{AiState, Awareness};
+use crate::color::{Color, Palette};
+use crate::data::{Data, InputMode};
+use super::deeds::Deeds;
+use super::ecs::{AiController, AiFlags, Attributes, Character, Fighter, Inventory, Liquid, MovementTraits, PlayerController, Position, Rect};
+use super::events::Time;
+use super::factions::{FactionConfig, Factions, Opinion, OpinionKey, FACTIONS_FILE};
+use super::fov::{Fov, Remembered};
+use super::items::{Item, ItemKind};
+use super::level::{self, Level, Tile, TileType};
+use super::level_gen::{self, MapLayout};
+use super::system::{GameActionQueue, GameActor, GameEventQueue};
+
+pub const SAVE_FILE: &str = "save.ron";
+
+/// An item, carried or lying on the floor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedItem {
+    pub glyph: char,
+    pub color: Color,
+    pub item: Item,
+}
+
+/// How an AI controlled creature behaves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedAi {
+    pub flags: AiFlags,
+    pub movement: MovementTraits,
+    pub awareness: Awareness,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedCreature {
+    pub position: Position,
+    pub glyph: char,
+    pub color: Color,
+    pub attributes: Attributes,
+    pub fighter: Fighter,
+    pub inventory: Vec<SavedItem>,
+    /// None for the player
+    pub ai: Option<SavedAi>,
+}
+
+/// What the player last told their companions. An attack target is an index into the creatures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SavedOrder {
+    Follow,
+    Stay,
+    Attack(usize),
+}
+
+/// A game in progress
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub depth: u32,
+    pub time: Time,
+    pub player_turns: u32,
+    pub area: Rect,
+    pub start: Position,
+    /// The terrain, row by row
+    pub tiles: Vec<TileType>,
+    /// What the player remembers of each cell, row by row
+    pub memories: Vec<Option<Remembered>>,
+    pub player: SavedCreature,
+    /// Everyone else still alive
+    pub creatures: Vec<SavedCreature>,
+    /// Items lying on the floor
+    pub items: Vec<(Position, SavedItem)>,
+    pub opinions: Vec<(OpinionKey, Opinion)>,
+    pub companion_order: SavedOrder,
+}
+
+type SaveData<'a> = (
+    ReadExpect<'a, Data>,
+    Entities<'a>,
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Character>,
+    ReadStorage<'a, Attributes>,
+    ReadStorage<'a, Fighter>,
+    ReadStorage<'a, Inventory>,
+    ReadStorage<'a, Item>,
+    ReadStorage<'a, AiFlags>,
+    ReadStorage<'a, MovementTraits>,
+    ReadStorage<'a, AiState>,
+);
+
+impl SavedGame {
+    /// Copy the game out of the world
+    pub fn capture(world: &World) -> Result<SavedGame, String> {
+        let (data, entities, positions, characters, attributes, fighters, inventories, items, flags, movement, ai_states): SaveData = world.system_data();
+
+        let saved_item = |e: Entity| -> Option<SavedItem> {
+            let character = characters.get(e)?;
+            Some(SavedItem {
+                glyph: character.glyph,
+                color: character.color,
+                item: items.get(e)?.clone(),
+            })
+        };
+
+        let mut player = None;
+        let mut creatures = vec![];
+        let mut ids = vec![];
+        for (e, position, character, attributes, fighter) in (&entities, &positions, &characters, &attributes, &fighters).join() {
+            if !attributes.alive {
+                continue;
+            }
+
+            let creature = SavedCreature {
+                position: position.clone(),
+                glyph: character.glyph,
+                color: character.color,
+                attributes: attributes.clone(),
+                fighter: fighter.clone(),
+                inventory: inventories.get(e)
+                    .map(|inventory| inventory.items.iter().filter_map(|&item| saved_item(item)).collect())
+                    .unwrap_or_default(),
+                ai: match (flags.get(e), movement.get(e), ai_states.get(e)) {
+                    (Some(flags), Some(movement), Some(state)) => Some(SavedAi {
+                        flags: flags.clone(),
+                        movement: movement.clone(),
+                        awareness: state.awareness,
+                    }),
+                    _ => None,
+                },
+            };
+
+            if e == data.player {
+                player = Some(creature);
+            } else {
+                ids.push(e);
+                creatures.push(creature);
+            }
+        }
+
+        let floor_items = (&entities, &items, &positions)
+            .join()
+            .filter_map(|(e, _, position)| saved_item(e).map(|item| (position.clone(), item)))
+            .collect();
+
+        let companion_order = match data.companion_order {
+            CompanionOrder::Follow => SavedOrder::Follow,
+            CompanionOrder::Stay => SavedOrder::Stay,
+            CompanionOrder::Attack(target) => ids.iter()
+                .position(|&e| e == target)
+                .map(SavedOrder::Attack)
+                .unwrap_or(SavedOrder::Follow),
+        };
+
+        Ok(SavedGame {
+            depth: data.depth,
+            time: data.time,
+            player_turns: data.player_turns,
+            area: data.level.area.clone(),
+            start: data.level.start.clone(),
+            tiles: data.level.content.iter().map(|tile| tile.cell_type).collect(),
+            memories: data.fov.memories().to_vec(),
+            player: player.ok_or("the player is dead")?,
+            creatures: creatures,
+            items: floor_items,
+            opinions: data.factions.snapshot(),
+            companion_order: companion_order,
+        })
+    }
+
+    /// Rebuild the game in a freshly set up world. Every creature takes its next turn
+    /// one tick after the player's.
+    pub fn restore(&self, world: &mut World) -> Result<Data, String> {
+        if self.tiles.len() != self.area.area() as usize {
+            return Err(format!("{} tiles saved for a level of {}", self.tiles.len(), self.area.area()));
+        }
+
+        let mut level = Level::empty(self.area.clone());
+        level.content = self.tiles.iter().map(|&tile| Tile::new(tile)).collect();
+        level.start = self.start.clone();
+
+        let player = spawn_creature(&self.player, &mut level, world);
+        let creatures: Vec<Entity> = self.creatures
+            .iter()
+            .map(|creature| spawn_creature(creature, &mut level, world))
+            .collect();
+        for (position, item) in self.items.iter() {
+            spawn_item(item, Some(position), &mut level, world);
+        }
+
+        let mut fov = Fov::new(&level);
+        fov.restore_memories(self.memories.clone())?;
+        fov.compute(&self.player.position, self.player.attributes.vision_radius);
+
+        let mut factions = Factions::new(&FactionConfig::load(FACTIONS_FILE));
+        factions.restore(self.opinions.clone(), self.time);
+
+        let companion_order = match self.companion_order {
+            SavedOrder::Follow => CompanionOrder::Follow,
+            SavedOrder::Stay => CompanionOrder::Stay,
+            SavedOrder::Attack(i) => creatures.get(i).map(|&e| CompanionOrder::Attack(e)).unwrap_or_default(),
+        };
+
+        let (monsters, items) = level_gen::load_books();
+        let mut data = Data {
+            level,
+            fov,
+            player,
+            turn: None,
+            player_turns: 0,
+            time: self.time,
+            event_queue: GameEventQueue::default(),
+            action_queue: GameActionQueue::default(),
+            stop: false,
+            cursor: None,
+            input_mode: InputMode::Play,
+            palette: Palette::new(),
+            factions: factions,
+            deeds: Deeds::default(),
+            companion_order: companion_order,
+            depth: self.depth,
+            layout: MapLayout { start: self.start.clone(), ..Default::default() },
+            monsters: monsters,
+            items: items,
+        };
+
+        data.new_turn(GameActor::Player(player));
+        data.player_turns = self.player_turns;
+
+        for entity in creatures {
+            data.schedule_turn(Time::new(1, 0), GameActor::NonPlayer(entity));
+        }
+
+        Ok(data)
+    }
+
+    pub fn parse(text: &str) -> Result<SavedGame, String> {
+        ron::de::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn to_ron(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, Default::default()).map_err(|e| e.to_string())
+    }
+
+    pub fn read<P>(path: P) -> Result<SavedGame, String> where P: AsRef<Path> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        SavedGame::parse(&text)
+    }
+
+    pub fn write<P>(&self, path: P) -> Result<(), String> where P: AsRef<Path> {
+        fs::write(path, self.to_ron()?).map_err(|e| e.to_string())
+    }
+}
+
+/// Throw a saved game away once it's over
+pub fn delete<P>(path: P) where P: AsRef<Path> {
+    if path.as_ref().exists() {
+        if let Err(e) = fs::remove_file(path.as_ref()) {
+            warn!("could not delete the saved game {:?}: {}", path.as_ref(), e);
+        }
+    }
+}
+
+fn spawn_creature(saved: &SavedCreature, level: &mut Level, world: &mut World) -> Entity {
+    let inventory = Inventory {
+        items: saved.inventory.iter().map(|item| spawn_item(item, None, level, world)).collect(),
+    };
+
+    let builder = world.create_entity()
+        .with(saved.position.clone())
+        .with(Character { glyph: saved.glyph, color: saved.color })
+        .with(saved.attributes.clone())
+        .with(saved.fighter.clone())
+        .with(inventory);
+
+    let e = match &saved.ai {
+        Some(ai) => builder
+            .with(AiController)
+            .with(ai.flags.clone())
+            .with(ai.movement.clone())
+            .with(AiState::new(ai.awareness))
+            .build(),
+        None => builder.with(PlayerController).build(),
+    };
+
+    level.get_mut(saved.position.x, saved.position.y).entities.push(level::Entity {
+        character: saved.glyph,
+        blocked: true,
+        id: e,
+        color: saved.color,
+    });
+
+    e
+}
+
+/// Items with a position are put on the floor, the rest are carried
+fn spawn_item(saved: &SavedItem, position: Option<&Position>, level: &mut Level, world: &mut World) -> Entity {
+    let mut builder = world.create_entity()
+        .with(Character { glyph: saved.glyph, color: saved.color })
+        .with(saved.item.clone());
+    if let ItemKind::Potion { .. } = saved.item.kind {
+        builder = builder.with(Liquid { potable: true });
+    }
+
+    match position {
+        Some(position) => {
+            let e = builder.with(position.clone()).build();
+            level.get_mut(position.x, position.y).entities.push(level::Entity {
+                character: saved.glyph,
+                blocked: false,
+                id: e,
+                color: saved.color,
+            });
+            e
+        },
+        None => builder.build(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Hue;
+    use crate::game::ecs;
+    use crate::game::items::Rarity;
+
+    fn new_world() -> World {
+        let mut world = World::new();
+        ecs::setup(&mut world);
+        world
+    }
+
+    #[test]
+    fn a_saved_game_comes_back_the_same() {
+        let mut world = new_world();
+        let data = Data::new(&mut world);
+        world.insert(data);
+        let saved = SavedGame::capture(&world).unwrap();
+        assert!(!saved.creatures.is_empty());
+
+        let loaded = SavedGame::parse(&saved.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded, saved);
+
+        let mut world = new_world();
+        let data = loaded.restore(&mut world).unwrap();
+        let player = data.player;
+        assert_eq!(data.level.get(saved.player.position.x, saved.player.position.y).top_entity().map(|e| e.id), Some(player));
+        world.insert(data);
+        assert_eq!(SavedGame::capture(&world).unwrap(), saved);
+    }
+
+    #[test]
+    fn carried_items_stay_carried() {
+        let mut world = new_world();
+        let data = Data::new(&mut world);
+        world.insert(data);
+        let mut saved = SavedGame::capture(&world).unwrap();
+        let potion = SavedItem {
+            glyph: '!',
+            color: Color::new(Hue::Red, 255),
+            item: Item { name: "potion".to_owned(), rarity: Rarity::Common, kind: ItemKind::Potion { heal: 5 }, quantity: 1 },
+        };
+        saved.player.inventory.push(potion.clone());
+
+        let mut world = new_world();
+        let data = saved.restore(&mut world).unwrap();
+        let inventories = world.read_storage::<Inventory>();
+        let carried = &inventories.get(data.player).unwrap().items;
+        assert_eq!(carried.len(), 1);
+        assert_eq!(world.read_storage::<Item>().get(carried[0]), Some(&potion.item));
+        assert!(world.read_storage::<Liquid>().get(carried[0]).is_some());
+        assert!(world.read_storage::<Position>().get(carried[0]).is_none());
+    }
+}
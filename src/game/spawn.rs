@@ -0,0 +1,123 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::cmp;
+
+fn no_max_depth() -> u32 {
+    std::u32::MAX
+}
+
+/// One row of a weighted spawn table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpawnEntry<T = String> {
+    pub name: T,
+    pub weight: u32,
+    /// Added to the weight for every level deeper than `min_depth`, can be negative
+    #[serde(default)]
+    pub weight_per_depth: i32,
+    #[serde(default)]
+    pub min_depth: u32,
+    #[serde(default = "no_max_depth")]
+    pub max_depth: u32,
+}
+
+impl <T> SpawnEntry<T> {
+    pub fn new<S>(name: S, weight: u32) -> Self where S: Into<T> {
+        SpawnEntry {
+            name: name.into(),
+            weight: weight,
+            weight_per_depth: 0,
+            min_depth: 0,
+            max_depth: no_max_depth(),
+        }
+    }
+
+    pub fn weight_at(&self, depth: u32) -> u32 {
+        if depth < self.min_depth || depth > self.max_depth {
+            return 0;
+        }
+
+        let levels = (depth - self.min_depth) as i64;
+        cmp::max(0, self.weight as i64 + levels * self.weight_per_depth as i64) as u32
+    }
+}
+
+/// Picks names at random, weighted by how deep in the dungeon we are
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct SpawnTable<T = String> {
+    pub entries: Vec<SpawnEntry<T>>,
+}
+
+impl <T> Default for SpawnTable<T> {
+    fn default() -> Self {
+        SpawnTable::new(vec![])
+    }
+}
+
+impl <T> SpawnTable<T> {
+    pub fn new(entries: Vec<SpawnEntry<T>>) -> Self {
+        SpawnTable {
+            entries: entries,
+        }
+    }
+
+    pub fn total_weight(&self, depth: u32) -> u32 {
+        self.entries.iter().map(|e| e.weight_at(depth)).sum()
+    }
+
+    pub fn roll<R>(&self, depth: u32, rng: &mut R) -> Option<&T> where R: Rng {
+        let total = self.total_weight(depth);
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = rng.gen_range(0, total);
+        for entry in self.entries.iter() {
+            let weight = entry.weight_at(depth);
+            if pick < weight {
+                return Some(&entry.name);
+            }
+            pick -= weight;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn weights_scale_with_depth() {
+        let entry: SpawnEntry = SpawnEntry {
+            weight_per_depth: -10,
+            min_depth: 2,
+            max_depth: 8,
+            ..SpawnEntry::new("orc", 80)
+        };
+
+        assert_eq!(entry.weight_at(1), 0);
+        assert_eq!(entry.weight_at(2), 80);
+        assert_eq!(entry.weight_at(5), 50);
+        assert_eq!(entry.weight_at(8), 20);
+        assert_eq!(entry.weight_at(9), 0);
+    }
+
+    #[test]
+    fn it_only_rolls_entries_allowed_at_depth() {
+        let table: SpawnTable = SpawnTable::new(vec![
+            SpawnEntry { max_depth: 3, ..SpawnEntry::new("rat", 10) },
+            SpawnEntry { min_depth: 4, ..SpawnEntry::new("troll", 10) },
+        ]);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..20 {
+            assert_eq!(table.roll(1, &mut rng).map(|s| s.as_str()), Some("rat"));
+            assert_eq!(table.roll(5, &mut rng).map(|s| s.as_str()), Some("troll"));
+        }
+        assert_eq!(SpawnTable::<String>::default().roll(1, &mut rng), None);
+    }
+}
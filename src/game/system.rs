@@ -1,10 +1,15 @@
 use crate::data::Data;
 use super::ecs::*;
 use super::events::*;
-use super::level::{TileType, Level, EntityGrid};
+use super::level::{self, Level, EntityGrid};
+use super::items::{Item, ItemKind};
+use super::deeds::{Deed, DeedKind};
 use super::path::PathFinder;
 use crate::ai::Ai;
-use specs::{Entities, Entity, ReadStorage, System, Write, WriteExpect, WriteStorage};
+use crate::ai::companion::CompanionOrder;
+use crate::ai::state::AiState;
+use specs::{Entities, Entity, LazyUpdate, Read, ReadStorage, System, Write, WriteExpect, WriteStorage};
+use std::cmp;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum PlayerAction {
@@ -26,7 +31,15 @@ pub enum GameActionType {
     Pass,
     Stop,
     MoveAttack(i32, i32),
+    PickUp(Entity),
+    Drink(Entity),
+    /// Hand a carried item to someone standing next to us
+    Give(Entity, Entity),
+    /// Tell the player's companions what to do
+    Order(CompanionOrder),
     Look(i32, i32),
+    /// Move the cursor used to pick a target for the companions
+    Target(i32, i32),
     Play,
 }
 
@@ -83,13 +96,20 @@ impl<'a> System<'a> for GameSystem {
         Entities<'a>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Character>,
-        ReadStorage<'a, Attributes>,
+        WriteStorage<'a, Attributes>,
         ReadStorage<'a, Liquid>,
+        ReadStorage<'a, MovementTraits>,
+        ReadStorage<'a, AiFlags>,
+        ReadStorage<'a, Fighter>,
+        WriteStorage<'a, Inventory>,
+        ReadStorage<'a, Item>,
+        WriteStorage<'a, AiState>,
+        Read<'a, LazyUpdate>,
     );
 
     fn run(
         &mut self,
-        (mut app, mut ai, entities, mut positions, characters, attributes, liquids): Self::SystemData,
+        (mut app, mut ai, entities, mut positions, characters, mut attributes, liquids, movement_traits, ai_flags, fighters, mut inventories, items, mut ai_states, lazy): Self::SystemData,
     ) {
         //use specs::Join;
 
@@ -122,6 +142,10 @@ impl<'a> System<'a> for GameSystem {
                         &mut positions,
                         &attributes,
                         &liquids,
+                        &movement_traits,
+                        &ai_flags,
+                        &inventories,
+                        &mut ai_states,
                     );
                 }
                 _ => {}
@@ -143,10 +167,49 @@ impl<'a> System<'a> for GameSystem {
                         &entities,
                         &mut positions,
                         &characters,
+                        &mut attributes,
+                        &movement_traits,
+                        &fighters,
+                        &inventories,
+                        &ai_states,
+                        &lazy,
+                    ),
+                    GameActionType::PickUp(item) => self.pick_up(
+                        actor,
+                        item,
+                        &mut app,
+                        &mut positions,
+                        &mut inventories,
+                    ),
+                    GameActionType::Drink(item) => self.drink(
+                        actor,
+                        item,
+                        &mut app,
+                        &entities,
+                        &mut attributes,
+                        &mut inventories,
+                        &liquids,
+                        &items,
+                    ),
+                    GameActionType::Give(item, recipient) => self.give(
+                        actor,
+                        item,
+                        recipient,
+                        &mut app,
+                        &positions,
+                        &mut inventories,
+                    ),
+                    GameActionType::Order(order) => self.order_companions(
+                        actor,
+                        order,
+                        &mut app,
+                        &positions,
                         &attributes,
+                        &mut ai_states,
                     ),
                     GameActionType::Look(x, y) => {
-                        let path_finder = PathFinder::new(&app.level);
+                        let traits = movement_traits.get(actor.entity()).cloned().unwrap_or_default();
+                        let path_finder = PathFinder::new(&app.level, &traits);
                         let actor_pos = positions.get(actor.entity()).unwrap().clone();
                         let cursor_pos = if let Some(cursor) = app.cursor.clone() {
                             cursor.delta(x, y)
@@ -159,6 +222,14 @@ impl<'a> System<'a> for GameSystem {
                         app.look_mode(cursor_pos, cursor_path);
                         TurnStatus::Continue
                     },
+                    GameActionType::Target(x, y) => {
+                        let cursor_pos = match app.cursor.clone() {
+                            Some(cursor) => cursor.delta(x, y),
+                            None => positions.get(actor.entity()).cloned().unwrap_or_default(),
+                        };
+                        app.target_mode(cursor_pos);
+                        TurnStatus::Continue
+                    },
                     GameActionType::Play => {
                         app.play_mode();
                         TurnStatus::Continue
@@ -175,6 +246,7 @@ impl<'a> System<'a> for GameSystem {
                     }
                     TurnStatus::Continue => {}
                 }
+                app.remember_seen();
             }
 
             if app.actor_turn().is_none() {
@@ -183,10 +255,16 @@ impl<'a> System<'a> for GameSystem {
                  */
                 if let Some((time, game_event)) = app.next_event() {
                     info!("[{:?}] {:?}", time, game_event);
+                    app.factions.decay(time);
 
                     match game_event {
                         GameEvent::Turn(actor) => {
-                            app.new_turn(actor);
+                            let alive = attributes.get(actor.entity()).map(|a| a.alive).unwrap_or(false);
+                            if alive {
+                                app.new_turn(actor);
+                            } else {
+                                debug!("[{:?}] {:?} is dead, skipping turn", app.time, actor);
+                            }
                         }
                     }
                 } else {
@@ -214,10 +292,15 @@ impl GameSystem {
         x: i32,
         y: i32,
         app: &mut WriteExpect<'a, Data>,
-        _entities: &Entities<'a>,
+        entities: &Entities<'a>,
         positions: &mut WriteStorage<'a, Position>,
-        _characters: &ReadStorage<'a, Character>,
-        attributes: &ReadStorage<'a, Attributes>,
+        characters: &ReadStorage<'a, Character>,
+        attributes: &mut WriteStorage<'a, Attributes>,
+        movement_traits: &ReadStorage<'a, MovementTraits>,
+        fighters: &ReadStorage<'a, Fighter>,
+        inventories: &WriteStorage<'a, Inventory>,
+        ai_states: &WriteStorage<'a, AiState>,
+        lazy: &Read<'a, LazyUpdate>,
     ) -> TurnStatus {
         //use specs::Join;
 
@@ -231,15 +314,17 @@ impl GameSystem {
             x: pos.x + x,
             y: pos.y + y,
         };
-        let attrs = attributes.get(entity).unwrap();
+        let vision_radius = attributes.get(entity).unwrap().vision_radius;
+        let traits = movement_traits.get(entity).cloned().unwrap_or_default();
 
-        match Collider::new(&app.level).get(&new_pos) {
+        match Collider::new(&app.level, &traits).get(&new_pos) {
             Occupier::Empty => {
+                let cost = app.level.get(new_pos.x, new_pos.y).move_cost(&traits).unwrap_or(1);
                 EntityMover::new(&mut app.level).move_entity(entity, &mut pos, x, y);
                 if actor.is_player() {
-                    app.fov.compute(&new_pos, attrs.vision_radius);
+                    app.fov.compute(&new_pos, vision_radius);
                 }
-                TurnStatus::EndTurn(Time::default() + 1)
+                TurnStatus::EndTurn(Time::default() + cost)
             }
             Occupier::Wall => {
                 debug!(
@@ -248,13 +333,289 @@ impl GameSystem {
                 TurnStatus::Continue
             }
             Occupier::Entity(target_entity) => {
-                debug!(
-                    "[{:?}] entity {:?} interact with {:?}", app.time, entity, target_entity
-                );
+                let grudge = ai_states.get(entity).map(|m| m.grudges.contains(&target_entity)).unwrap_or(false);
+                let ally = match (attributes.get(entity), attributes.get(target_entity)) {
+                    (Some(a), Some(t)) => a.faction == t.faction || app.factions.is_friendly(&a.faction, &t.faction),
+                    _ => false,
+                };
+
+                // allies make way by swapping places, though companions don't push the player about
+                let target_traits = movement_traits.get(target_entity).cloned().unwrap_or_default();
+                let room = app.level.get(pos.x, pos.y).move_cost(&target_traits).is_some();
+                if ally && !grudge && room && (actor.is_player() || target_entity != app.player) {
+                    let cost = app.level.get(new_pos.x, new_pos.y).move_cost(&traits).unwrap_or(1);
+                    EntityMover::new(&mut app.level).move_entity(entity, &mut pos, x, y);
+                    if let Some(other) = positions.get_mut(target_entity) {
+                        EntityMover::new(&mut app.level).move_entity(target_entity, other, -x, -y);
+                    }
+                    if actor.is_player() {
+                        app.fov.compute(&new_pos, vision_radius);
+                    }
+                    debug!("[{:?}] {:?} swaps places with {:?}", app.time, entity, target_entity);
+                    return TurnStatus::EndTurn(Time::default() + cost);
+                }
+
+                // Monsters only go for those their faction hates, or who wronged them. Neutral
+                // factions only matter to them: the player bumping into anyone who isn't an ally attacks
+                // on purpose, which is how a fight with a neutral starts (and sours its opinion).
+                let hostile = match (attributes.get(entity), attributes.get(target_entity)) {
+                    (Some(a), Some(t)) => actor.is_player() || grudge || app.factions.is_hostile(&a.faction, &t.faction),
+                    _ => false,
+                };
+
+                if hostile {
+                    self.attack(entity, target_entity, &new_pos, app, entities, characters, attributes, fighters, inventories, lazy);
+                } else {
+                    debug!(
+                        "[{:?}] entity {:?} interact with {:?}", app.time, entity, target_entity
+                    );
+                }
                 TurnStatus::EndTurn(Time::default() + 1)
             }
         }
     }
+
+    /// Melee attack. Damage is the attacker's attack less the target's defense.
+    pub fn attack<'a>(
+        &mut self,
+        attacker: Entity,
+        target: Entity,
+        target_pos: &Position,
+        app: &mut WriteExpect<'a, Data>,
+        entities: &Entities<'a>,
+        characters: &ReadStorage<'a, Character>,
+        attributes: &mut WriteStorage<'a, Attributes>,
+        fighters: &ReadStorage<'a, Fighter>,
+        inventories: &WriteStorage<'a, Inventory>,
+        lazy: &Read<'a, LazyUpdate>,
+    ) {
+        let attack = fighters.get(attacker).map(|f| f.attack).unwrap_or(0);
+        let defense = fighters.get(target).map(|f| f.defense).unwrap_or(0);
+        let damage = cmp::max(0, attack - defense);
+
+        let target_attrs = match attributes.get_mut(target) {
+            Some(target_attrs) => target_attrs,
+            None => return,
+        };
+        target_attrs.hp -= damage;
+        info!(
+            "[{:?}] {:?} hits {} for {} ({}/{})", app.time, attacker, target_attrs.name, damage, target_attrs.hp, target_attrs.max_hp
+        );
+
+        let died = target_attrs.hp <= 0;
+        if died {
+            target_attrs.alive = false;
+            target_attrs.blocks = false;
+        }
+        let name = target_attrs.name.clone();
+        let victim_faction = target_attrs.faction.clone();
+
+        // word gets around
+        if let Some(attacker_faction) = attributes.get(attacker).map(|a| a.faction.clone()) {
+            let at_war = app.factions.is_hostile(&attacker_faction, &victim_faction)
+                || app.factions.is_hostile(&victim_faction, &attacker_faction);
+            let time = app.time;
+            app.deeds.record(Deed {
+                id: 0,
+                kind: if died { DeedKind::Kill } else { DeedKind::Attack },
+                actor: attacker,
+                actor_faction: attacker_faction.clone(),
+                victim: target,
+                victim_faction: victim_faction.clone(),
+                position: target_pos.clone(),
+                time: time,
+                crime: !at_war,
+            });
+
+            if died {
+                app.factions.killed(&attacker_faction, &victim_faction);
+            } else {
+                app.factions.attacked(&attacker_faction, &victim_faction);
+            }
+        }
+
+        if died {
+            self.kill(target, &name, target_pos, app, entities, characters, inventories, lazy);
+        }
+    }
+
+    /// Take a dead creature off the map and drop its loot and whatever it carried where it fell
+    fn kill<'a>(
+        &mut self,
+        target: Entity,
+        name: &str,
+        pos: &Position,
+        app: &mut WriteExpect<'a, Data>,
+        entities: &Entities<'a>,
+        characters: &ReadStorage<'a, Character>,
+        inventories: &WriteStorage<'a, Inventory>,
+        lazy: &Read<'a, LazyUpdate>,
+    ) {
+        let app: &mut Data = &mut **app;
+        info!("[{:?}] {} dies", app.time, name);
+        app.level.get_mut(pos.x, pos.y).remove_entity(target);
+
+        if target == app.player {
+            app.finish();
+            return;
+        }
+
+        for item in inventories.get(target).map(|i| i.items.clone()).unwrap_or_default() {
+            if let Some(character) = characters.get(item) {
+                lazy.insert(item, pos.clone());
+                app.level.get_mut(pos.x, pos.y).add_entity(level::Entity {
+                    character: character.glyph,
+                    blocked: false,
+                    id: item,
+                    color: character.color,
+                });
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let drops = app.monsters
+            .get(name)
+            .map(|template| template.loot.roll(&app.items, app.depth, &mut rng))
+            .unwrap_or_default();
+
+        for item in drops {
+            debug!("[{:?}] {} drops {}", app.time, name, item);
+            app.items.spawn(lazy.create_entity(entities), &item, pos, &mut app.level, &mut rng);
+        }
+
+        if let Err(e) = entities.delete(target) {
+            warn!("[{:?}] could not remove {}: {:?}", app.time, name, e);
+        }
+    }
+}
+
+impl GameSystem {
+    /// Pick up an item from the tile the actor is standing on
+    pub fn pick_up<'a>(
+        &mut self,
+        actor: GameActor,
+        item: Entity,
+        app: &mut WriteExpect<'a, Data>,
+        positions: &mut WriteStorage<'a, Position>,
+        inventories: &mut WriteStorage<'a, Inventory>,
+    ) -> TurnStatus {
+        let entity = actor.entity();
+        let here = positions.get(entity).cloned();
+        if here.is_none() || positions.get(item) != here.as_ref() {
+            debug!("[{:?}] {:?} can't reach {:?} to pick it up", app.time, entity, item);
+            return TurnStatus::Continue;
+        }
+
+        let inventory = match inventories.get_mut(entity) {
+            Some(inventory) => inventory,
+            None => return TurnStatus::Continue,
+        };
+
+        let pos = positions.remove(item).unwrap();
+        app.level.get_mut(pos.x, pos.y).remove_entity(item);
+        inventory.items.push(item);
+        info!("[{:?}] {:?} picks up {:?}", app.time, entity, item);
+
+        TurnStatus::EndTurn(Time::default() + 1)
+    }
+
+    /// Drink a carried potion, quenching thirst and healing by the potion's strength
+    pub fn drink<'a>(
+        &mut self,
+        actor: GameActor,
+        item: Entity,
+        app: &mut WriteExpect<'a, Data>,
+        entities: &Entities<'a>,
+        attributes: &mut WriteStorage<'a, Attributes>,
+        inventories: &mut WriteStorage<'a, Inventory>,
+        liquids: &ReadStorage<'a, Liquid>,
+        items: &ReadStorage<'a, Item>,
+    ) -> TurnStatus {
+        let entity = actor.entity();
+        let carried = inventories.get(entity).map(|i| i.items.contains(&item)).unwrap_or(false);
+        let potable = liquids.get(item).map(|l| l.potable).unwrap_or(false);
+        if !carried || !potable {
+            debug!("[{:?}] {:?} can't drink {:?}", app.time, entity, item);
+            return TurnStatus::Continue;
+        }
+
+        let heal = match items.get(item).map(|i| &i.kind) {
+            Some(ItemKind::Potion { heal }) => *heal,
+            _ => 0,
+        };
+
+        if let Some(attrs) = attributes.get_mut(entity) {
+            attrs.thirst = 0.0;
+            attrs.hp = cmp::min(attrs.max_hp, attrs.hp + heal);
+            info!("[{:?}] {} drinks {:?} ({}/{})", app.time, attrs.name, item, attrs.hp, attrs.max_hp);
+        }
+
+        inventories.get_mut(entity).unwrap().items.retain(|i| *i != item);
+        if let Err(e) = entities.delete(item) {
+            warn!("[{:?}] could not remove {:?}: {:?}", app.time, item, e);
+        }
+
+        TurnStatus::EndTurn(Time::default() + 1)
+    }
+
+    /// Change the orders of every companion at once. Staying companions hold where they are now.
+    pub fn order_companions<'a>(
+        &mut self,
+        actor: GameActor,
+        order: CompanionOrder,
+        app: &mut WriteExpect<'a, Data>,
+        positions: &WriteStorage<'a, Position>,
+        attributes: &WriteStorage<'a, Attributes>,
+        ai_states: &mut WriteStorage<'a, AiState>,
+    ) -> TurnStatus {
+        use specs::Join;
+
+        if !actor.is_player() {
+            return TurnStatus::Continue;
+        }
+
+        for (pos, attr, memory) in (positions, attributes, ai_states).join() {
+            if attr.faction != app.factions.player_faction {
+                continue;
+            }
+            if order == CompanionOrder::Stay {
+                memory.home = Some(pos.clone());
+            }
+            memory.plan = None;
+        }
+
+        info!("[{:?}] companions ordered to {:?}", app.time, order);
+        app.companion_order = order;
+
+        TurnStatus::EndTurn(Time::default() + 1)
+    }
+
+    pub fn give<'a>(
+        &mut self,
+        actor: GameActor,
+        item: Entity,
+        recipient: Entity,
+        app: &mut WriteExpect<'a, Data>,
+        positions: &WriteStorage<'a, Position>,
+        inventories: &mut WriteStorage<'a, Inventory>,
+    ) -> TurnStatus {
+        let entity = actor.entity();
+        let carried = inventories.get(entity).map(|i| i.items.contains(&item)).unwrap_or(false);
+        let near = match (positions.get(entity), positions.get(recipient)) {
+            (Some(from), Some(to)) => from.distance(to) <= 1,
+            _ => false,
+        };
+        if !carried || !near || inventories.get(recipient).is_none() {
+            debug!("[{:?}] {:?} can't give {:?} to {:?}", app.time, entity, item, recipient);
+            return TurnStatus::Continue;
+        }
+
+        inventories.get_mut(entity).unwrap().items.retain(|i| *i != item);
+        inventories.get_mut(recipient).unwrap().items.push(item);
+        info!("[{:?}] {:?} gives {:?} to {:?}", app.time, entity, item, recipient);
+
+        TurnStatus::EndTurn(Time::default() + 1)
+    }
 }
 
 pub struct EntityMover<'a> {
@@ -287,14 +648,25 @@ pub enum Occupier {
     Entity(Entity),
 }
 
+impl Occupier {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Occupier::Empty => true,
+            _ => false,
+        }
+    }
+}
+
 pub struct Collider<'a> {
     level_map: &'a Level,
+    traits: &'a MovementTraits,
 }
 
 impl<'a> Collider<'a> {
-    pub fn new(level_map: &'a Level) -> Self {
+    pub fn new(level_map: &'a Level, traits: &'a MovementTraits) -> Self {
         Collider {
             level_map: level_map,
+            traits: traits,
         }
     }
 
@@ -302,11 +674,10 @@ impl<'a> Collider<'a> {
         let level_cell = self.level_map.get(p.x, p.y);
         if let Some(entity) = level_cell.entities.iter().find(|e| e.blocked) {
             Occupier::Entity(entity.id)
+        } else if level_cell.move_cost(self.traits).is_none() {
+            Occupier::Wall
         } else {
-            match level_cell.cell_type {
-                TileType::Wall => Occupier::Wall,
-                _ => Occupier::Empty,
-            }
+            Occupier::Empty
         }
     }
 }
@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{self, Write};
+use specs::prelude::*;
+use crate::ai::Ai;
+use crate::ai::utility::{UtilityConfig, UTILITY_FILE};
+use crate::data::Data;
+use crate::game::ecs;
+use crate::game::system::{GameActionType, GameActor, GameSystem};
+
+/// Settings for running the game without a window
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadlessOptions {
+    /// How many player turns to run for; the player just waits
+    pub turns: u32,
+    /// Where to write the AI debug records, one JSON array per turn. `-` means stdout.
+    pub ai_dump: Option<String>,
+}
+
+impl HeadlessOptions {
+    /// Parse `--headless [turns] [--ai-dump <path>]`. None unless `--headless` was given.
+    pub fn from_args<I>(args: I) -> Option<HeadlessOptions> where I: IntoIterator<Item=String> {
+        let mut options = HeadlessOptions {
+            turns: 100,
+            ai_dump: None,
+        };
+        let mut headless = false;
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    headless = true;
+                    if let Some(turns) = args.peek().and_then(|t| t.parse().ok()) {
+                        options.turns = turns;
+                        args.next();
+                    }
+                },
+                "--ai-dump" => {
+                    options.ai_dump = Some(args.next().unwrap_or_else(|| "-".to_owned()));
+                },
+                _ => {
+                    warn!("ignoring argument {}", arg);
+                },
+            }
+        }
+
+        if headless {
+            Some(options)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn run(options: &HeadlessOptions) -> io::Result<()> {
+    let mut world = World::new();
+
+    let mut dispatcher = DispatcherBuilder::new()
+        .with(GameSystem::new(), "game", &[])
+        .build();
+
+    dispatcher.setup(&mut world);
+
+    ecs::setup(&mut world);
+
+    let data = Data::new(&mut world);
+    world.insert(data);
+
+    let mut ai = Ai::new(UtilityConfig::load(UTILITY_FILE));
+    ai.debug = options.ai_dump.is_some();
+    world.insert(ai);
+
+    let mut out: Box<dyn Write> = match options.ai_dump.as_ref().map(|p| p.as_str()) {
+        Some("-") => Box::new(io::stdout()),
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::sink()),
+    };
+
+    for _ in 0..options.turns {
+        {
+            let mut data = world.write_resource::<Data>();
+            if data.is_finished() {
+                break;
+            }
+            let player = data.player;
+            data.action(GameActor::Player(player), GameActionType::Pass);
+        }
+
+        dispatcher.dispatch(&world);
+
+        world.maintain();
+
+        let mut ai = world.write_resource::<Ai>();
+        if ai.debug {
+            let json = ai.dump_json().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            writeln!(out, "{}", json)?;
+            ai.records.clear();
+        }
+    }
+
+    info!("headless run finished after {} turns", world.read_resource::<Data>().turns());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn it_parses_headless_options() {
+        assert_eq!(HeadlessOptions::from_args(args(&[])), None);
+        assert_eq!(
+            HeadlessOptions::from_args(args(&["--headless", "20", "--ai-dump", "ai.json"])),
+            Some(HeadlessOptions { turns: 20, ai_dump: Some("ai.json".to_owned()) })
+        );
+        assert_eq!(
+            HeadlessOptions::from_args(args(&["--headless"])),
+            Some(HeadlessOptions { turns: 100, ai_dump: None })
+        );
+    }
+}
@@ -1,32 +1,62 @@
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
-use piston::input::*;
-use piston::input::keyboard::ModifierKey;
+use quicksilver::input::{ButtonState, Key};
+use quicksilver::lifecycle::Event;
+use serde::Deserialize;
+use crate::charmap::{self, Layout};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct KeyboardKey {
-    pub button: Button,
-    pub scancode: Option<i32>,
+/// Names for the keys that don't type anything, as used in the key bindings file
+pub const KEY_NAMES: &[(Key, &str)] = &[
+    (Key::Escape, "Escape"), (Key::Return, "Enter"), (Key::Back, "Backspace"), (Key::Tab, "Tab"),
+    (Key::Space, "Space"), (Key::Insert, "Insert"), (Key::Delete, "Delete"),
+    (Key::Home, "Home"), (Key::End, "End"), (Key::PageUp, "PageUp"), (Key::PageDown, "PageDown"),
+    (Key::Left, "Left"), (Key::Right, "Right"), (Key::Up, "Up"), (Key::Down, "Down"),
+    (Key::F1, "F1"), (Key::F2, "F2"), (Key::F3, "F3"), (Key::F4, "F4"), (Key::F5, "F5"), (Key::F6, "F6"),
+    (Key::F7, "F7"), (Key::F8, "F8"), (Key::F9, "F9"), (Key::F10, "F10"), (Key::F11, "F11"), (Key::F12, "F12"),
+    (Key::Numpad0, "Numpad0"), (Key::Numpad1, "Numpad1"), (Key::Numpad2, "Numpad2"), (Key::Numpad3, "Numpad3"),
+    (Key::Numpad4, "Numpad4"), (Key::Numpad5, "Numpad5"), (Key::Numpad6, "Numpad6"), (Key::Numpad7, "Numpad7"),
+    (Key::Numpad8, "Numpad8"), (Key::Numpad9, "Numpad9"), (Key::Add, "NumpadAdd"), (Key::Subtract, "NumpadSubtract"),
+    (Key::Multiply, "NumpadMultiply"), (Key::Divide, "NumpadDivide"), (Key::Decimal, "NumpadDecimal"),
+    (Key::NumpadEnter, "NumpadEnter"),
+];
+
+/// The key with the given name, ignoring case
+pub fn key_from_name(name: &str) -> Option<Key> {
+    KEY_NAMES.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)).map(|&(key, _)| key)
 }
 
-impl From<ButtonArgs> for KeyboardKey {
-    fn from(ba: ButtonArgs) -> KeyboardKey {
-        KeyboardKey {
-            button: ba.button,
-            scancode: ba.scancode,
-        }
-    }
+pub fn key_name(key: Key) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|(k, _)| *k == key).map(|&(_, name)| name)
 }
 
-impl From<&ButtonArgs> for KeyboardKey {
-    fn from(ba: &ButtonArgs) -> KeyboardKey {
-        KeyboardKey {
-            button: ba.button,
-            scancode: ba.scancode,
+/// Modifier keys held down alongside a key
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { ctrl: false, shift: false, alt: false };
+
+    /// Track a modifier key going up or down. Returns false if the key isn't a modifier.
+    pub fn event(&mut self, key: Key, down: bool) -> bool {
+        match key {
+            Key::LControl | Key::RControl => self.ctrl = down,
+            Key::LShift | Key::RShift => self.shift = down,
+            Key::LAlt | Key::RAlt => self.alt = down,
+            _ => return false,
         }
+        true
     }
 }
 
+#[cfg(test)]
+impl Modifiers {
+    pub const CTRL: Modifiers = Modifiers { ctrl: true, shift: false, alt: false };
+    pub const SHIFT: Modifiers = Modifiers { ctrl: false, shift: true, alt: false };
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum InputEventType {
     KeyDown,
@@ -34,41 +64,27 @@ pub enum InputEventType {
     KeyUp,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum InputEventKey {
     KeyboardKey {
         key: Key,
-        scancode: Option<i32>,
-        character:  Option<char>,
-        modifiers: ModifierKey,
+        /// What the key types, given the modifiers, if anything
+        character: Option<char>,
+        modifiers: Modifiers,
     },
-    Controller(ControllerButton),
-    Hat(ControllerHat),
-    Mouse(MouseButton),
 }
 
 impl InputEventKey {
-    pub fn new(k: KeyboardKey, modifiers: ModifierKey) -> InputEventKey {
-        match k.button {
-            Button::Keyboard(key) => {
-                let c = crate::charmap::modify(key.code(), k.scancode, modifiers);
-
-                InputEventKey::KeyboardKey {
-                    key: key,
-                    scancode: k.scancode,
-                    character: c,
-                    modifiers: modifiers
-                }
-            },
-            Button::Controller(button) => {
-                InputEventKey::Controller(button)
-            },
-            Button::Hat(button) => {
-                InputEventKey::Hat(button)
-            },
-            Button::Mouse(button) => {
-                InputEventKey::Mouse(button)
-            }
+    /// A key typing whatever the layout says it does
+    pub fn new(key: Key, modifiers: Modifiers, layout: Layout) -> InputEventKey {
+        InputEventKey::with_character(key, layout.modify(key, modifiers), modifiers)
+    }
+
+    pub fn with_character(key: Key, character: Option<char>, modifiers: Modifiers) -> InputEventKey {
+        InputEventKey::KeyboardKey {
+            key: key,
+            character: character,
+            modifiers: modifiers,
         }
     }
 }
@@ -76,29 +92,69 @@ impl InputEventKey {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct InputEvent {
     pub state: InputEventType,
-    pub modifiers: keyboard::ModifierKey,
+    pub modifiers: Modifiers,
     pub key: InputEventKey,
 }
 
+/// How held keys repeat, in seconds
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct KeyRepeat {
+    pub enabled: bool,
+    /// How long a key has to be held before it starts repeating
+    pub initial_delay: f64,
+    /// Time between repeats after that
+    pub delay: f64,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        KeyRepeat {
+            enabled: true,
+            initial_delay: 0.2,
+            delay: 0.1,
+        }
+    }
+}
+
+#[derive(Debug)]
 struct KeyRepeatTimer {
     pub button_repeat_delay: f64,
     pub elapsed: f64,
+    /// Stopped timers stay stopped until the key is pressed again
+    pub stopped: bool,
+    /// When the key went down, counted in key presses
+    pub pressed_at: u64,
+    /// What the key typed, so repeats type it too
+    pub character: Option<char>,
 }
 
 impl KeyRepeatTimer {
-    pub fn new(delay: f64) -> Self {
+    pub fn new(delay: f64, pressed_at: u64, character: Option<char>) -> Self {
         KeyRepeatTimer {
             button_repeat_delay: delay,
             elapsed: 0.0,
+            stopped: false,
+            pressed_at: pressed_at,
+            character: character,
         }
     }
 }
 
+/// Turns quicksilver's window events into key downs, repeats and ups, keeping track of the modifiers
+#[derive(Debug)]
 pub struct InputHandler {
-    pressed: HashMap<KeyboardKey,KeyRepeatTimer>,
+    pressed: HashMap<Key, KeyRepeatTimer>,
+    /// Key presses so far, to tell which held key went down last
+    presses: u64,
+    pub button_repeat: bool,
     pub button_repeat_delay: f64,
     pub button_repeat_initial_delay: f64,
-    pub modifier_keys: keyboard::ModifierKey,
+    pub modifier_keys: Modifiers,
+    /// Decides what character each key types when the window doesn't say
+    pub layout: Layout,
+    /// A key that types something, waiting for the window to say what
+    pending: Option<(Key, Modifiers)>,
 }
 
 impl Default for InputHandler {
@@ -108,87 +164,310 @@ impl Default for InputHandler {
 }
 
 impl InputHandler {
+    /// Held keys repeat after the first delay, then every second delay, in seconds
     pub fn new(button_repeat_delay: (f64, f64)) -> Self {
         let (initial, thereafter) = button_repeat_delay;
 
         InputHandler {
             pressed: HashMap::new(),
+            presses: 0,
+            button_repeat: true,
             button_repeat_initial_delay: initial,
             button_repeat_delay: thereafter,
-            modifier_keys: keyboard::ModifierKey::default(),
+            modifier_keys: Modifiers::NONE,
+            layout: Layout::default(),
+            pending: None,
         }
     }
 
-    pub fn is_pressed(&self, button: &ButtonArgs) -> bool {
-        self.pressed.contains_key(&KeyboardKey::from(button))
+    /// The keyboard layout for backends that don't say what was typed
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
     }
 
-    pub fn press(&mut self, button: &ButtonArgs) -> Vec<InputEvent> {
-        let mut events = vec![];
-        match self.pressed.entry(KeyboardKey::from(button)) {
-            Entry::Vacant(v) => {
-                events.push(InputEvent {
-                    state: InputEventType::KeyDown,
-                    key: InputEventKey::new(*v.key(), self.modifier_keys),
-                    modifiers: self.modifier_keys
-                });
-                v.insert(KeyRepeatTimer::new(self.button_repeat_initial_delay));                
-            },
-            _ => {}
+    pub fn with_repeat(mut self, repeat: KeyRepeat) -> Self {
+        self.button_repeat = repeat.enabled;
+        self.button_repeat_initial_delay = repeat.initial_delay;
+        self.button_repeat_delay = repeat.delay;
+        self
+    }
+
+    /// A key went down. Keys that type something wait for the window to say what before
+    /// they're sent on.
+    pub fn press(&mut self, key: Key) -> Vec<InputEvent> {
+        let mut events = self.flush();
+        if !self.pressed.contains_key(&key) {
+            self.presses += 1;
+            let character = self.layout.modify(key, self.modifier_keys);
+            self.pressed.insert(key, KeyRepeatTimer::new(self.button_repeat_initial_delay, self.presses, character));
+            if character.is_some() {
+                self.pending = Some((key, self.modifier_keys));
+            } else {
+                events.push(input_event(InputEventType::KeyDown, key, None, self.modifier_keys));
+            }
         }
         events
     }
 
-    pub fn unpress(&mut self, button: &ButtonArgs) -> Vec<InputEvent> {
-        let mut events = vec![];
-        if self.pressed.remove(&KeyboardKey::from(button)).is_some() {
-            events.push(InputEvent {
-                state: InputEventType::KeyUp,
-                key: InputEventKey::new(KeyboardKey::from(button), self.modifier_keys),
-                modifiers: self.modifier_keys
-            });
+    pub fn unpress(&mut self, key: Key) -> Vec<InputEvent> {
+        let mut events = self.flush();
+        if let Some(timer) = self.pressed.remove(&key) {
+            events.push(input_event(InputEventType::KeyUp, key, timer.character, self.modifier_keys));
         }
         events
     }
 
     pub fn event(&mut self, e: &Event) -> Vec<InputEvent> {
-        let mut events = vec![];
-        self.modifier_keys.event(e);
-
-        if let Some(b) = e.button_args() {
-            events.append(&mut self.button(&b));
+        match *e {
+            Event::Key(key, ButtonState::Pressed) => {
+                if self.modifier_keys.event(key, true) {
+                    vec![]
+                } else {
+                    self.press(key)
+                }
+            },
+            Event::Key(key, ButtonState::Released) => {
+                if self.modifier_keys.event(key, false) {
+                    vec![]
+                } else {
+                    self.unpress(key)
+                }
+            },
+            Event::Typed(c) => self.typed(c),
+            Event::Unfocused => {
+                self.clear();
+                vec![]
+            },
+            _ => vec![],
         }
+    }
 
-        if let Some(u) = e.update_args() {
-            events.append(&mut self.update(&u));
+    /// Advance the repeat timer of the key held down by dt seconds. Only the key pressed
+    /// last repeats, so holding two keys at once still goes one predictable way.
+    /// A key still waiting for its character is sent on with what the layout says it types.
+    pub fn update(&mut self, dt: f64) -> Vec<InputEvent> {
+        let mut events = self.flush();
+        if !self.button_repeat {
+            return events;
         }
 
+        let last = self.pressed.iter_mut().max_by_key(|(_, timer)| timer.pressed_at);
+        if let Some((key, timer)) = last.filter(|(_, timer)| !timer.stopped) {
+            timer.elapsed += dt;
+
+            if timer.elapsed >= timer.button_repeat_delay {
+                timer.elapsed -= timer.button_repeat_delay;
+                timer.button_repeat_delay = self.button_repeat_delay;
+
+                events.push(input_event(InputEventType::KeyRepeat, *key, timer.character, self.modifier_keys));
+            }
+        }
         events
     }
 
-    pub fn button(&mut self, args: &ButtonArgs) -> Vec<InputEvent> {
-        match args.state {
-            ButtonState::Press => {
-                self.press(args)
-            },
-            ButtonState::Release => {
-                self.unpress(args)
-            },
+    /// Stop the keys held down from repeating until they're pressed again
+    pub fn stop_repeat(&mut self) {
+        for timer in self.pressed.values_mut() {
+            timer.stopped = true;
         }
     }
 
-    pub fn update(&mut self, args: &UpdateArgs) -> Vec<InputEvent> {
-        let mut events = vec![];
-        for (k,v) in self.pressed.iter_mut() {
-            v.elapsed += args.dt;
+    /// The window says what the key waiting for its character typed. Characters typed by
+    /// anything else, such as the OS repeating a key, are ignored.
+    fn typed(&mut self, c: char) -> Vec<InputEvent> {
+        let (key, modifiers) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return vec![],
+        };
 
-            if v.elapsed >= v.button_repeat_delay {
-                v.elapsed -= v.button_repeat_delay;
-                v.button_repeat_delay = self.button_repeat_delay;
+        let character = charmap::typed(c, modifiers).or_else(|| self.layout.modify(key, modifiers));
+        if let Some(timer) = self.pressed.get_mut(&key) {
+            timer.character = character;
+        }
+        vec![input_event(InputEventType::KeyDown, key, character, modifiers)]
+    }
 
-                events.push(InputEvent { state: InputEventType::KeyRepeat, key: InputEventKey::new(*k, self.modifier_keys), modifiers: self.modifier_keys });
-            }
+    /// Send on the key waiting for its character, with what the layout says it types
+    fn flush(&mut self) -> Vec<InputEvent> {
+        match self.pending.take() {
+            Some((key, modifiers)) => vec![InputEvent {
+                state: InputEventType::KeyDown,
+                key: InputEventKey::new(key, modifiers, self.layout),
+                modifiers: modifiers,
+            }],
+            None => vec![],
         }
-        events
+    }
+
+    /// Forget everything held down, e.g. when the window loses focus
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+        self.pending = None;
+        self.modifier_keys = Modifiers::NONE;
+    }
+}
+
+fn input_event(state: InputEventType, key: Key, character: Option<char>, modifiers: Modifiers) -> InputEvent {
+    InputEvent {
+        state: state,
+        key: InputEventKey::with_character(key, character, modifiers),
+        modifiers: modifiers,
+    }
+}
+
+/// Stops held keys repeating when something new turns up, such as a hostile coming into view
+#[derive(Debug)]
+pub struct RepeatGuard<T> {
+    seen: Vec<T>,
+}
+
+impl <T> Default for RepeatGuard<T> {
+    fn default() -> Self {
+        RepeatGuard {
+            seen: vec![],
+        }
+    }
+}
+
+impl <T> RepeatGuard<T> where T: PartialEq {
+    /// Note what is around now, stopping the repeat if any of it wasn't there last time
+    pub fn update(&mut self, now: Vec<T>, input: &mut InputHandler) {
+        if now.iter().any(|t| !self.seen.contains(t)) {
+            input.stop_repeat();
+        }
+        self.seen = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repeats(events: Vec<InputEvent>) -> usize {
+        events.iter().filter(|e| e.state == InputEventType::KeyRepeat).count()
+    }
+
+    #[test]
+    fn held_keys_repeat_after_a_delay() {
+        let mut input = InputHandler::new((0.5, 0.1));
+
+        assert_eq!(input.event(&Event::Key(Key::Left, ButtonState::Pressed)).len(), 1);
+        assert_eq!(repeats(input.update(0.4)), 0);
+        assert_eq!(repeats(input.update(0.1)), 1);
+        assert_eq!(repeats(input.update(0.05)), 0);
+        assert_eq!(repeats(input.update(0.05)), 1);
+
+        input.event(&Event::Key(Key::Left, ButtonState::Released));
+        assert_eq!(repeats(input.update(1.0)), 0);
+    }
+
+    #[test]
+    fn stopped_keys_repeat_again_once_pressed_again() {
+        let mut input = InputHandler::new((0.1, 0.1));
+
+        input.event(&Event::Key(Key::Left, ButtonState::Pressed));
+        assert_eq!(repeats(input.update(0.1)), 1);
+
+        input.stop_repeat();
+        assert_eq!(repeats(input.update(1.0)), 0);
+
+        input.event(&Event::Key(Key::Left, ButtonState::Released));
+        input.event(&Event::Key(Key::Left, ButtonState::Pressed));
+        assert_eq!(repeats(input.update(0.1)), 1);
+    }
+
+    #[test]
+    fn only_the_key_pressed_last_repeats() {
+        let mut input = InputHandler::new((0.1, 0.1));
+        let repeated = |events: Vec<InputEvent>| -> Vec<Key> {
+            events.into_iter()
+                .filter(|e| e.state == InputEventType::KeyRepeat)
+                .map(|e| match e.key { InputEventKey::KeyboardKey { key, .. } => key })
+                .collect()
+        };
+
+        input.event(&Event::Key(Key::Left, ButtonState::Pressed));
+        input.event(&Event::Key(Key::Up, ButtonState::Pressed));
+        for _ in 0..5 {
+            assert_eq!(repeated(input.update(0.1)), vec![Key::Up]);
+        }
+
+        input.event(&Event::Key(Key::Up, ButtonState::Released));
+        assert_eq!(repeated(input.update(0.1)), vec![Key::Left]);
+    }
+
+    #[test]
+    fn a_new_arrival_stops_the_repeat() {
+        let mut input = InputHandler::new((0.1, 0.1));
+        let mut guard = RepeatGuard::default();
+        input.event(&Event::Key(Key::Left, ButtonState::Pressed));
+
+        guard.update(vec!["orc"], &mut input);
+        assert_eq!(repeats(input.update(0.1)), 0);
+
+        // the same orc staying in view doesn't stop the key again once it's pressed again
+        input.event(&Event::Key(Key::Left, ButtonState::Released));
+        input.event(&Event::Key(Key::Left, ButtonState::Pressed));
+        guard.update(vec!["orc"], &mut input);
+        assert_eq!(repeats(input.update(0.1)), 1);
+
+        // one leaving doesn't stop it either, but another turning up does
+        guard.update(vec![], &mut input);
+        assert_eq!(repeats(input.update(0.1)), 1);
+        guard.update(vec!["goblin"], &mut input);
+        assert_eq!(repeats(input.update(0.1)), 0);
+    }
+
+    #[test]
+    fn characters_come_from_what_the_window_says_was_typed() {
+        let mut input = InputHandler::default().with_layout(Layout::De);
+        let typed = |events: Vec<InputEvent>| -> Vec<Option<char>> {
+            events.into_iter()
+                .filter(|e| e.state != InputEventType::KeyUp)
+                .map(|e| match e.key { InputEventKey::KeyboardKey { character, .. } => character })
+                .collect()
+        };
+
+        // a German keyboard already reports its z key as Z, so it isn't moved again
+        assert_eq!(typed(input.event(&Event::Key(Key::Z, ButtonState::Pressed))), vec![]);
+        assert_eq!(typed(input.event(&Event::Typed('z'))), vec![Some('z')]);
+        input.event(&Event::Key(Key::Z, ButtonState::Released));
+
+        // ctrl types control codes
+        input.event(&Event::Key(Key::LControl, ButtonState::Pressed));
+        input.event(&Event::Key(Key::X, ButtonState::Pressed));
+        assert_eq!(typed(input.event(&Event::Typed('\u{18}'))), vec![Some('x')]);
+        input.event(&Event::Key(Key::X, ButtonState::Released));
+        input.event(&Event::Key(Key::LControl, ButtonState::Released));
+
+        // keys that don't type anything go straight through
+        assert_eq!(typed(input.event(&Event::Key(Key::Left, ButtonState::Pressed))), vec![None]);
+        input.event(&Event::Key(Key::Left, ButtonState::Released));
+
+        // and without a typed character the layout decides, by the next update
+        input.event(&Event::Key(Key::Y, ButtonState::Pressed));
+        assert_eq!(typed(input.update(0.0)), vec![Some('z')]);
+        assert_eq!(typed(input.event(&Event::Typed('z'))), vec![]);
+    }
+
+    #[test]
+    fn repeats_type_what_the_key_typed() {
+        let mut input = InputHandler::new((0.1, 0.1));
+
+        input.event(&Event::Key(Key::Y, ButtonState::Pressed));
+        input.event(&Event::Typed('z'));
+        let repeated: Vec<Option<char>> = input.update(0.1).into_iter()
+            .map(|e| match e.key { InputEventKey::KeyboardKey { character, .. } => character })
+            .collect();
+        assert_eq!(repeated, vec![Some('z')]);
+    }
+
+    #[test]
+    fn repeat_can_be_turned_off() {
+        let mut input = InputHandler::default().with_repeat(KeyRepeat { enabled: false, ..KeyRepeat::default() });
+
+        input.event(&Event::Key(Key::Left, ButtonState::Pressed));
+        assert_eq!(repeats(input.update(10.0)), 0);
     }
 }
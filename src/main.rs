@@ -9,6 +9,13 @@ pub mod data;
 pub mod game;
 pub mod color;
 pub mod tween;
+pub mod camera;
+pub mod headless;
+pub mod input;
+pub mod chords;
+pub mod commands;
+pub mod charmap;
+pub mod data_file;
 
 use quicksilver::lifecycle::{run, Settings};
 use quicksilver::graphics::{ImageScaleStrategy, ResizeStrategy};
@@ -21,6 +28,14 @@ fn main() {
 
     env_logger::init();
 
+    if let Some(options) = headless::HeadlessOptions::from_args(std::env::args().skip(1)) {
+        info!("starting headless");
+        if let Err(e) = headless::run(&options) {
+            error!("headless run failed: {}", e);
+        }
+        return;
+    }
+
     let settings = Settings {
         // If the graphics do need to be scaled (e.g. using
         // `with_center`), blur them. This looks better with fonts.
@@ -1,28 +1,38 @@
-use std::rc::Rc;
+use std::mem;
 use quicksilver::prelude::*;
 use quicksilver::graphics::View;
 use specs::prelude::*;
 use crate::ai::Ai;
-use crate::scene::Scene;
+use crate::ai::utility::{UtilityConfig, UTILITY_FILE};
+use crate::camera::{self, Camera};
+use crate::chords::{self, ChordResult};
+use crate::commands::{Command, Commands, KeyConfig, KEYS_FILE};
+use crate::input::{InputEvent, InputEventType, InputHandler, RepeatGuard};
+use crate::scene::SceneStack;
 use crate::data::Data;
-use crate::game::ecs;
-use crate::game::system::{GameActionType, GameActor, GameSystem};
-use crate::qs_ui::Tileset;
+use crate::game::ecs::{self, Attributes, Position};
+use crate::game::save::{SavedGame, SAVE_FILE};
+use crate::game::system::GameSystem;
+use crate::qs_ui::{MinimapMode, Tileset, WhichKey, Widget};
 use crate::data::GameText;
 
-type SystemData<'a> = (WriteExpect<'a, Data>,);
-
 pub struct Game<'a, 'b> {
-    pub scene: Rc<Scene>,
+    pub scenes: SceneStack,
+    pub input: InputHandler,
+    /// Stops held keys when a hostile comes into view
+    hostiles: RepeatGuard<Entity>,
+    pub commands: Commands,
+    /// Commands typed since the last update
+    queued: Vec<Command>,
     dispatcher: Dispatcher<'a, 'b>,
     pub world: World,
     pub tileset: Asset<Tileset>,
     pub text: Asset<GameText>,
 }
 
-impl State for Game<'static, 'static> {
-    /// Load the assets and initialise the game
-    fn new() -> Result<Self> {
+impl <'a, 'b> Game<'a, 'b> {
+    /// An empty world and the systems that run it
+    fn empty_world() -> (World, Dispatcher<'a, 'b>) {
         let mut world = World::new();
 
         let mut dispatcher = DispatcherBuilder::new()
@@ -33,66 +43,136 @@ impl State for Game<'static, 'static> {
 
         ecs::setup(&mut world);
 
+        world.insert(Ai::new(UtilityConfig::load(UTILITY_FILE)));
+        world.insert(Camera::default()
+            .with_dead_zone(camera::DEAD_ZONE.0, camera::DEAD_ZONE.1)
+            .with_smoothing(camera::SCROLL_FACTOR, camera::SCROLL_MIN_STEP));
+        world.insert(MinimapMode::default());
+
+        (world, dispatcher)
+    }
+
+    /// A fresh world with a newly generated level
+    fn new_world() -> (World, Dispatcher<'a, 'b>) {
+        let (mut world, dispatcher) = Game::empty_world();
         let data = Data::new(&mut world);
         world.insert(data);
+        (world, dispatcher)
+    }
 
-        world.insert(Ai::default());
-        
-        let scene = Scene::default();
+    /// Switch to another game
+    fn play(&mut self, world: World, dispatcher: Dispatcher<'a, 'b>) {
+        self.world = world;
+        self.dispatcher = dispatcher;
+        self.hostiles = RepeatGuard::default();
+    }
+
+    /// Throw the current game away and start a new one
+    pub fn new_game(&mut self) {
+        let (world, dispatcher) = Game::new_world();
+        self.play(world, dispatcher);
+    }
+
+    /// Throw the current game away and pick up the saved one. If it can't be loaded,
+    /// the current game carries on.
+    pub fn load_game(&mut self) -> std::result::Result<(), String> {
+        let saved = SavedGame::read(SAVE_FILE)?;
+        let (mut world, dispatcher) = Game::empty_world();
+        let data = saved.restore(&mut world)?;
+        world.insert(data);
+        self.play(world, dispatcher);
+        Ok(())
+    }
+
+    /// Save the game in progress, to be loaded from the main menu
+    pub fn save_game(&self) -> std::result::Result<(), String> {
+        SavedGame::capture(&self.world)?.write(SAVE_FILE)
+    }
+}
+
+impl State for Game<'static, 'static> {
+    /// Load the assets and initialise the game
+    fn new() -> Result<Self> {
+        let (world, dispatcher) = Game::new_world();
+        let keys = KeyConfig::load(KEYS_FILE);
+        let glyphs = world.read_resource::<Data>().glyphs();
 
         Ok(Self {
             dispatcher,
             world: world,
-            scene: Rc::new(scene),
+            scenes: SceneStack::new(Default::default()),
+            hostiles: RepeatGuard::default(),
+            input: InputHandler::default().with_layout(keys.keyboard_layout()).with_repeat(keys.repeat),
+            commands: Commands::configure(&keys),
+            queued: vec![],
             text: GameText::load(),
-            tileset: Tileset::load(),
+            tileset: Tileset::load(glyphs),
         })
     }
 
-    /// Process keyboard and mouse, update the game state
-    fn update(&mut self, window: &mut Window) -> Result<()> {
-        use quicksilver::input::ButtonState::*;
-
-        {
-            let (mut data,): SystemData = self.world.system_data();
-            let player = data.player;
+    /// Turn key presses into commands, using the bindings for the top scene
+    fn event(&mut self, event: &Event, _window: &mut Window) -> Result<()> {
+        self.commands.set_mode(self.scenes.mode(&self.world));
 
-            if window.keyboard()[Key::Left] == Pressed {
-                data.action(GameActor::Player(player), GameActionType::MoveAttack(-1, 0));
-            }
-            if window.keyboard()[Key::Right] == Pressed {
-                data.action(GameActor::Player(player), GameActionType::MoveAttack(1, 0));
-            }
-            if window.keyboard()[Key::Up] == Pressed {
-                data.action(GameActor::Player(player), GameActionType::MoveAttack(0, -1));
-            }
-            if window.keyboard()[Key::Down] == Pressed {
-                data.action(GameActor::Player(player), GameActionType::MoveAttack(0, 1));
+        for input in self.input.event(event) {
+            if let Some(ChordResult::Action(command)) = self.commands.key_event(input.state, input.key) {
+                self.queued.push(command);
             }
+        }
 
-            if window.keyboard()[Key::Escape].is_down() {
-                data.stop = true;
-            }
+        Ok(())
+    }
 
-            if window.keyboard()[Key::X].is_down() {
-                self.text.execute(|text| {
-                    let inventory = text.font.render(
-                        "Inventory:\n[A] Dagger\n[B] Buckler",
-                        &FontStyle::new(20.0, Color::BLACK),
-                    );
-                    text.inventory = inventory?;
-                    Ok(())
-                })?;
+    /// Let the top scene handle the commands, then update the game state
+    fn update(&mut self, window: &mut Window) -> Result<()> {
+        // Keys still waiting for the character they typed go through first. Held keys repeat,
+        // but only into an empty queue: a repeat never piles up behind other commands, so the
+        // player can't get ahead of the turns
+        self.commands.set_mode(self.scenes.mode(&self.world));
+        let (repeats, pressed): (Vec<InputEvent>, Vec<InputEvent>) = self.input.update(window.update_rate() / 1000.0)
+            .into_iter()
+            .partition(|input| input.state == InputEventType::KeyRepeat);
+        for input in pressed {
+            if let Some(ChordResult::Action(command)) = self.commands.key_event(input.state, input.key) {
+                self.queued.push(command);
             }
+        }
+        if self.queued.is_empty() {
+            let repeated = repeats.into_iter()
+                .filter_map(|input| match self.commands.key_event(input.state, input.key) {
+                    Some(ChordResult::Action(command)) => Some(command),
+                    _ => None,
+                })
+                .next();
+            self.queued.extend(repeated);
+        }
 
-            if data.stop {
-                window.close();
-            }
+        let commands = mem::replace(&mut self.queued, vec![]);
+
+        // the scenes get the game to themselves while they run
+        let mut scenes = mem::replace(&mut self.scenes, SceneStack::default());
+        let result = scenes.update(commands, self);
+        self.scenes = scenes;
+        result?;
+
+        if self.scenes.is_empty() {
+            window.close();
+            return Ok(());
         }
 
-        self.dispatcher.dispatch(&self.world);
+        if self.scenes.is_playing() {
+            self.dispatcher.dispatch(&self.world);
+
+            self.world.maintain();
 
-        self.world.maintain();
+            // checked straight after the turns run, so a hostile they reveal stops held keys
+            // before the next update can repeat them
+            let hostiles = {
+                let (data, entities, positions, attributes): (ReadExpect<Data>, Entities, ReadStorage<Position>, ReadStorage<Attributes>) = self.world.system_data();
+                data.fov.hostiles_in_view(data.player, &data.factions, &entities, &positions, &attributes)
+            };
+            self.hostiles.update(hostiles, &mut self.input);
+        }
 
         Ok(())
     }
@@ -101,9 +181,21 @@ impl State for Game<'static, 'static> {
     fn draw(&mut self, window: &mut Window) -> Result<()> {
         window.set_view(View::new(Rectangle::new(Vector::ZERO, window.screen_size())));
 
-        let scene = self.scene.clone();
-
-        scene.draw(window, self)?;
+        let scenes = mem::replace(&mut self.scenes, SceneStack::default());
+        let result = scenes.draw(window, self);
+        self.scenes = scenes;
+        result?;
+
+        // halfway through a chord, show what can come next
+        if let Some((keys, next)) = self.commands.pending() {
+            let screen = Rectangle::new_sized(window.screen_size());
+            let entries = next.into_iter().map(|(key, name)| (key.to_string(), name)).collect();
+            self.text.execute(|text| {
+                WhichKey::new(chords::chord_to_string(&keys), entries, &text.font)
+                    .with_area(screen)
+                    .draw(window)
+            })?;
+        }
 
         Ok(())
     }
@@ -0,0 +1,133 @@
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::{Rectangle, Vector};
+use quicksilver::graphics::{Background, Color, Font, FontStyle};
+use quicksilver::Result;
+use tui::layout::{Constraint, Direction, Layout};
+use super::{Label, LayoutRect, Tileset, Widget};
+
+/// Width of the side panel in pixels
+pub const HUD_WIDTH: u16 = 240;
+
+const TEXT_SIZE: f32 = 16.0;
+const ROW_HEIGHT: u16 = 24;
+const BAR_HEIGHT: f32 = 8.0;
+const MARGIN: u16 = 8;
+
+/// A hostile creature the player can see
+#[derive(Debug, Clone, PartialEq)]
+pub struct HudHostile {
+    pub glyph: char,
+    pub color: Color,
+    pub name: String,
+    pub hp: i32,
+    pub max_hp: i32,
+}
+
+/// Everything the side panel shows
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HudStats {
+    pub hp: i32,
+    pub max_hp: i32,
+    /// 0 (quenched) to 1 (parched)
+    pub thirst: f32,
+    pub turns: u32,
+    /// Game time, already formatted
+    pub time: String,
+    pub depth: u32,
+    /// Nearest first
+    pub hostiles: Vec<HudHostile>,
+}
+
+/// Side panel with the player's stats and the hostiles in view
+pub struct Hud<'a> {
+    pub area: Rectangle,
+    pub stats: &'a HudStats,
+    pub font: &'a Font,
+    pub tileset: &'a Tileset,
+}
+
+impl <'a> Hud<'a> {
+    pub fn new(stats: &'a HudStats, font: &'a Font, tileset: &'a Tileset) -> Self {
+        Hud {
+            area: Rectangle::new_sized(Vector::ZERO),
+            stats: stats,
+            font: font,
+            tileset: tileset,
+        }
+    }
+
+    pub fn with_area(&mut self, area: Rectangle) -> &mut Self {
+        self.area = area;
+        self
+    }
+
+    fn text(&self, window: &mut Window, text: &str, pos: Vector, color: Color) -> Result<()> {
+        Label::new(text, self.font, FontStyle::new(TEXT_SIZE, color))?
+            .with_pos(pos)
+            .draw(window)
+    }
+
+    /// A bar filled in proportion to value / max
+    fn bar(&self, window: &mut Window, area: Rectangle, value: i32, max: i32, color: Color) {
+        let fraction = if max > 0 { (value as f32 / max as f32).max(0.0).min(1.0) } else { 0.0 };
+        let empty = Color { r: color.r * 0.3, g: color.g * 0.3, b: color.b * 0.3, a: 1.0 };
+        window.draw(&area, Background::Col(empty));
+        window.draw(&Rectangle::new(area.pos, Vector::new(area.width() * fraction, area.height())), Background::Col(color));
+    }
+}
+
+impl <'a> Widget for Hud<'a> {
+    fn area(&self) -> Rectangle {
+        self.area.clone()
+    }
+
+    fn draw(&mut self, window: &mut Window) -> Result<()> {
+        let stats = self.stats;
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(MARGIN)
+            .constraints([
+                Constraint::Length(ROW_HEIGHT * 2),
+                Constraint::Length(ROW_HEIGHT),
+                Constraint::Length(ROW_HEIGHT),
+                Constraint::Length(ROW_HEIGHT),
+                Constraint::Length(ROW_HEIGHT),
+                Constraint::Min(0),
+            ].as_ref())
+            .split(LayoutRect::from(self.area.clone()).into());
+        let rows: Vec<Rectangle> = chunks.into_iter().map(|r| LayoutRect::from(r).into()).collect();
+
+        window.draw(&self.area, Background::Col(Color { r: 0.1, g: 0.1, b: 0.1, a: 1.0 }));
+
+        self.text(window, &format!("HP {}/{}", stats.hp, stats.max_hp), rows[0].pos, Color::WHITE)?;
+        let hp_bar = Rectangle::new(rows[0].pos + Vector::new(0.0, ROW_HEIGHT as f32), Vector::new(rows[0].width(), BAR_HEIGHT));
+        self.bar(window, hp_bar, stats.hp, stats.max_hp, Color::RED);
+
+        self.text(window, &format!("Thirst {:.0}%", stats.thirst * 100.0), rows[1].pos, Color::CYAN)?;
+        self.text(window, &format!("Turn {}", stats.turns), rows[2].pos, Color::WHITE)?;
+        self.text(window, &format!("Time {}", stats.time), rows[3].pos, Color::WHITE)?;
+        self.text(window, &format!("Depth {}", stats.depth), rows[4].pos, Color::WHITE)?;
+
+        // hostiles in view, as many as fit
+        let list = &rows[5];
+        let tile_size = self.tileset.tile_size();
+        let row_height = tile_size.y.max(ROW_HEIGHT as f32);
+        let fits = (list.height() / row_height) as usize;
+        for (i, hostile) in stats.hostiles.iter().take(fits).enumerate() {
+            let pos = list.pos + Vector::new(0.0, i as f32 * row_height);
+            self.tileset.draw(window, hostile.glyph, pos, hostile.color)?;
+
+            let name_pos = pos + Vector::new(tile_size.x + 4.0, 0.0);
+            self.text(window, &hostile.name, name_pos, Color::WHITE)?;
+
+            let bar_width = list.width() / 3.0;
+            let bar = Rectangle::new(
+                Vector::new(list.x() + list.width() - bar_width, pos.y + (row_height - BAR_HEIGHT) / 2.0),
+                Vector::new(bar_width, BAR_HEIGHT),
+            );
+            self.bar(window, bar, hostile.hp, hostile.max_hp, Color::RED);
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,15 @@
 use specs::{Entities, ReadStorage};
 use quicksilver::geom::{Rectangle, Vector};
 use quicksilver::lifecycle::Window;
+use quicksilver::graphics::{Background, Color};
 use quicksilver::Result;
 use crate::game::level::{Level, Tile};
 use crate::game::ecs::{Character, Position, Rect};
-use crate::game::fov::Fov;
+use crate::game::fov::{Fov, Remembered};
 use crate::color::{Palette};
 use super::Tileset;
 use super::Widget;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Visibility {
-    Visible,
-    Explored,
-}
-
 pub struct LevelView<'a> {
     pub area: Rectangle,
     pub level_offset: Position,
@@ -25,6 +20,8 @@ pub struct LevelView<'a> {
     pub positions: &'a ReadStorage<'a, Position>,
     pub tileset: &'a Tileset,
     pub palette: &'a Palette,
+    /// Cells to highlight, e.g. where a monster is heading
+    pub path: Vec<Position>,
 }
 
 impl <'a> LevelView<'a> {
@@ -39,6 +36,7 @@ impl <'a> LevelView<'a> {
             positions: positions,
             tileset: tileset,
             palette: palette,
+            path: Vec::new(),
         }
     }
 
@@ -52,27 +50,27 @@ impl <'a> LevelView<'a> {
         self
     }
 
-    fn draw_cell(&self, window: &mut Window, palette: &Palette, pos: Vector, level_cell: &Tile, visibility: Visibility) -> Result<()> {
-        if let Some(entity) = level_cell.entities.get(0) {
-            let color = if visibility == Visibility::Explored {
-                entity.color.darker()
-            } else {
-                entity.color
-            }.qs_color(palette);
+    pub fn with_path(&mut self, path: Vec<Position>) -> &mut Self {
+        self.path = path;
+        self
+    }
 
-            self.tileset.draw(window, entity.character, pos, color)?;
+    /// Cells in view show what is there now
+    fn draw_cell(&self, window: &mut Window, palette: &Palette, pos: Vector, level_cell: &Tile) -> Result<()> {
+        if let Some(entity) = level_cell.top_entity() {
+            self.tileset.draw(window, entity.character, pos, entity.color.qs_color(palette))?;
         } else {
-            let color = if visibility == Visibility::Explored {
-                level_cell.color.darker()
-            } else {
-                level_cell.color
-            }.qs_color(palette);
-
-            self.tileset.draw(window, level_cell.glyph, pos, color)?;
+            self.tileset.draw(window, level_cell.glyph, pos, level_cell.color.qs_color(palette))?;
         }
 
         Ok(())
     }
+
+    /// Out of view cells show what was there when the player last saw them
+    fn draw_remembered(&self, window: &mut Window, palette: &Palette, pos: Vector, memory: &Remembered) -> Result<()> {
+        let (glyph, color) = memory.top();
+        self.tileset.draw(window, glyph, pos, color.darker().qs_color(palette))
+    }
 }
 
 impl <'a> Widget for LevelView<'a> {
@@ -91,17 +89,26 @@ impl <'a> Widget for LevelView<'a> {
             for level_j in 0..level_area.height {
                 let level_x = level_area.x + level_i;
                 let level_y = level_area.y + level_j;
-                let draw_pos = self.area.top_left() + tile_size.times((level_i, level_j));
+                // relative to the camera, which sits left of or above the level to centre a small one
+                let draw_pos = self.area.top_left() + tile_size.times((level_x - self.level_offset.x, level_y - self.level_offset.y));
 
                 let c = self.level.get(level_x, level_y);
                 if self.fov.is_in_fov(level_x, level_y) {
-                    self.draw_cell(window, self.palette, draw_pos, &c, Visibility::Visible)?;
-                } else if self.fov.is_explored(level_x, level_y) {
-                    self.draw_cell(window, self.palette, draw_pos, &c, Visibility::Explored)?;
+                    self.draw_cell(window, self.palette, draw_pos, &c)?;
+                } else if let Some(memory) = self.fov.remembered(level_x, level_y) {
+                    self.draw_remembered(window, self.palette, draw_pos, memory)?;
                 }
             }
         }
 
+        let highlight = Color { r: 1.0, g: 1.0, b: 0.0, a: 0.3 };
+        for pos in self.path.iter() {
+            if level_area.contains(pos) {
+                let draw_pos = self.area.top_left() + tile_size.times((pos.x - self.level_offset.x, pos.y - self.level_offset.y));
+                window.draw(&Rectangle::new(draw_pos, tile_size), Background::Col(highlight));
+            }
+        }
+
         /*for (entity, pos) in (self.characters, self.positions).join() {
             let draw_x = pos.x - level_area.x as i32 + draw_area.x as i32;
             let draw_y = pos.y - level_area.y as i32 + draw_area.y as i32;
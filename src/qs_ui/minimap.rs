@@ -0,0 +1,248 @@
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::{Rectangle, Vector};
+use quicksilver::graphics::{Background, Color};
+use quicksilver::Result;
+use crate::game::ecs::{Position, Rect};
+use crate::game::fov::Fov;
+use crate::game::level::{Level, TileType};
+use super::Widget;
+
+/// Biggest block drawn per tile, in pixels, so small levels don't fill the screen
+pub const MAX_BLOCK: f32 = 4.0;
+/// Size of the corner overlay as a fraction of the map view
+pub const CORNER_FRACTION: f32 = 0.3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MinimapMode {
+    /// A small map in the top right corner of the map view
+    Corner,
+    /// The whole level over the map view
+    Full,
+}
+
+impl Default for MinimapMode {
+    fn default() -> Self {
+        MinimapMode::Corner
+    }
+}
+
+impl MinimapMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            MinimapMode::Corner => MinimapMode::Full,
+            MinimapMode::Full => MinimapMode::Corner,
+        }
+    }
+
+    /// Where the minimap goes within the map view
+    pub fn area(self, map_area: &Rectangle) -> Rectangle {
+        match self {
+            MinimapMode::Corner => {
+                let size = map_area.size() * CORNER_FRACTION;
+                Rectangle::new(map_area.pos + Vector::new(map_area.width() - size.x, 0.0), size)
+            },
+            MinimapMode::Full => map_area.clone(),
+        }
+    }
+}
+
+/// What a block of the minimap shows. Later marks win when a block covers several tiles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Mark {
+    Floor,
+    Liquid,
+    Wall,
+    Item,
+    Player,
+}
+
+impl Mark {
+    fn color(self) -> Color {
+        match self {
+            Mark::Floor => Color { r: 0.35, g: 0.35, b: 0.35, a: 1.0 },
+            Mark::Liquid => Color { r: 0.2, g: 0.3, b: 0.8, a: 1.0 },
+            Mark::Wall => Color { r: 0.75, g: 0.7, b: 0.6, a: 1.0 },
+            Mark::Item => Color::YELLOW,
+            Mark::Player => Color::WHITE,
+        }
+    }
+}
+
+/// How a level is shrunk to fit an area: tiles per block, blocks across and down, and the
+/// size of a block in pixels
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Scale {
+    step: i32,
+    blocks_x: i32,
+    blocks_y: i32,
+    block: f32,
+}
+
+impl Scale {
+    /// Fit a level into an area, or None if either is empty
+    fn fit(level_area: &Rect, size: Vector) -> Option<Scale> {
+        if level_area.width <= 0 || level_area.height <= 0 || size.x < 1.0 || size.y < 1.0 {
+            return None;
+        }
+
+        // tiles per block, so that the whole level fits
+        let step = (level_area.width as f32 / size.x)
+            .max(level_area.height as f32 / size.y)
+            .ceil()
+            .max(1.0) as i32;
+        let blocks_x = (level_area.width + step - 1) / step;
+        let blocks_y = (level_area.height + step - 1) / step;
+        let block = (size.x / blocks_x as f32)
+            .min(size.y / blocks_y as f32)
+            .min(MAX_BLOCK);
+
+        Some(Scale {
+            step: step,
+            blocks_x: blocks_x,
+            blocks_y: blocks_y,
+            block: block,
+        })
+    }
+}
+
+/// The explored part of a level at a few pixels per tile. Levels too big for the
+/// area get several tiles to a pixel block.
+pub struct Minimap<'a> {
+    pub area: Rectangle,
+    pub level: &'a Level,
+    pub fov: &'a Fov,
+    pub player: Position,
+}
+
+impl <'a> Minimap<'a> {
+    pub fn new(level: &'a Level, fov: &'a Fov, player: Position) -> Self {
+        Minimap {
+            area: Rectangle::new_sized(Vector::ZERO),
+            level: level,
+            fov: fov,
+            player: player,
+        }
+    }
+
+    pub fn with_area(&mut self, area: Rectangle) -> &mut Self {
+        self.area = area;
+        self
+    }
+
+    /// What the player knows is at a tile, if anything
+    fn mark(&self, x: i32, y: i32) -> Option<Mark> {
+        if self.player.x == x && self.player.y == y {
+            return Some(Mark::Player);
+        }
+
+        let memory = self.fov.remembered(x, y)?;
+        if memory.item.is_some() {
+            return Some(Mark::Item);
+        }
+
+        match self.level.get(x, y).cell_type {
+            TileType::Void => None,
+            TileType::Wall => Some(Mark::Wall),
+            TileType::ShallowWater | TileType::DeepWater | TileType::Lava => Some(Mark::Liquid),
+            _ => Some(Mark::Floor),
+        }
+    }
+
+    /// The mark that wins among the tiles a block covers
+    fn block_mark(&self, bx: i32, by: i32, step: i32) -> Option<Mark> {
+        let level_area = self.level.area();
+        let mut best = None;
+        for x in (level_area.x + bx * step)..(level_area.x + (bx + 1) * step).min(level_area.right()) {
+            for y in (level_area.y + by * step)..(level_area.y + (by + 1) * step).min(level_area.bottom()) {
+                best = best.max(self.mark(x, y));
+            }
+        }
+        best
+    }
+}
+
+impl <'a> Widget for Minimap<'a> {
+    fn area(&self) -> Rectangle {
+        self.area.clone()
+    }
+
+    fn draw(&mut self, window: &mut Window) -> Result<()> {
+        let Scale { step, blocks_x, blocks_y, block } = match Scale::fit(&self.level.area(), self.area.size()) {
+            Some(scale) => scale,
+            None => return Ok(()),
+        };
+        let size = Vector::new(blocks_x as f32 * block, blocks_y as f32 * block);
+        let origin = self.area.pos + (self.area.size() - size) * 0.5;
+
+        window.draw(&Rectangle::new(origin, size), Background::Col(Color { r: 0.0, g: 0.0, b: 0.0, a: 0.8 }));
+
+        for bx in 0..blocks_x {
+            for by in 0..blocks_y {
+                if let Some(mark) = self.block_mark(bx, by, step) {
+                    let pos = origin + Vector::new(bx as f32 * block, by as f32 * block);
+                    window.draw(&Rectangle::new(pos, Vector::new(block, block)), Background::Col(mark.color()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ecs::entities;
+    use crate::color::{Color as GameColor, Hue};
+    use crate::game::level::{self, Tile};
+
+    #[test]
+    fn the_full_map_gets_bigger_blocks_than_the_corner() {
+        let map_area = Rectangle::new_sized(Vector::new(1000, 600));
+        let corner = MinimapMode::Corner.area(&map_area).size();
+        let full = MinimapMode::Full.area(&map_area).size();
+
+        // a small level fits a tile to a block either way, capped at MAX_BLOCK
+        let small = Rect::new_sized(200, 120);
+        assert_eq!(Scale::fit(&small, corner), Some(Scale { step: 1, blocks_x: 200, blocks_y: 120, block: 1.5 }));
+        assert_eq!(Scale::fit(&small, full), Some(Scale { step: 1, blocks_x: 200, blocks_y: 120, block: MAX_BLOCK }));
+
+        // a big one needs two tiles to a block in the corner
+        let big = Rect::new_sized(400, 240);
+        assert_eq!(Scale::fit(&big, corner), Some(Scale { step: 2, blocks_x: 200, blocks_y: 120, block: 1.5 }));
+        assert_eq!(Scale::fit(&big, full), Some(Scale { step: 1, blocks_x: 400, blocks_y: 240, block: 2.5 }));
+
+        assert_eq!(Scale::fit(&Rect::new_sized(0, 10), full), None);
+        assert_eq!(Scale::fit(&big, Vector::ZERO), None);
+    }
+
+    #[test]
+    fn the_most_important_mark_wins_a_block() {
+        let mut level = Level::filled(Rect::new_sized(6, 2), &Tile::floor());
+        *level.get_mut(1, 1) = Tile::wall();
+
+        let (_world, ids) = entities(2);
+        let (player, potion) = (ids[0], ids[1]);
+        level.get_mut(2, 0).entities.push(level::Entity {
+            character: '!',
+            blocked: false,
+            id: potion,
+            color: GameColor::new(Hue::Red, 255),
+        });
+
+        let mut fov = Fov::new(&level);
+        fov.compute(&Position::new(0, 0), 3);
+        fov.remember(&level, player);
+
+        let minimap = Minimap::new(&level, &fov, Position::new(3, 0));
+        // floor and wall, then item and player, then unexplored
+        assert_eq!(minimap.block_mark(0, 0, 2), Some(Mark::Wall));
+        assert_eq!(minimap.block_mark(1, 0, 2), Some(Mark::Player));
+        assert_eq!(minimap.block_mark(2, 0, 2), None);
+
+        // a tile to a block shows each on its own
+        assert_eq!(minimap.block_mark(0, 0, 1), Some(Mark::Floor));
+        assert_eq!(minimap.block_mark(1, 1, 1), Some(Mark::Wall));
+        assert_eq!(minimap.block_mark(2, 0, 1), Some(Mark::Item));
+    }
+}
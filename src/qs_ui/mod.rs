@@ -4,10 +4,16 @@ pub mod tileset;
 pub mod layout;
 pub mod picture;
 pub mod level_view;
+pub mod hud;
+pub mod minimap;
+pub mod which_key;
 
 pub use widget::Widget;
 pub use label::Label;
 pub use tileset::Tileset;
 pub use layout::LayoutRect;
 pub use picture::Picture;
-pub use level_view::LevelView;
\ No newline at end of file
+pub use level_view::LevelView;
+pub use hud::{Hud, HudHostile, HudStats};
+pub use minimap::{Minimap, MinimapMode};
+pub use which_key::WhichKey;
\ No newline at end of file
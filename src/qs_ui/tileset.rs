@@ -43,14 +43,14 @@ impl Tileset {
         Ok(())
     }
 
-    pub fn load() -> Asset<Tileset> {
+    /// Load the font and render a tile for each of the glyphs
+    pub fn load(glyphs: String) -> Asset<Tileset> {
         // The Square font: http://strlen.com/square/?s[]=font
         // License: CC BY 3.0 https://creativecommons.org/licenses/by/3.0/deed.en_US
         let font_square = "square.ttf";
-        let game_glyphs = "#@oTg.%";
         let tile_size_px = Vector::new(24, 24);
         Asset::new(Font::load(font_square).and_then(move |text| {
-            Tileset::new(tile_size_px, &text, &game_glyphs)
+            Tileset::new(tile_size_px, &text, &glyphs)
         }))
     }
 }
@@ -0,0 +1,83 @@
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::{Rectangle, Shape, Vector};
+use quicksilver::graphics::{Background, Color, Font, FontStyle};
+use quicksilver::Result;
+use super::{Label, Widget};
+
+const TEXT_SIZE: f32 = 16.0;
+const ROW_HEIGHT: f32 = 22.0;
+const COLUMN_WIDTH: f32 = 220.0;
+const MARGIN: f32 = 8.0;
+
+/// Strip along the bottom of the screen listing the keys that can finish a partly typed chord
+pub struct WhichKey<'a> {
+    /// The screen; the overlay only covers as much of the bottom of it as it needs
+    pub area: Rectangle,
+    /// What's been typed so far, e.g. "C-x"
+    pub keys: String,
+    /// Each key that could come next and what it does
+    pub entries: Vec<(String, String)>,
+    pub font: &'a Font,
+}
+
+impl <'a> WhichKey<'a> {
+    pub fn new(keys: String, entries: Vec<(String, String)>, font: &'a Font) -> Self {
+        WhichKey {
+            area: Rectangle::new_sized(Vector::ZERO),
+            keys: keys,
+            entries: entries,
+            font: font,
+        }
+    }
+
+    pub fn with_area(&mut self, area: Rectangle) -> &mut Self {
+        self.area = area;
+        self
+    }
+
+    fn columns(&self) -> usize {
+        (((self.area.width() - MARGIN * 2.0) / COLUMN_WIDTH) as usize).max(1)
+    }
+
+    /// The part of the screen the overlay covers
+    fn panel(&self) -> Rectangle {
+        let columns = self.columns();
+        let rows = (self.entries.len() + columns - 1) / columns;
+        let height = (rows + 1) as f32 * ROW_HEIGHT + MARGIN * 2.0;
+        Rectangle::new(
+            Vector::new(self.area.x(), self.area.y() + self.area.height() - height),
+            Vector::new(self.area.width(), height),
+        )
+    }
+
+    fn text(&self, window: &mut Window, text: &str, pos: Vector, color: Color) -> Result<()> {
+        Label::new(text, self.font, FontStyle::new(TEXT_SIZE, color))?
+            .with_pos(pos)
+            .draw(window)
+    }
+}
+
+impl <'a> Widget for WhichKey<'a> {
+    fn area(&self) -> Rectangle {
+        self.panel()
+    }
+
+    fn draw(&mut self, window: &mut Window) -> Result<()> {
+        let panel = self.panel();
+        window.draw(&panel, Background::Col(Color { r: 0.1, g: 0.1, b: 0.1, a: 0.9 }));
+
+        let origin = panel.pos + Vector::new(MARGIN, MARGIN);
+        self.text(window, &format!("{} -", self.keys), origin, Color::YELLOW)?;
+
+        // fill each column before moving on to the next
+        let columns = self.columns();
+        let rows = ((self.entries.len() + columns - 1) / columns).max(1);
+        for (i, (key, name)) in self.entries.iter().enumerate() {
+            let pos = origin + Vector::new((i / rows) as f32 * COLUMN_WIDTH, (i % rows + 1) as f32 * ROW_HEIGHT);
+            self.text(window, key, pos, Color::CYAN)?;
+            self.text(window, name, pos + Vector::new(COLUMN_WIDTH / 3.0, 0.0), Color::WHITE)?;
+        }
+
+        Ok(())
+    }
+}
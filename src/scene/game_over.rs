@@ -0,0 +1,83 @@
+use quicksilver::graphics::{Color, FontStyle};
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::{Rectangle, Shape, Vector};
+use quicksilver::Result;
+use crate::commands::{Command, Mode};
+use crate::qs_ui::{Label, Widget};
+use crate::qs_game::Game;
+use super::{Scene, Transition};
+use super::menu::MenuScene;
+
+const TITLE_SIZE: f32 = 48.0;
+const TEXT_SIZE: f32 = 20.0;
+
+/// How the player's game went
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Morgue {
+    pub name: String,
+    /// Whoever dealt the killing blow, if anyone
+    pub killer: Option<String>,
+    pub turns: u32,
+    /// Game time, already formatted
+    pub time: String,
+    pub depth: u32,
+}
+
+impl Morgue {
+    pub fn lines(&self) -> Vec<String> {
+        let cause = match &self.killer {
+            Some(killer) => format!("{} was killed by {} on depth {}.", self.name, killer, self.depth),
+            None => format!("{} died on depth {}.", self.name, self.depth),
+        };
+        vec![
+            cause,
+            format!("They lasted {} turns (time {}).", self.turns, self.time),
+        ]
+    }
+}
+
+/// Shown when the player dies. Confirming goes back to the main menu.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GameOverScene {
+    pub morgue: Morgue,
+}
+
+impl GameOverScene {
+    pub fn new(morgue: Morgue) -> Self {
+        GameOverScene {
+            morgue: morgue,
+        }
+    }
+
+    pub fn command(&mut self, command: Command, _game: &mut Game) -> Result<Transition> {
+        match command {
+            Command::Confirm => Ok(Transition::Reset(Scene::Menu(MenuScene::default()))),
+            _ => Ok(Transition::None),
+        }
+    }
+
+    pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
+        window.clear(Color::BLACK)?;
+
+        let screen = Rectangle::new_sized(window.screen_size());
+        let lines = self.morgue.lines();
+        let hints = game.commands.hints(Mode::GameOver, &[(Command::Confirm, "return to the menu")]);
+
+        game.text.execute(|text| {
+            Label::new("You died", &text.font, FontStyle::new(TITLE_SIZE, Color::RED))?
+                .with_center(screen.center() - Vector::new(0.0, TITLE_SIZE * 2.0))
+                .draw(window)?;
+
+            for (i, line) in lines.iter().enumerate() {
+                Label::new(line.as_str(), &text.font, FontStyle::new(TEXT_SIZE, Color::WHITE))?
+                    .with_center(screen.center() + Vector::new(0.0, i as f32 * TEXT_SIZE * 1.5))
+                    .draw(window)?;
+            }
+
+            Label::new(hints.as_str(), &text.font, FontStyle::new(14.0, Color::WHITE))?
+                .with_center(screen.center() + Vector::new(0.0, screen.height() / 3.0))
+                .draw(window)?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use quicksilver::graphics::{Color, FontStyle};
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::Vector;
+use quicksilver::Result;
+use crate::chords;
+use crate::commands::{Command, Commands, Mode};
+use crate::qs_ui::{Label, Widget};
+use crate::qs_game::Game;
+use super::{modal_panel, Transition};
+
+const TEXT_SIZE: f32 = 18.0;
+const LINE_SPACING: f32 = 1.3;
+
+/// A list of the keys bound in a mode and what they do, drawn over the level
+#[derive(Debug, Clone, PartialEq)]
+pub struct HelpScene {
+    /// The mode help was asked for in
+    pub mode: Mode,
+}
+
+impl HelpScene {
+    pub fn new(mode: Mode) -> Self {
+        HelpScene {
+            mode: mode,
+        }
+    }
+
+    /// One line per command, with every chord bound to it
+    fn lines(&self, commands: &Commands) -> Vec<String> {
+        commands.bindings(self.mode)
+            .into_iter()
+            .map(|(command, keys)| {
+                let keys: Vec<String> = keys.iter().map(|keys| chords::chord_to_string(keys)).collect();
+                format!("{:<16} {}", keys.join(", "), command.description(self.mode))
+            })
+            .collect()
+    }
+
+    pub fn command(&mut self, command: Command, _game: &mut Game) -> Result<Transition> {
+        match command {
+            Command::Cancel => Ok(Transition::Pop),
+            _ => Ok(Transition::None),
+        }
+    }
+
+    pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
+        let lines = self.lines(&game.commands);
+        let height = (lines.len() + 3) as f32 * TEXT_SIZE * LINE_SPACING;
+        let area = modal_panel(window, Vector::new(720.0, height));
+
+        game.text.execute(|text| {
+            let title = format!("Help: {} keys", self.mode.name());
+            Label::new(title, &text.font, FontStyle::new(TEXT_SIZE, Color::YELLOW))?
+                .with_pos(area.pos)
+                .draw(window)?;
+            for (i, line) in lines.iter().enumerate() {
+                Label::new(line.as_str(), &text.font, FontStyle::new(TEXT_SIZE, Color::WHITE))?
+                    .with_pos(area.pos + Vector::new(0.0, (i + 2) as f32 * TEXT_SIZE * LINE_SPACING))
+                    .draw(window)?;
+            }
+            Ok(())
+        })
+    }
+}
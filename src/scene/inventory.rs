@@ -0,0 +1,108 @@
+use quicksilver::graphics::{Color, FontStyle};
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::Vector;
+use quicksilver::Result;
+use specs::{ReadExpect, ReadStorage, WriteExpect};
+use crate::commands::{Command, Mode};
+use crate::data::Data;
+use crate::game::ecs::{Inventory, Liquid};
+use crate::game::items::Item;
+use crate::game::system::{GameActionType, GameActor};
+use crate::qs_ui::{Label, Widget};
+use crate::qs_game::Game;
+use super::{modal_panel, Transition};
+
+const TEXT_SIZE: f32 = 20.0;
+const ROW_HEIGHT: f32 = 28.0;
+
+/// What the player is carrying, drawn over the level. Potions can be drunk from here.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InventoryScene {
+    pub selected: usize,
+}
+
+impl InventoryScene {
+    pub fn command(&mut self, command: Command, game: &mut Game) -> Result<Transition> {
+        let (mut data, inventories, liquids): (
+            WriteExpect<Data>,
+            ReadStorage<Inventory>,
+            ReadStorage<Liquid>,
+        ) = game.world.system_data();
+        let player = data.player;
+        let carried = inventories.get(player).map(|i| i.items.clone()).unwrap_or_default();
+
+        if command == Command::Cancel {
+            return Ok(Transition::Pop);
+        }
+
+        if carried.is_empty() {
+            return Ok(Transition::None);
+        }
+        self.selected = self.selected.min(carried.len() - 1);
+
+        match command {
+            Command::Move(_, dy) if dy < 0 => self.selected = (self.selected + carried.len() - 1) % carried.len(),
+            Command::Move(_, dy) if dy > 0 => self.selected = (self.selected + 1) % carried.len(),
+            Command::Confirm => {
+                let item = carried[self.selected];
+                if liquids.get(item).map(|l| l.potable).unwrap_or(false) {
+                    data.action(GameActor::Player(player), GameActionType::Drink(item));
+                    return Ok(Transition::Pop);
+                }
+            },
+            _ => {},
+        }
+
+        Ok(Transition::None)
+    }
+
+    pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
+        let lines: Vec<String> = {
+            let (data, inventories, items): (
+                ReadExpect<Data>,
+                ReadStorage<Inventory>,
+                ReadStorage<Item>,
+            ) = game.world.system_data();
+            inventories.get(data.player)
+                .map(|i| i.items.iter()
+                    .enumerate()
+                    .map(|(n, e)| {
+                        let name = items.get(*e).map(|item| match item.quantity {
+                            1 => item.name.clone(),
+                            quantity => format!("{} x{}", item.name, quantity),
+                        }).unwrap_or_default();
+                        let marker = if n == self.selected { ">" } else { " " };
+                        format!("{} {}) {}", marker, (b'a' + n as u8 % 26) as char, name)
+                    })
+                    .collect())
+                .unwrap_or_default()
+        };
+
+        let hints = game.commands.hints(Mode::Inventory, &[(Command::Confirm, "drink"), (Command::Cancel, "close")]);
+        let area = modal_panel(window, Vector::new(400.0, 360.0));
+
+        game.text.execute(|text| {
+            Label::new("Inventory", &text.font, FontStyle::new(TEXT_SIZE, Color::YELLOW))?
+                .with_pos(area.pos)
+                .draw(window)?;
+
+            if lines.is_empty() {
+                Label::new("You aren't carrying anything.", &text.font, FontStyle::new(TEXT_SIZE, Color::WHITE))?
+                    .with_pos(area.pos + Vector::new(0.0, ROW_HEIGHT * 1.5))
+                    .draw(window)?;
+            }
+
+            let fits = ((area.height() / ROW_HEIGHT) as usize).saturating_sub(3);
+            for (i, line) in lines.iter().take(fits).enumerate() {
+                Label::new(line.as_str(), &text.font, FontStyle::new(TEXT_SIZE, Color::WHITE))?
+                    .with_pos(area.pos + Vector::new(0.0, ROW_HEIGHT * (i as f32 + 1.5)))
+                    .draw(window)?;
+            }
+
+            Label::new(hints.as_str(), &text.font, FontStyle::new(14.0, Color::WHITE))?
+                .with_pos(area.pos + Vector::new(0.0, area.height() - ROW_HEIGHT))
+                .draw(window)?;
+            Ok(())
+        })
+    }
+}
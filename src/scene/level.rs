@@ -1,38 +1,253 @@
-use quicksilver::graphics::Color;
+use quicksilver::graphics::{Color, FontStyle};
 use quicksilver::lifecycle::Window;
-use quicksilver::geom::Rectangle;
+use quicksilver::geom::{Rectangle, Vector};
 use quicksilver::Result;
-use crate::data::Data;
-use crate::qs_ui::{LevelView, Widget};
-use specs::{Entities, ReadStorage, ReadExpect};
-use crate::game::ecs::{Position, Character};
+use tui::layout::{Constraint, Direction, Layout};
+use crate::ai::Ai;
+use crate::ai::companion::CompanionOrder;
+use crate::camera::Camera;
+use crate::commands::{Command, Mode};
+use crate::data::{Data, InputMode};
+use crate::qs_ui::{Hud, HudHostile, HudStats, Label, LayoutRect, LevelView, Minimap, MinimapMode, Widget};
+use crate::qs_ui::hud::HUD_WIDTH;
+use specs::{Entities, ReadStorage, ReadExpect, World, WorldExt, WriteExpect};
+use crate::game::deeds::DeedKind;
+use crate::game::ecs::{Attributes, Position, Character};
+use crate::game::items::Item;
+use crate::game::save::{self, SAVE_FILE};
+use crate::game::system::{GameActionType, GameActor};
 use crate::qs_game::Game;
+use super::{save_and_quit, Scene, Transition};
+use super::game_over::{GameOverScene, Morgue};
+use super::help::HelpScene;
+use super::inventory::InventoryScene;
+use super::menu::MenuScene;
 
+/// The game itself: the level, the side panel and the minimap
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct LevelScene;
 
-type SystemData<'a> = (ReadExpect<'a, Data>, Entities<'a>, ReadStorage<'a, Position>, ReadStorage<'a, Character>);
+type SystemData<'a> = (
+    ReadExpect<'a, Data>,
+    ReadExpect<'a, Ai>,
+    WriteExpect<'a, Camera>,
+    ReadExpect<'a, MinimapMode>,
+    Entities<'a>,
+    ReadStorage<'a, Position>,
+    ReadStorage<'a, Character>,
+    ReadStorage<'a, Attributes>,
+);
 
 impl LevelScene {
+    pub fn mode(&self, world: &World) -> Mode {
+        match world.read_resource::<Data>().input_mode {
+            InputMode::Look => Mode::Look,
+            InputMode::Targeting => Mode::Targeting,
+            _ => Mode::Play,
+        }
+    }
+
+    /// Turn commands into player actions
+    pub fn command(&mut self, command: Command, game: &mut Game) -> Result<Transition> {
+        if command == Command::Quit {
+            return Ok(save_and_quit(game));
+        }
+
+        let mode = self.mode(&game.world);
+        let (mut data, mut ai, mut minimap, items, positions): (
+            WriteExpect<Data>,
+            WriteExpect<Ai>,
+            WriteExpect<MinimapMode>,
+            ReadStorage<Item>,
+            ReadStorage<Position>,
+        ) = game.world.system_data();
+        let player = GameActor::Player(data.player);
+        let looking = data.input_mode == InputMode::Look;
+        let targeting = data.input_mode == InputMode::Targeting;
+
+        match command {
+            Command::Move(dx, dy) if looking => data.action(player, GameActionType::Look(dx, dy)),
+            Command::Move(dx, dy) if targeting => data.action(player, GameActionType::Target(dx, dy)),
+            Command::Move(dx, dy) => data.action(player, GameActionType::MoveAttack(dx, dy)),
+            Command::Look => data.action(player, GameActionType::Look(0, 0)),
+            Command::PickUp => {
+                // whatever is lying under the player
+                let here = positions.get(player.entity()).cloned().unwrap_or_default();
+                let item = data.level.get(here.x, here.y).entities.iter()
+                    .find(|e| !e.blocked && items.get(e.id).is_some())
+                    .map(|e| e.id);
+                if let Some(item) = item {
+                    data.action(player, GameActionType::PickUp(item));
+                }
+            },
+            Command::Follow => data.action(player, GameActionType::Order(CompanionOrder::Follow)),
+            Command::Stay => data.action(player, GameActionType::Order(CompanionOrder::Stay)),
+            Command::Attack if !targeting => data.action(player, GameActionType::Target(0, 0)),
+            Command::Attack | Command::Confirm if targeting => {
+                // whatever is under the cursor
+                let target = data.cursor
+                    .as_ref()
+                    .and_then(|c| data.level.get(c.x, c.y).entities.iter().find(|e| e.blocked && e.id != player.entity()).map(|e| e.id));
+                if let Some(target) = target {
+                    data.action(player, GameActionType::Play);
+                    data.action(player, GameActionType::Order(CompanionOrder::Attack(target)));
+                }
+            },
+            Command::Minimap => *minimap = minimap.toggle(),
+            Command::DebugAi => ai.toggle_debug(),
+            Command::Cancel if looking || targeting => data.action(player, GameActionType::Play),
+            Command::Cancel => return Ok(Transition::Push(Scene::Menu(MenuScene::paused()))),
+            Command::Inventory => return Ok(Transition::Push(Scene::Inventory(InventoryScene::default()))),
+            Command::Help => return Ok(Transition::Push(Scene::Help(HelpScene::new(mode)))),
+            Command::Attack | Command::Confirm | Command::Quit => {},
+        }
+
+        Ok(Transition::None)
+    }
+
+    /// Notice when the game is over
+    pub fn update(&mut self, game: &mut Game) -> Result<Transition> {
+        let (data, attributes): (ReadExpect<Data>, ReadStorage<Attributes>) = game.world.system_data();
+
+        let alive = attributes.get(data.player).map(|a| a.alive).unwrap_or(false);
+        if !alive {
+            // the game is over, so there's nothing left to load
+            save::delete(SAVE_FILE);
+            return Ok(Transition::Replace(Scene::GameOver(GameOverScene::new(morgue(&data, &attributes)))));
+        }
+        if data.stop {
+            return Ok(Transition::Quit);
+        }
+
+        Ok(Transition::None)
+    }
+
     pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
         //use specs::Join;
 
         window.clear(Color::BLACK)?;
 
-        let (data, entities, positions, characters): SystemData = game.world.system_data();
+        let (data, ai, mut camera, minimap, entities, positions, characters, attributes): SystemData = game.world.system_data();
 
         let level = &data.level;
         let fov = &data.fov;
-        let tileset = &mut game.tileset;
         let palette = &data.palette;
+        let (tileset, text) = (&mut game.tileset, &mut game.text);
+
+        // the map on the left, the side panel on the right
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(HUD_WIDTH),
+            ].as_ref())
+            .split(LayoutRect::from(Rectangle::new_sized(window.screen_size())).into());
+        let map_area: Rectangle = LayoutRect::from(chunks[0]).into();
+        let hud_area: Rectangle = LayoutRect::from(chunks[1]).into();
+
+        // With AI debugging on, show what the monster under the look cursor is thinking
+        let debug = if ai.debug {
+            data.cursor.as_ref()
+                .and_then(|cursor| level.get(cursor.x, cursor.y).top_entity().map(|e| e.id))
+                .and_then(|id| ai.debug_for(id))
+        } else {
+            None
+        };
+        let path = debug.map(|record| record.path_positions()).unwrap_or_default();
+
+        // keep the look cursor, or failing that the player, in view
+        let focus = data.cursor.clone().or_else(|| positions.get(data.player).cloned()).unwrap_or_default();
+
+        let player_pos = positions.get(data.player).cloned().unwrap_or_default();
+        let stats = hud_stats(&data, &entities, &positions, &characters, &attributes);
 
         tileset.execute(|tileset| {
-            LevelView::new(level, &tileset, &palette, &fov, &entities, &characters, &positions)
-                .with_area(Rectangle::new_sized(window.screen_size()))
+            let tiles = map_area.size().times(tileset.tile_size().recip());
+            camera.resize(tiles.x as i32, tiles.y as i32);
+            camera.follow(&focus, &level.area());
+
+            // the full-screen map covers the level view
+            if *minimap == MinimapMode::Corner {
+                LevelView::new(level, &tileset, &palette, &fov, &entities, &characters, &positions)
+                    .with_area(map_area)
+                    .with_level_offset(camera.offset())
+                    .with_path(path.clone())
+                    .draw(window)?;
+            }
+
+            Minimap::new(level, &fov, player_pos.clone())
+                .with_area(minimap.area(&map_area))
                 .draw(window)?;
-            Ok(())
+
+            text.execute(|text| {
+                Hud::new(&stats, &text.font, &tileset)
+                    .with_area(hud_area)
+                    .draw(window)
+            })
         })?;
-        
+
+        if let Some(record) = debug {
+            let lines = record.lines().join("\n");
+            game.text.execute(|text| {
+                Label::new(lines.as_str(), &text.font, FontStyle::new(14.0, Color::WHITE))?
+                    .with_pos(Vector::new(8, 8))
+                    .draw(window)?;
+                Ok(())
+            })?;
+        }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// How the player's game went, for the morgue screen
+fn morgue<'a>(data: &Data, attributes: &ReadStorage<'a, Attributes>) -> Morgue {
+    let player = data.player;
+    let killer = data.deeds.recent.iter()
+        .rev()
+        .find(|deed| deed.kind == DeedKind::Kill && deed.victim == player)
+        .map(|deed| attributes.get(deed.actor).map(|a| a.name.clone()).unwrap_or_else(|| "something".to_string()));
+
+    Morgue {
+        name: attributes.get(player).map(|a| a.name.clone()).unwrap_or_default(),
+        killer: killer,
+        turns: data.turns(),
+        time: format!("{:.1}", data.time),
+        depth: data.depth,
+    }
+}
+
+/// What the side panel needs to know about the player and the hostiles they can see
+fn hud_stats<'a>(
+    data: &Data,
+    entities: &Entities<'a>,
+    positions: &ReadStorage<'a, Position>,
+    characters: &ReadStorage<'a, Character>,
+    attributes: &ReadStorage<'a, Attributes>,
+) -> HudStats {
+    let player = attributes.get(data.player).cloned().unwrap_or_default();
+
+    let hostiles = data.fov.hostiles_in_view(data.player, &data.factions, entities, positions, attributes)
+        .into_iter()
+        .filter_map(|e| {
+            let (character, attr) = (characters.get(e)?, attributes.get(e)?);
+            Some(HudHostile {
+                glyph: character.glyph,
+                color: character.color.qs_color(&data.palette),
+                name: attr.name.clone(),
+                hp: attr.hp,
+                max_hp: attr.max_hp,
+            })
+        })
+        .collect();
+
+    HudStats {
+        hp: player.hp,
+        max_hp: player.max_hp,
+        thirst: player.thirst,
+        turns: data.turns(),
+        time: format!("{:.1}", data.time),
+        depth: data.depth,
+        hostiles: hostiles,
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+use quicksilver::graphics::{Color, FontStyle};
+use quicksilver::lifecycle::Window;
+use quicksilver::geom::{Rectangle, Shape, Vector};
+use quicksilver::Result;
+use tui::layout::{Constraint, Direction, Layout};
+use crate::commands::Command;
+use crate::game::save::SAVE_FILE;
+use crate::qs_ui::{Label, LayoutRect, Widget};
+use crate::qs_game::Game;
+use super::{save_and_quit, Scene, Transition};
+use super::level::LevelScene;
+
+const TITLE_SIZE: f32 = 48.0;
+const ENTRY_SIZE: f32 = 24.0;
+const ENTRY_HEIGHT: f32 = 36.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MenuEntry {
+    /// Back to the game the menu was opened from
+    Continue,
+    New,
+    /// Only offered when there's a saved game
+    Load,
+    Quit,
+    /// Quit from a game in progress, which is saved to be loaded later
+    SaveAndQuit,
+}
+
+impl MenuEntry {
+    fn label(self) -> &'static str {
+        match self {
+            MenuEntry::Continue => "Continue",
+            MenuEntry::New => "New game",
+            MenuEntry::Load => "Load game",
+            MenuEntry::Quit => "Quit",
+            MenuEntry::SaveAndQuit => "Save and quit",
+        }
+    }
+}
+
+/// The title screen, also shown over the game when the player presses escape
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuScene {
+    pub entries: Vec<MenuEntry>,
+    pub selected: usize,
+}
+
+impl Default for MenuScene {
+    fn default() -> Self {
+        MenuScene::new(vec![MenuEntry::New, MenuEntry::Load, MenuEntry::Quit])
+    }
+}
+
+impl MenuScene {
+    /// The menu opened from a game in progress, which can be gone back to
+    pub fn paused() -> Self {
+        MenuScene::new(vec![MenuEntry::Continue, MenuEntry::New, MenuEntry::Load, MenuEntry::SaveAndQuit])
+    }
+
+    /// A menu with the given entries, leaving out loading when there's no saved game
+    fn new(entries: Vec<MenuEntry>) -> Self {
+        let saved = Path::new(SAVE_FILE).exists();
+        MenuScene {
+            entries: entries.into_iter().filter(|&entry| entry != MenuEntry::Load || saved).collect(),
+            selected: 0,
+        }
+    }
+
+    /// Move the selection by `delta`, wrapping around at either end
+    fn select(&mut self, delta: i32) {
+        let count = self.entries.len() as i32;
+        self.selected = (self.selected as i32 + delta).rem_euclid(count) as usize;
+    }
+
+    pub fn command(&mut self, command: Command, game: &mut Game) -> Result<Transition> {
+        let transition = match command {
+            Command::Move(_, dy) if dy != 0 => {
+                self.select(dy.signum());
+                Transition::None
+            },
+            Command::Cancel if self.entries.contains(&MenuEntry::Continue) => Transition::Pop,
+            Command::Confirm => match self.entries[self.selected] {
+                MenuEntry::Continue => Transition::Pop,
+                MenuEntry::New => {
+                    game.new_game();
+                    Transition::Reset(Scene::Level(LevelScene::default()))
+                },
+                MenuEntry::Load => match game.load_game() {
+                    Ok(()) => Transition::Reset(Scene::Level(LevelScene::default())),
+                    Err(e) => {
+                        warn!("could not load the saved game: {}", e);
+                        self.entries.retain(|&entry| entry != MenuEntry::Load);
+                        self.selected = 0;
+                        Transition::None
+                    },
+                },
+                MenuEntry::Quit => Transition::Quit,
+                MenuEntry::SaveAndQuit => save_and_quit(game),
+            },
+            _ => Transition::None,
+        };
+
+        Ok(transition)
+    }
+
+    pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
+        window.clear(Color::BLACK)?;
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(50)
+            .constraints([
+                Constraint::Length(100),
+                Constraint::Min(0),
+                Constraint::Length(60),
+            ].as_ref())
+            .split(LayoutRect::from(Rectangle::new_sized(window.screen_size())).into());
+        let title_area: Rectangle = LayoutRect::from(chunks[0]).into();
+        let entries_area: Rectangle = LayoutRect::from(chunks[1]).into();
+        let credits_area: Rectangle = LayoutRect::from(chunks[2]).into();
+
+        game.text.execute(|text| {
+            Label::new(text.title.as_str(), &text.font, FontStyle::new(TITLE_SIZE, Color::WHITE))?
+                .with_center(title_area.center())
+                .draw(window)?;
+
+            for (i, entry) in self.entries.iter().enumerate() {
+                let color = if i == self.selected {
+                    Color::YELLOW
+                } else {
+                    Color::WHITE
+                };
+                let marker = if i == self.selected { "> " } else { "  " };
+                let center = Vector::new(entries_area.center().x, entries_area.y() + (i as f32 + 0.5) * ENTRY_HEIGHT);
+                Label::new(format!("{}{}", marker, entry.label()), &text.font, FontStyle::new(ENTRY_SIZE, color))?
+                    .with_center(center)
+                    .draw(window)?;
+            }
+
+            Label::new(text.mononoki_info.as_str(), &text.font, FontStyle::new(14.0, Color::WHITE))?
+                .with_pos(credits_area.pos)
+                .draw(window)?;
+            Ok(())
+        })
+    }
+}
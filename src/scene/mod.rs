@@ -1,27 +1,285 @@
 use quicksilver::lifecycle::{Window};
+use quicksilver::geom::{Rectangle, Vector};
+use quicksilver::graphics::{Background, Color};
 use quicksilver::Result;
+use specs::World;
+use crate::commands::{Command, Mode};
 use crate::qs_game::Game;
 
 pub mod level;
+pub mod menu;
+pub mod inventory;
+pub mod help;
+pub mod game_over;
 
+use level::LevelScene;
+use menu::MenuScene;
+use inventory::InventoryScene;
+use help::HelpScene;
+use game_over::GameOverScene;
+
+/// Space between the edge of a modal scene's backdrop and its contents
+const MODAL_MARGIN: f32 = 16.0;
+
+/// A screen with its own input handling and drawing
+#[derive(Debug, Clone, PartialEq)]
 pub enum Scene {
-    Main,
+    Menu(MenuScene),
+    Level(LevelScene),
+    Inventory(InventoryScene),
+    Help(HelpScene),
+    GameOver(GameOverScene),
+}
+
+/// What a scene wants done with the stack after handling input
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transition {
+    None,
+    Push(Scene),
+    Pop,
+    /// Swap the top scene for another one
+    Replace(Scene),
+    /// Throw the whole stack away and start again from this scene
+    Reset(Scene),
+    Quit,
 }
 
 impl Default for Scene {
     fn default() -> Self {
-        Scene::Main
+        Scene::Menu(MenuScene::default())
     }
 }
 
 impl Scene {
+    /// The key bindings the scene wants
+    pub fn mode(&self, world: &World) -> Mode {
+        match self {
+            Scene::Menu(_) => Mode::Menu,
+            Scene::Level(scene) => scene.mode(world),
+            Scene::Inventory(_) => Mode::Inventory,
+            Scene::Help(_) => Mode::Help,
+            Scene::GameOver(_) => Mode::GameOver,
+        }
+    }
+
+    pub fn command(&mut self, command: Command, game: &mut Game) -> Result<Transition> {
+        match self {
+            Scene::Menu(scene) => scene.command(command, game),
+            Scene::Level(scene) => scene.command(command, game),
+            Scene::Inventory(scene) => scene.command(command, game),
+            Scene::Help(scene) => scene.command(command, game),
+            Scene::GameOver(scene) => scene.command(command, game),
+        }
+    }
+
+    /// Called once a frame after the commands have been handled
+    pub fn update(&mut self, game: &mut Game) -> Result<Transition> {
+        match self {
+            Scene::Level(scene) => scene.update(game),
+            _ => Ok(Transition::None),
+        }
+    }
+
+    /// Handle the commands, stopping at the first that changes scene: the rest were
+    /// bound for this one
+    fn run(&mut self, commands: Vec<Command>, game: &mut Game) -> Result<Transition> {
+        for command in commands {
+            let transition = self.command(command, game)?;
+            if transition != Transition::None {
+                return Ok(transition);
+            }
+        }
+
+        self.update(game)
+    }
+
     pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
         match self {
-            Scene::Main => {
-                level::LevelScene.draw(window, game)?;
+            Scene::Menu(scene) => scene.draw(window, game),
+            Scene::Level(scene) => scene.draw(window, game),
+            Scene::Inventory(scene) => scene.draw(window, game),
+            Scene::Help(scene) => scene.draw(window, game),
+            Scene::GameOver(scene) => scene.draw(window, game),
+        }
+    }
+
+    /// Modal scenes are drawn over whatever is below them instead of clearing the screen
+    pub fn is_modal(&self) -> bool {
+        match self {
+            Scene::Inventory(_) | Scene::Help(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The scenes on screen. Only the top one gets input.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SceneStack {
+    scenes: Vec<Scene>,
+}
+
+impl SceneStack {
+    pub fn new(scene: Scene) -> Self {
+        SceneStack {
+            scenes: vec![scene],
+        }
+    }
+
+    pub fn push(&mut self, scene: Scene) {
+        self.scenes.push(scene);
+    }
+
+    pub fn pop(&mut self) -> Option<Scene> {
+        self.scenes.pop()
+    }
+
+    pub fn top(&self) -> Option<&Scene> {
+        self.scenes.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    /// True if a game is in progress somewhere in the stack
+    pub fn is_playing(&self) -> bool {
+        self.scenes.iter().any(|scene| if let Scene::Level(_) = scene { true } else { false })
+    }
+
+    pub fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::None => {},
+            Transition::Push(scene) => self.push(scene),
+            Transition::Pop => {
+                self.pop();
+            },
+            Transition::Replace(scene) => {
+                self.pop();
+                self.push(scene);
             },
+            Transition::Reset(scene) => {
+                self.scenes.clear();
+                self.push(scene);
+            },
+            Transition::Quit => self.scenes.clear(),
+        }
+    }
+
+    /// The key bindings for the top scene
+    pub fn mode(&self, world: &World) -> Mode {
+        self.top().map(|scene| scene.mode(world)).unwrap_or(Mode::Menu)
+    }
+
+    /// Let the top scene handle the commands, then apply whatever transition it asks for
+    pub fn update(&mut self, commands: Vec<Command>, game: &mut Game) -> Result<()> {
+        if let Some(mut scene) = self.pop() {
+            let result = scene.run(commands, game);
+            self.push(scene);
+            self.apply(result?);
+        }
+
+        Ok(())
+    }
+
+    /// The top scene, and the scenes below it as far down as the first one that isn't modal
+    fn visible(&self) -> &[Scene] {
+        let bottom = self.scenes.iter().rposition(|scene| !scene.is_modal()).unwrap_or(0);
+        &self.scenes[bottom..]
+    }
+
+    /// Draw the visible scenes, bottom first
+    pub fn draw(&self, window: &mut Window, game: &mut Game) -> Result<()> {
+        for scene in self.visible() {
+            scene.draw(window, game)?;
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Save the game and quit, or stay put if it can't be saved
+pub fn save_and_quit(game: &Game) -> Transition {
+    match game.save_game() {
+        Ok(()) => Transition::Quit,
+        Err(e) => {
+            error!("could not save the game: {}", e);
+            Transition::None
+        }
+    }
+}
+
+/// Draw the backdrop of a modal scene in the middle of the screen and return the area inside it
+pub fn modal_panel(window: &mut Window, size: Vector) -> Rectangle {
+    let screen = window.screen_size();
+    let size = Vector::new(size.x.min(screen.x), size.y.min(screen.y));
+    let area = Rectangle::new((screen - size) * 0.5, size);
+
+    window.draw(&area, Background::Col(Color { r: 0.1, g: 0.1, b: 0.1, a: 0.95 }));
+    let margin = Vector::new(MODAL_MARGIN, MODAL_MARGIN);
+    Rectangle::new(area.pos + margin, area.size() - margin * 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::game_over::Morgue;
+
+    fn level() -> Scene {
+        Scene::Level(LevelScene::default())
+    }
+
+    fn help() -> Scene {
+        Scene::Help(HelpScene::new(Mode::Play))
+    }
+
+    fn inventory() -> Scene {
+        Scene::Inventory(InventoryScene::default())
+    }
+
+    #[test]
+    fn transitions_change_the_stack() {
+        let mut stack = SceneStack::new(Scene::default());
+
+        stack.apply(Transition::Push(level()));
+        assert_eq!(stack.top(), Some(&level()));
+        assert!(stack.is_playing());
+
+        stack.apply(Transition::Push(help()));
+        stack.apply(Transition::Pop);
+        assert_eq!(stack.top(), Some(&level()));
+
+        let game_over = Scene::GameOver(GameOverScene::new(Morgue::default()));
+        stack.apply(Transition::Replace(game_over.clone()));
+        assert_eq!(stack.scenes, vec![Scene::default(), game_over]);
+        assert!(!stack.is_playing());
+
+        stack.apply(Transition::Reset(level()));
+        assert_eq!(stack.scenes, vec![level()]);
+
+        stack.apply(Transition::None);
+        assert_eq!(stack.scenes, vec![level()]);
+
+        stack.apply(Transition::Quit);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn modal_scenes_are_drawn_over_the_scene_below() {
+        let mut stack = SceneStack::new(Scene::default());
+        stack.push(level());
+        assert_eq!(stack.visible(), &[level()][..]);
+
+        stack.push(inventory());
+        stack.push(help());
+        assert_eq!(stack.visible(), &[level(), inventory(), help()][..]);
+
+        // the menu isn't modal, so it hides everything under it
+        stack.push(Scene::default());
+        assert_eq!(stack.visible(), &[Scene::default()][..]);
+
+        // with nothing but modal scenes, all of them show
+        let mut stack = SceneStack::new(help());
+        stack.push(inventory());
+        assert_eq!(stack.visible(), &[help(), inventory()][..]);
+    }
+}